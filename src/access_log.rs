@@ -0,0 +1,102 @@
+//! `--log-format json`, writing every log line as a JSON object and one access record per
+//! request, which log pipelines can ingest without parsing the human readable messages.
+use std::io::Write;
+use std::time::Instant;
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, web, HttpMessage};
+use actix_web::middleware::Next;
+use clap::ValueEnum;
+use env_logger::Env;
+use log::info;
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use crate::client_ip::ClientIp;
+use crate::request_id::RequestId;
+
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+/// The hash of the template context a response was rendered from, attached to the
+/// response extensions by the handlers rendering bars.
+pub struct ContextHash(pub String);
+
+impl ContextHash {
+    pub fn of(ctx: &minijinja::value::Value) -> Self {
+        let hash = Sha256::digest(serde_json::to_vec(ctx).unwrap_or_default());
+        ContextHash(hash.iter().take(16).map(|x| format!("{x:02x}")).collect())
+    }
+}
+
+#[derive(Serialize)]
+struct AccessRecord<'a> {
+    timestamp: String,
+    request_id: Option<String>,
+    client_ip: Option<String>,
+    method: &'a str,
+    path: &'a str,
+    query: &'a str,
+    route: Option<&'a str>,
+    status: u16,
+    context_hash: Option<String>,
+    duration_ms: f64,
+}
+
+pub fn init_logger(format: LogFormat) {
+    let mut builder = env_logger::Builder::from_env(Env::default()
+        .default_filter_or(concat!(env!("CARGO_CRATE_NAME"), "=info")));
+    if format == LogFormat::Json {
+        builder.format(|buf, record| {
+            // access records are JSON already.
+            if record.target() == module_path!() {
+                return writeln!(buf, "{}", record.args());
+            }
+            writeln!(buf, "{}", json!({
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+                "level": record.level().as_str(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            }))
+        });
+    }
+    builder.init();
+}
+
+/// Middleware logging an [`AccessRecord`] per request when the [`LogFormat`] found in the
+/// app data is JSON.
+pub async fn record(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if req.app_data::<web::Data<LogFormat>>().is_none_or(|x| *x.get_ref() != LogFormat::Json) {
+        return next.call(req).await;
+    }
+    let started = Instant::now();
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let request_id = req.extensions().get::<RequestId>().map(|x| x.0.clone());
+    let client_ip = req.extensions().get::<ClientIp>().map(|x| x.0.to_string());
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let query = req.query_string().to_string();
+    let route = req.match_name().map(str::to_string);
+
+    let response = next.call(req).await?;
+    let record = AccessRecord {
+        timestamp,
+        request_id,
+        client_ip,
+        method: &method,
+        path: &path,
+        query: &query,
+        route: route.as_deref(),
+        status: response.status().as_u16(),
+        context_hash: response.response().extensions().get::<ContextHash>().map(|x| x.0.clone()),
+        duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+    };
+    info!("{}", serde_json::to_string(&record).unwrap_or_default());
+    Ok(response)
+}
@@ -0,0 +1,19 @@
+//! Bearer token checks for the routes which modify state.
+use actix_web::{http::header, HttpRequest};
+
+
+/// Whether `req` carries `Authorization: Bearer <token>`, compared in constant time.
+pub fn has_bearer(req: &HttpRequest, token: &str) -> bool {
+    let Some(given) = req.headers().get(header::AUTHORIZATION)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+    constant_time_eq(given.trim().as_bytes(), token.as_bytes())
+}
+
+/// Whether `a` equals `b`, taking the same time wherever they differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
@@ -0,0 +1,79 @@
+//! Named badges defined in a YAML file, served at `/b/{name}.svg`, so that long parameter
+//! lists live in version controlled config instead of URLs, e.g.
+//!
+//! ```yaml
+//! coverage:
+//!   title: coverage
+//!   source: https://ci.example.com/coverage.json
+//!   value_path: $.total.percent
+//!   progress_color: "#4c1"
+//! ```
+//!
+//! Every badge holds the same fields as the query of `/render`.
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::{bail, Context};
+use progress_bar::BarSpec;
+use crate::compose;
+
+
+#[derive(Default)]
+pub struct Badges(HashMap<String, BarSpec>);
+
+impl Badges {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read badges file {}", path.display()))?;
+        let badges: HashMap<String, BarSpec> = serde_yaml::from_str(&text)
+            .with_context(|| format!("failed to parse badges file {}", path.display()))?;
+        for (name, spec) in &badges {
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+                bail!("badge name `{name}` may only hold letters, digits, `_` and `-`");
+            }
+            compose::validate(spec).with_context(|| format!("invalid badge `{name}`"))?;
+            if let Some((field, e)) = spec.invalid_fields().into_iter().next() {
+                bail!("invalid `{field}` of badge `{name}`: {e}");
+            }
+        }
+        Ok(Badges(badges))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BarSpec> {
+        self.0.get(name)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, yaml: &str) -> anyhow::Result<Badges> {
+        let path = std::env::temp_dir().join(format!("pbar-badges-{name}-{}.yaml", std::process::id()));
+        std::fs::write(&path, yaml).unwrap();
+        let badges = Badges::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        badges
+    }
+
+    #[test]
+    fn badges_are_validated_when_loaded() {
+        let badges = load("valid", "coverage:\n  title: coverage\n  progress: 73\n  progress_color: \"#4c1\"\n").unwrap();
+        assert_eq!(badges.len(), 1);
+        assert_eq!(badges.get("coverage").unwrap().progress, Some(73.0));
+        assert!(badges.get("missing").is_none());
+
+        for (name, yaml, error) in [
+            ("color", "coverage:\n  progress: 73\n  progress_color: oops\n", "invalid `progress_color` of badge `coverage`"),
+            ("name", "\"cov/erage\":\n  progress: 73\n", "badge name `cov/erage`"),
+            ("type", "coverage:\n  progress: lots\n", "failed to parse badges file"),
+        ] {
+            let e = load(name, yaml).err().unwrap();
+            assert!(format!("{e:#}").contains(error), "{name}: {e:#}");
+        }
+    }
+}
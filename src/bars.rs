@@ -0,0 +1,293 @@
+//! Bars stored on the server, updated with `PUT /bars/{id}`, the history of their values and
+//! their immutable snapshots.
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use actix_web::{rt, web};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast;
+use progress_bar::BarSpec;
+
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StoredBar {
+    pub spec: BarSpec,
+    pub updated: DateTime<Utc>,
+}
+
+/// A value a bar was set to.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryPoint {
+    pub at: DateTime<Utc>,
+    pub value: f32,
+}
+
+/// A frozen copy of a bar. Time based progress is evaluated at `created`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub bar: String,
+    pub spec: BarSpec,
+    pub created: DateTime<Utc>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct Contents {
+    bars: HashMap<String, StoredBar>,
+    snapshots: HashMap<String, Snapshot>,
+    /// The latest values of the bars, oldest first.
+    #[serde(default)]
+    history: HashMap<String, VecDeque<HistoryPoint>>,
+    /// Counts the changes, telling whether the file is up to date.
+    #[serde(skip)]
+    version: u64,
+}
+
+impl Contents {
+    /// Appends the value of bar `id`, if it has one, dropping the oldest beyond `limit`.
+    fn record(&mut self, id: &str, limit: usize) {
+        let Some(bar) = self.bars.get(id) else { return };
+        let Some(value) = bar.spec.value.or(bar.spec.progress).filter(|_| limit > 0) else { return };
+        let points = self.history.entry(id.to_string()).or_default();
+        points.push_back(HistoryPoint { at: bar.updated, value });
+        while points.len() > limit {
+            points.pop_front();
+        }
+    }
+}
+
+pub struct BarStore {
+    /// Where the bars are persisted, kept in memory only without.
+    path: Option<PathBuf>,
+    contents: RwLock<Contents>,
+    /// Points of history kept per bar.
+    history_limit: usize,
+    snapshot_counter: AtomicU64,
+    /// Ids of bars which were updated or deleted.
+    changes: broadcast::Sender<String>,
+    /// The version of the contents last written to `path`.
+    saved: Mutex<u64>,
+}
+
+/// How often values set by [`BarStore::update_value`] are written.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+impl BarStore {
+    /// Opens the bars persisted at `path`, keeping the last `history_limit` values of each.
+    pub fn open(path: Option<PathBuf>, history_limit: usize) -> anyhow::Result<Self> {
+        let contents = match &path {
+            Some(path) if path.exists() => {
+                let text = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read bars from {}", path.display()))?;
+                let contents: Contents = serde_json::from_str(&text)
+                    .with_context(|| format!("failed to parse bars in {}", path.display()))?;
+                info!("Loaded {} bar(s) and {} snapshot(s) from {}.",
+                    contents.bars.len(), contents.snapshots.len(), path.display());
+                contents
+            },
+            _ => Contents::default(),
+        };
+        Ok(BarStore {
+            path,
+            contents: RwLock::new(contents),
+            history_limit,
+            snapshot_counter: AtomicU64::new(0),
+            changes: broadcast::channel(64).0,
+            saved: Mutex::new(0),
+        })
+    }
+
+    /// Writes `contents`, after counting a change.
+    fn save(&self, contents: &mut Contents) -> anyhow::Result<()> {
+        contents.version += 1;
+        if self.path.is_none() {
+            return Ok(());
+        }
+        self.write(contents.version, serde_json::to_vec(&*contents)?)
+    }
+
+    /// Writes the serialized `bytes` of the contents at `version`, unless newer ones were
+    /// written meanwhile.
+    fn write(&self, version: u64, bytes: Vec<u8>) -> anyhow::Result<()> {
+        let Some(path) = &self.path else { return Ok(()) };
+        let mut saved = self.saved.lock().unwrap();
+        if version <= *saved {
+            return Ok(());
+        }
+        // write a sibling first, so a crash never leaves a truncated file behind.
+        let temp = path.with_extension("tmp");
+        fs::write(&temp, bytes)
+            .with_context(|| format!("failed to write bars to {}", temp.display()))?;
+        fs::rename(&temp, path)
+            .with_context(|| format!("failed to replace {}", path.display()))?;
+        *saved = version;
+        Ok(())
+    }
+
+    /// Writes the values set by [`BarStore::update_value`] since the last write, if any,
+    /// without keeping the bars locked meanwhile.
+    pub fn flush(&self) -> anyhow::Result<()> {
+        if self.path.is_none() {
+            return Ok(());
+        }
+        let (version, bytes) = {
+            let contents = self.contents.read().unwrap();
+            if contents.version <= *self.saved.lock().unwrap() {
+                return Ok(());
+            }
+            (contents.version, serde_json::to_vec(&*contents)?)
+        };
+        self.write(version, bytes)
+    }
+
+    /// Flushes `store` every [`FLUSH_INTERVAL`] on a blocking thread.
+    pub fn spawn_flusher(store: web::Data<BarStore>) {
+        if store.path.is_none() {
+            return;
+        }
+        rt::spawn(async move {
+            let mut interval = rt::time::interval(FLUSH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let store = store.clone();
+                match web::block(move || store.flush()).await {
+                    Ok(Ok(())) => {},
+                    Ok(Err(e)) => error!("Failed to write the bars: {:#}", e),
+                    Err(e) => error!("Failed to write the bars: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Subscribes to the ids of changed bars.
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.changes.subscribe()
+    }
+
+    fn notify(&self, id: &str) {
+        // nobody listening is not an error.
+        let _ = self.changes.send(id.to_string());
+    }
+
+    pub fn get(&self, id: &str) -> Option<StoredBar> {
+        self.contents.read().unwrap().bars.get(id).cloned()
+    }
+
+    /// Stores `spec` as bar `id`, returning whether it was created.
+    pub fn put(&self, id: &str, spec: BarSpec) -> anyhow::Result<bool> {
+        let mut contents = self.contents.write().unwrap();
+        let bar = StoredBar { spec, updated: Utc::now() };
+        let created = contents.bars.insert(id.to_string(), bar).is_none();
+        contents.record(id, self.history_limit);
+        self.save(&mut contents)?;
+        self.notify(id);
+        Ok(created)
+    }
+
+    /// Sets the value of bar `id` to `update` of its current value, creating the bar if
+    /// needed and `create` holds, and returns whether the bar was set. Composed bars have no
+    /// value of their own and are left alone. The value is written by the next
+    /// [`BarStore::flush`], as values may be set many times a second.
+    pub fn update_value(&self, id: &str, create: bool, update: impl FnOnce(Option<f32>) -> f32) -> anyhow::Result<bool> {
+        let mut contents = self.contents.write().unwrap();
+        if !create && !contents.bars.contains_key(id) {
+            return Ok(false);
+        }
+        let bar = contents.bars.entry(id.to_string())
+            .or_insert_with(|| StoredBar { spec: BarSpec::default(), updated: Utc::now() });
+        if bar.spec.components.is_some() {
+            anyhow::bail!("bar `{id}` is composed of other bars");
+        }
+        bar.spec.value = Some(update(bar.spec.value.or(bar.spec.progress)));
+        bar.updated = Utc::now();
+        contents.record(id, self.history_limit);
+        contents.version += 1;
+        self.notify(id);
+        Ok(true)
+    }
+
+    /// The last `limit` values of bar `id`, oldest first.
+    pub fn history(&self, id: &str, limit: usize) -> Vec<HistoryPoint> {
+        let contents = self.contents.read().unwrap();
+        let Some(points) = contents.history.get(id) else { return Vec::new() };
+        points.iter().skip(points.len().saturating_sub(limit)).copied().collect()
+    }
+
+    /// Removes bar `id` and its history, returning whether it existed. Its snapshots are kept.
+    pub fn delete(&self, id: &str) -> anyhow::Result<bool> {
+        let mut contents = self.contents.write().unwrap();
+        contents.history.remove(id);
+        let existed = contents.bars.remove(id).is_some();
+        if existed {
+            self.save(&mut contents)?;
+            self.notify(id);
+        }
+        Ok(existed)
+    }
+
+    pub fn snapshot(&self, sid: &str) -> Option<Snapshot> {
+        self.contents.read().unwrap().snapshots.get(sid).cloned()
+    }
+
+    /// Freezes `spec`, the current state of bar `id` with composed values resolved, returning
+    /// the id of the snapshot, or `None` if the bar does not exist.
+    pub fn create_snapshot(&self, id: &str, spec: BarSpec) -> anyhow::Result<Option<String>> {
+        let mut contents = self.contents.write().unwrap();
+        if !contents.bars.contains_key(id) {
+            return Ok(None);
+        }
+        let created = Utc::now();
+        let counter = self.snapshot_counter.fetch_add(1, Ordering::Relaxed);
+        let seed = format!("{id}/{}/{counter}", created.timestamp_nanos_opt().unwrap_or_default());
+        let sid: String = Sha256::digest(seed.as_bytes()).iter()
+            .take(8)
+            .map(|x| format!("{x:02x}"))
+            .collect();
+        let snapshot = Snapshot { bar: id.to_string(), spec, created };
+        contents.snapshots.insert(sid.clone(), snapshot);
+        self.save(&mut contents)?;
+        Ok(Some(sid))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_keeps_the_latest_values() {
+        let store = BarStore::open(None, 3).unwrap();
+        for value in [10.0, 20.0, 30.0] {
+            store.put("cov", BarSpec { value: Some(value), ..Default::default() }).unwrap();
+        }
+        assert!(store.update_value("cov", false, |x| x.unwrap_or_default() + 5.0).unwrap());
+        let values = |limit| store.history("cov", limit).iter().map(|x| x.value).collect::<Vec<_>>();
+        assert_eq!(values(30), [20.0, 30.0, 35.0]);
+        assert_eq!(values(2), [30.0, 35.0]);
+        store.delete("cov").unwrap();
+        assert!(values(30).is_empty());
+    }
+
+    #[test]
+    fn updated_values_are_written_by_flushing() {
+        let path = std::env::temp_dir().join(format!("pbar-bars-{}.json", std::process::id()));
+        let store = BarStore::open(Some(path.clone()), 3).unwrap();
+        assert!(!store.update_value("cov", false, |_| 50.0).unwrap());
+        assert!(store.get("cov").is_none());
+        assert!(store.update_value("cov", true, |_| 50.0).unwrap());
+        assert!(!path.exists());
+        store.flush().unwrap();
+        store.update_value("cov", false, |_| 60.0).unwrap();
+        assert_eq!(BarStore::open(Some(path.clone()), 3).unwrap().get("cov").unwrap().spec.value, Some(50.0));
+        store.flush().unwrap();
+        assert_eq!(BarStore::open(Some(path.clone()), 3).unwrap().get("cov").unwrap().spec.value, Some(60.0));
+        fs::remove_file(&path).unwrap();
+    }
+}
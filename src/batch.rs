@@ -0,0 +1,32 @@
+//! Several bars stacked into one SVG, served at `POST /batch`, so dashboards get all their
+//! bars with a single request.
+use minijinja::value::Value;
+
+
+/// Most bars accepted in one batch.
+pub const MAX_BARS: usize = 100;
+/// Vertical distance of the stacked bars.
+const ROW_HEIGHT: usize = 24;
+
+fn width(ctx: &Value) -> usize {
+    let attr = |name| ctx.get_attr(name).ok().and_then(|x| i64::try_from(x).ok()).unwrap_or(0);
+    (attr("title_width") + attr("progress_width")).max(0) as usize
+}
+
+/// Stacks rendered bars, given with their template context, from top to bottom.
+pub fn stack(bars: &[(Value, String)]) -> String {
+    let mut rows = String::new();
+    let mut sheet_width = 0;
+    for (i, (ctx, svg)) in bars.iter().enumerate() {
+        sheet_width = sheet_width.max(width(ctx));
+        // nested documents must not repeat the XML declaration.
+        let bar = svg.find("<svg").map_or(svg.as_str(), |start| &svg[start..]);
+        rows.push_str(&format!("<svg y=\"{}\">{bar}</svg>\n", i * ROW_HEIGHT));
+    }
+
+    let height = (bars.len() * ROW_HEIGHT).saturating_sub(ROW_HEIGHT - 20);
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <svg width=\"{sheet_width}\" height=\"{height}\" version=\"1.1\" xmlns=\"http://www.w3.org/2000/svg\" \
+         xmlns:xlink=\"http://www.w3.org/1999/xlink\">\n{rows}</svg>\n")
+}
@@ -0,0 +1,292 @@
+//! A typed alternative to filling a [`BarSpec`] by hand, e.g.
+//!
+//! ```
+//! use progress_bar::{ProgressBar, Theme};
+//!
+//! let svg = ProgressBar::builder()
+//!     .progress(72.0)
+//!     .title("Build")
+//!     .theme(Theme::Dark)
+//!     .render_svg()
+//!     .unwrap();
+//! assert!(svg.contains("Build"));
+//! ```
+//!
+//! Sources and components are left out, as they are resolved by the server.
+use std::borrow::Cow;
+use std::sync::OnceLock;
+use chrono::{DateTime, TimeZone};
+use minijinja::value::Value;
+use serde::Deserialize;
+use crate::render::{ProgressBarRenderer, RenderError};
+use crate::spec::{BarSpec, ColorMode, ColorSpace, Direction, LabelPosition, Mode, OverflowPolicy, Palette, Preset, SpecError, State, Units};
+
+
+/// Default colors of the bar, overridden by the colors given explicitly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    /// The colors of the bars served by `/render`.
+    #[default]
+    Default,
+    /// A dark title, for pages with a dark background.
+    Dark,
+}
+
+impl Theme {
+    pub(crate) fn title_color(self) -> Option<&'static str> {
+        match self {
+            Theme::Default => None,
+            Theme::Dark => Some("#30363d"),
+        }
+    }
+}
+
+/// Entry point of [`ProgressBarBuilder`].
+pub struct ProgressBar;
+
+impl ProgressBar {
+    pub fn builder() -> ProgressBarBuilder {
+        ProgressBarBuilder::default()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ProgressBarBuilder {
+    spec: BarSpec,
+    theme: Theme,
+}
+
+impl ProgressBarBuilder {
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.spec.title = Some(title.into());
+        self
+    }
+
+    pub fn title_width(mut self, width: i32) -> Self {
+        self.spec.title_width = Some(width);
+        self
+    }
+
+    pub fn title_color(mut self, color: impl Into<Cow<'static, str>>) -> Self {
+        self.spec.title_color = Some(color.into());
+        self
+    }
+
+    /// The value, from 0 to 100 unless a range is given.
+    pub fn progress(mut self, progress: f32) -> Self {
+        self.spec.progress = Some(progress);
+        self
+    }
+
+    /// The value, placed within `[min, max]`.
+    pub fn value(mut self, value: f32) -> Self {
+        self.spec.value = Some(value);
+        self
+    }
+
+    pub fn range(mut self, min: f32, max: f32) -> Self {
+        self.spec.min = Some(min);
+        self.spec.max = Some(max);
+        self
+    }
+
+    /// Adds the difference of the value to `baseline`, e.g. the coverage of the base branch.
+    pub fn baseline(mut self, baseline: f32) -> Self {
+        self.spec.baseline = Some(baseline);
+        self
+    }
+
+    /// Places the bar at `done` of `total` bytes, labeled like `700 MiB / 2 GiB`.
+    pub fn bytes(mut self, done: u64, total: u64, units: Units) -> Self {
+        self.spec.done = Some(done as f64);
+        self.spec.total = Some(total as f64);
+        self.spec.units = Some(units);
+        self
+    }
+
+    /// Sugar for `range(0.0, scale)`.
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.spec.scale = Some(scale);
+        self
+    }
+
+    /// Computes the progress from the time elapsed between `start` and `end` at render time.
+    pub fn timespan<Tz: TimeZone>(mut self, start: DateTime<Tz>, end: DateTime<Tz>) -> Self {
+        self.spec.start = Some(start.to_rfc3339());
+        self.spec.end = Some(end.to_rfc3339());
+        self
+    }
+
+    /// Shows the time left until `until` with a shrinking bar, which starts full at `start`
+    /// if given, and otherwise shrinks over the last `max` days.
+    pub fn countdown<Tz: TimeZone>(mut self, until: DateTime<Tz>, start: Option<DateTime<Tz>>) -> Self {
+        self.spec.mode = Some(Mode::Countdown);
+        self.spec.until = Some(until.to_rfc3339());
+        self.spec.start = start.map(|x| x.to_rfc3339());
+        self
+    }
+
+    /// Evaluates time based progress at `instant` instead of now.
+    pub fn as_of<Tz: TimeZone>(mut self, instant: DateTime<Tz>) -> Self {
+        self.spec.as_of = Some(instant.to_utc());
+        self
+    }
+
+    pub fn states(mut self, states: impl IntoIterator<Item = State>) -> Self {
+        self.spec.states = Some(states.into_iter().collect());
+        self
+    }
+
+    /// Name of a pipeline of the [`RendererOptions::transforms`](crate::RendererOptions).
+    pub fn transform(mut self, name: impl Into<String>) -> Self {
+        self.spec.transform = Some(name.into());
+        self
+    }
+
+    pub fn overflow(mut self, policy: OverflowPolicy) -> Self {
+        self.spec.overflow = Some(policy);
+        self
+    }
+
+    pub fn progress_width(mut self, width: i32) -> Self {
+        self.spec.progress_width = Some(width);
+        self
+    }
+
+    pub fn progress_color(mut self, color: impl Into<Cow<'static, str>>) -> Self {
+        self.spec.progress_color = Some(color.into());
+        self
+    }
+
+    /// Draws one of the bundled [`crate::logos::NAMES`] left of the title.
+    pub fn logo(mut self, name: impl Into<String>) -> Self {
+        self.spec.logo = Some(name.into());
+        self
+    }
+
+    /// Draws the image of a base64 `data:image/svg+xml` or `data:image/png` URI left of the title.
+    pub fn logo_data(mut self, uri: impl Into<String>) -> Self {
+        self.spec.logo_data = Some(uri.into());
+        self
+    }
+
+    /// Makes the whole bar a link, or only the title if [`Self::link2`] is given too.
+    pub fn link(mut self, url: impl Into<String>) -> Self {
+        self.spec.link = Some(url.into());
+        self
+    }
+
+    /// Makes the progress part of the bar a link.
+    pub fn link2(mut self, url: impl Into<String>) -> Self {
+        self.spec.link2 = Some(url.into());
+        self
+    }
+
+    pub fn suffix(mut self, suffix: impl Into<Cow<'static, str>>) -> Self {
+        self.spec.suffix = Some(suffix.into());
+        self
+    }
+
+    /// Shows the value as percentage, ratio or count.
+    pub fn preset(mut self, preset: Preset) -> Self {
+        self.spec.preset = Some(preset);
+        self
+    }
+
+    /// Formats the value for a language tag like `de-DE`.
+    pub fn locale(mut self, locale: impl Into<String>) -> Self {
+        self.spec.locale = Some(locale.into());
+        self
+    }
+
+    /// Replaces the formatted value.
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.spec.label = Some(label.into());
+        self
+    }
+
+    /// Replaces the text screen readers announce for the bar.
+    pub fn aria_label(mut self, text: impl Into<String>) -> Self {
+        self.spec.aria_label = Some(text.into());
+        self
+    }
+
+    /// Mirrors the bar for right-to-left titles.
+    pub fn dir(mut self, dir: Direction) -> Self {
+        self.spec.dir = Some(dir);
+        self
+    }
+
+    /// Moves or hides the formatted value.
+    pub fn label_position(mut self, position: LabelPosition) -> Self {
+        self.spec.label_position = Some(position);
+        self
+    }
+
+    /// Picks the default colors of the progress.
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.spec.palette = Some(palette);
+        self
+    }
+
+    /// Places the colors of the progress at percentages of the range, e.g. `0:#d9534f,100:#5cb85c`.
+    pub fn color_stops(mut self, stops: impl Into<String>) -> Self {
+        self.spec.color_stops = Some(stops.into());
+        self
+    }
+
+    /// Blends the colors of the progress between the stops in `space` instead of switching at each.
+    pub fn interpolate_colors(mut self, space: ColorSpace) -> Self {
+        self.spec.color_mode = Some(ColorMode::Interpolate);
+        self.spec.color_space = Some(space);
+        self
+    }
+
+    /// Switches to darker colors for viewers preferring a dark color scheme.
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.spec.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Hatches the filled part of low and medium values.
+    pub fn patterns(mut self, patterns: bool) -> Self {
+        self.spec.patterns = Some(patterns);
+        self
+    }
+
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
+    /// The spec of the bar, with the colors of the theme filled in.
+    pub fn build(self) -> BarSpec {
+        let mut spec = self.spec;
+        if spec.title_color.is_none() {
+            spec.title_color = self.theme.title_color().map(Into::into);
+        }
+        spec
+    }
+
+    /// The template context of the bar, as returned by `?format=json`.
+    pub fn context(self) -> Result<Value, SpecError> {
+        default_renderer().context(&self.build())
+    }
+
+    /// Renders the bar with the default template.
+    pub fn render_svg(self) -> Result<String, RenderError> {
+        self.render_svg_with(default_renderer())
+    }
+
+    /// Renders the bar with `renderer`, e.g. one with a custom template.
+    pub fn render_svg_with(self, renderer: &ProgressBarRenderer) -> Result<String, RenderError> {
+        renderer.render(&self.build())
+    }
+}
+
+fn default_renderer() -> &'static ProgressBarRenderer {
+    static RENDERER: OnceLock<ProgressBarRenderer> = OnceLock::new();
+    RENDERER.get_or_init(|| ProgressBarRenderer::new(Default::default())
+        .expect("the default template is valid"))
+}
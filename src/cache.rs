@@ -0,0 +1,36 @@
+//! A small in-memory cache for responses of upstream services.
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        TtlCache { ttl, entries: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// The value stored for `key`, unless it is older than the TTL.
+    pub fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        entries.get(key)
+            .filter(|(inserted, _)| inserted.elapsed() < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Stores `value` for `key`, dropping all expired entries.
+    pub fn insert(&self, key: K, value: V) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (inserted, _)| inserted.elapsed() < self.ttl);
+        entries.insert(key, (Instant::now(), value));
+    }
+}
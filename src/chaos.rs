@@ -0,0 +1,146 @@
+//! `?simulate=slow|500|garbage` for operators verifying their monitoring, timeouts and the
+//! handling of broken bars in production. It requires the bearer token named by
+//! `[chaos] token`, and the parameter is ignored while no token is configured. With
+//! `[chaos] rate` below 1, only that fraction of the requests fails.
+use std::time::Duration;
+use actix_web::{body::{BoxBody, MessageBody}, dev::{ServiceRequest, ServiceResponse}, http::StatusCode, rt, web, HttpResponse};
+use actix_web::middleware::Next;
+use log::warn;
+use serde::Deserialize;
+use crate::auth;
+use crate::config::ChaosConfig;
+use crate::request_id::random_u128;
+use crate::secrets::SecretStore;
+
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Simulation {
+    /// Delays the request by `[chaos] slow_secs`, running into the route timeouts.
+    Slow,
+    /// Fails with an internal server error.
+    #[serde(rename = "500")]
+    Error,
+    /// Succeeds with a body which is no image.
+    Garbage,
+}
+
+#[derive(Deserialize)]
+struct SimulateQuery {
+    simulate: Option<Simulation>,
+}
+
+/// Whether to carry out a simulation, with the probability `rate`.
+fn roll(rate: f64) -> bool {
+    // 53 random bits, uniform in [0, 1).
+    ((random_u128() >> 75) as f64 / (1u64 << 53) as f64) < rate
+}
+
+fn text(status: StatusCode, body: &'static str) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("text/plain; charset=utf-8")
+        .body(body)
+}
+
+/// Middleware applying the simulation requested by an authorized request.
+pub async fn simulate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let config = req.app_data::<web::Data<ChaosConfig>>().cloned();
+    let Some((config, name)) = config.and_then(|x| x.token.clone().map(|name| (x, name))) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let simulation = match web::Query::<SimulateQuery>::from_query(req.query_string()) {
+        Ok(query) => query.simulate,
+        Err(_) if req.query_string().contains("simulate=") =>
+            return Ok(req.into_response(text(StatusCode::BAD_REQUEST, "`simulate` must be slow, 500 or garbage"))),
+        Err(_) => None,
+    };
+    let Some(simulation) = simulation else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let authorized = req.app_data::<web::Data<SecretStore>>()
+        .and_then(|secrets| secrets.get(&name))
+        .is_some_and(|token| auth::has_bearer(req.request(), &token));
+    if !authorized {
+        let response = HttpResponse::build(StatusCode::UNAUTHORIZED)
+            .insert_header((actix_web::http::header::WWW_AUTHENTICATE, "Bearer"))
+            .content_type("text/plain; charset=utf-8")
+            .body("Simulations require a valid bearer token");
+        return Ok(req.into_response(response));
+    }
+    if !roll(config.rate) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    warn!("Simulating a failure of {} {}.", req.method(), req.uri());
+    match simulation {
+        Simulation::Slow => {
+            rt::time::sleep(Duration::from_secs(config.slow_secs)).await;
+            Ok(next.call(req).await?.map_into_boxed_body())
+        },
+        Simulation::Error => Ok(req.into_response(
+            text(StatusCode::INTERNAL_SERVER_ERROR, "Simulated internal server error"))),
+        Simulation::Garbage => Ok(req.into_response(HttpResponse::Ok()
+            .content_type("image/svg+xml; charset=utf-8")
+            .insert_header((actix_web::http::header::CACHE_CONTROL, "no-store"))
+            .body("<svg \u{0}garbage"))),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{middleware::from_fn, test, App, HttpRequest};
+    use actix_web::http::header::AUTHORIZATION;
+    use crate::config::SecretSource;
+
+    #[actix_web::test]
+    async fn simulations_follow_the_rate() {
+        let app = |rate: f64, token: Option<&str>| {
+            let config = ChaosConfig { token: token.map(str::to_string), slow_secs: 0, rate };
+            let sources = [("chaos".to_string(), SecretSource::Value("sesame".to_string()))].into();
+            let secrets = SecretStore::new(None, &sources).unwrap();
+            test::init_service(App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(secrets))
+                .wrap(from_fn(simulate))
+                .route("/bar", web::get().to(|_: HttpRequest| async { HttpResponse::Ok().body("bar") })))
+        };
+        let get = |query: &str, token: Option<&str>| {
+            let mut req = test::TestRequest::get().uri(&format!("/bar?{query}"));
+            if let Some(token) = token {
+                req = req.insert_header((AUTHORIZATION, format!("Bearer {token}")));
+            }
+            req.to_request()
+        };
+
+        let always = app(1.0, Some("chaos")).await;
+        for (query, status, body) in [
+            ("simulate=slow", StatusCode::OK, "bar"),
+            ("simulate=500", StatusCode::INTERNAL_SERVER_ERROR, "Simulated internal server error"),
+            ("simulate=garbage", StatusCode::OK, "<svg \u{0}garbage"),
+            ("simulate=flood", StatusCode::BAD_REQUEST, "`simulate` must be slow, 500 or garbage"),
+            ("", StatusCode::OK, "bar"),
+        ] {
+            let res = test::call_service(&always, get(query, Some("sesame"))).await;
+            assert_eq!(res.status(), status, "{query}");
+            assert_eq!(test::read_body(res).await, body, "{query}");
+        }
+        let res = test::call_service(&always, get("simulate=500", Some("guess"))).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let never = app(0.0, Some("chaos")).await;
+        for query in ["simulate=slow", "simulate=500", "simulate=garbage"] {
+            let res = test::call_service(&never, get(query, Some("sesame"))).await;
+            assert_eq!(res.status(), StatusCode::OK, "{query}");
+            assert_eq!(test::read_body(res).await, "bar", "{query}");
+        }
+
+        let disabled = app(1.0, None).await;
+        let res = test::call_service(&disabled, get("simulate=500", None)).await;
+        assert_eq!(test::read_body(res).await, "bar");
+    }
+}
@@ -0,0 +1,223 @@
+//! Validation of custom templates against a battery of representative bars, so mistakes
+//! show up before deployment instead of as failing requests.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Instant;
+use minijinja::UndefinedBehavior;
+use crate::context::build_context;
+use crate::render::{environment, RenderLimits, TEMPLATE_NAME};
+use crate::spec::{BarSpec, ColorMode, Direction, LabelPosition, OverflowPolicy, Palette, Preset, State};
+
+
+#[derive(Debug)]
+pub struct Problem {
+    /// The bar being rendered, `None` if the template does not compile.
+    pub case: Option<&'static str>,
+    /// The template the problem is in, `None` for the checked one.
+    pub template: Option<String>,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(template) = &self.template {
+            write!(f, "{template} ")?;
+        }
+        if let Some(line) = self.line {
+            write!(f, "line {line}: ")?;
+        }
+        write!(f, "{}", self.message)?;
+        if let Some(case) = self.case {
+            write!(f, " (rendering {case})")?;
+        }
+        Ok(())
+    }
+}
+
+impl From<minijinja::Error> for Problem {
+    fn from(e: minijinja::Error) -> Self {
+        Problem::new(None, e)
+    }
+}
+
+impl Problem {
+    fn new(case: Option<&'static str>, e: minijinja::Error) -> Self {
+        let mut message = e.to_string();
+        // the location is reported separately.
+        if let Some(i) = message.rfind(" (in ") {
+            message.truncate(i);
+        }
+        Problem {
+            case,
+            template: e.name().filter(|x| *x != TEMPLATE_NAME).map(str::to_string),
+            line: e.line(),
+            message,
+        }
+    }
+}
+
+/// Representative bars with a description.
+fn cases() -> Vec<(&'static str, BarSpec)> {
+    let bar = |progress: f32| BarSpec { progress: Some(progress), ..Default::default() };
+    vec![
+        ("an empty bar", bar(0.0)),
+        ("a half full bar", bar(50.0)),
+        ("a full bar", bar(100.0)),
+        ("an overflowing bar", BarSpec { overflow: Some(OverflowPolicy::Allow), ..bar(150.0) }),
+        ("a titled bar", BarSpec { title: Some("coverage".into()), ..bar(42.0) }),
+        ("a logo", BarSpec { title: Some("stars".into()), logo: Some("star".into()), ..bar(42.0) }),
+        ("links", BarSpec {
+            title: Some("build".into()),
+            link: Some("https://example.com/?a=1&b=2".into()),
+            link2: Some("https://example.com/runs".into()),
+            ..bar(42.0)
+        }),
+        ("a right-to-left bar", BarSpec {
+            title: Some("התקדמות".into()),
+            logo: Some("check".into()),
+            dir: Some(Direction::Rtl),
+            overflow: Some(OverflowPolicy::Allow),
+            ..bar(130.0)
+        }),
+        ("a label inside", BarSpec { label_position: Some(LabelPosition::Inside), ..bar(80.0) }),
+        ("a label outside", BarSpec {
+            title: Some("build".into()),
+            label_position: Some(LabelPosition::Outside),
+            dir: Some(Direction::Rtl),
+            ..bar(20.0)
+        }),
+        ("a hidden label", BarSpec { label_position: Some(LabelPosition::None), ..bar(50.0) }),
+        ("a colorblind palette with patterns", BarSpec {
+            palette: Some(Palette::Colorblind),
+            patterns: Some(true),
+            ..bar(20.0)
+        }),
+        ("interpolated color stops", BarSpec {
+            color_mode: Some(ColorMode::Interpolate),
+            color_stops: Some("0:#d9534f,100:#5cb85c".into()),
+            ..bar(50.0)
+        }),
+        ("an adaptive bar", BarSpec {
+            adaptive: Some(true),
+            label_position: Some(LabelPosition::Outside),
+            ..bar(60.0)
+        }),
+        ("a byte count", BarSpec {
+            done: Some(734003200.0),
+            total: Some(2147483648.0),
+            ..Default::default()
+        }),
+        ("a count preset", BarSpec {
+            preset: Some(Preset::Count),
+            max: Some(200.0),
+            locale: Some("de-DE".into()),
+            ..bar(146.0)
+        }),
+        ("a delta to a baseline", BarSpec { baseline: Some(55.0), ..bar(60.0) }),
+        ("an adaptive bar with a title color", BarSpec {
+            adaptive: Some(true),
+            title_color: Some("#123456".into()),
+            ..bar(60.0)
+        }),
+        ("a long title", BarSpec { title: Some("a rather long title of a bar".into()), ..bar(42.0) }),
+        ("a negative range", BarSpec { value: Some(-5.0), min: Some(-10.0), max: Some(0.0), ..Default::default() }),
+        ("custom colors and widths", BarSpec {
+            title: Some("custom".into()),
+            title_color: Some("#333".into()),
+            progress_color: Some("#a0f".into()),
+            title_width: Some(80),
+            progress_width: Some(200),
+            ..bar(60.0)
+        }),
+        ("a custom suffix", BarSpec { suffix: Some("/10".into()), scale: Some(10.0), ..bar(3.0) }),
+        ("a label", BarSpec { label: Some("3 / 10".into()), ..bar(30.0) }),
+        ("a state", BarSpec {
+            states: Some(vec![State { name: "build".into(), from: 0.0 }]),
+            ..bar(10.0)
+        }),
+    ]
+}
+
+/// Compiles `template` and renders it for every representative bar, treating undefined
+/// variables as errors. Returns the problems found, empty if the template is fine.
+pub fn check_template(template: &str, globals: &BTreeMap<String, serde_json::Value>) -> Vec<Problem> {
+    check_template_with(template, &BTreeMap::new(), &BTreeMap::new(), globals, RenderLimits::default())
+}
+
+/// Like [`check_template`], for a template which extends, includes or imports the `named`
+/// templates or the `partials`. Each bar is rendered with the fuel of `limits`, and the bars
+/// left when its timeout is up are skipped as a problem.
+pub fn check_template_with(
+    template: &str,
+    named: &BTreeMap<String, String>,
+    partials: &BTreeMap<String, String>,
+    globals: &BTreeMap<String, serde_json::Value>,
+    limits: RenderLimits,
+) -> Vec<Problem> {
+    let deadline = limits.timeout.map(|x| (Instant::now() + x, x));
+    let mut env = match environment(template, named, partials, globals) {
+        Ok(x) => x,
+        Err(e) => return vec![Problem::new(None, e)],
+    };
+    env.set_undefined_behavior(UndefinedBehavior::Strict);
+    env.set_fuel(limits.fuel);
+    let compiled = match env.get_template(TEMPLATE_NAME) {
+        Ok(x) => x,
+        Err(e) => return vec![Problem::new(None, e)],
+    };
+
+    let mut problems: Vec<Problem> = Vec::new();
+    for (case, spec) in cases() {
+        if let Some((deadline, timeout)) = deadline {
+            if Instant::now() > deadline {
+                let message = format!("the check did not finish within {} s", timeout.as_secs_f64());
+                problems.push(Problem::new(Some(case), minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, message)));
+                break;
+            }
+        }
+        let rendered = build_context(spec)
+            .map_err(|e| minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, e.to_string()))
+            .and_then(|ctx| compiled.render(&ctx));
+        if let Err(e) = rendered {
+            let problem = Problem::new(Some(case), e);
+            // the same mistake usually fails every case.
+            if !problems.iter().any(|x| x.line == problem.line && x.message == problem.message) {
+                problems.push(problem);
+            }
+        }
+    }
+    problems
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render::DEFAULT_TEMPLATE;
+
+    #[test]
+    fn default_template_passes() {
+        assert!(check_template(DEFAULT_TEMPLATE, &BTreeMap::new()).is_empty());
+    }
+
+    #[test]
+    fn problems_carry_line_numbers() {
+        let problems = check_template("<svg>\n{{ progres }}\n</svg>", &BTreeMap::new());
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(2));
+
+        let problems = check_template("<svg>\n{% if %}\n</svg>", &BTreeMap::new());
+        assert_eq!(problems[0].case, None);
+        assert_eq!(problems[0].line, Some(2));
+    }
+
+    #[test]
+    fn limits_apply_to_the_check() {
+        let looping = "{% for a in range(1000) %}{% for b in range(1000) %}.{% endfor %}{% endfor %}";
+        let limits = RenderLimits { fuel: Some(10_000), timeout: Some(std::time::Duration::from_secs(5)) };
+        let problems = check_template_with(looping, &BTreeMap::new(), &BTreeMap::new(), &BTreeMap::new(), limits);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].message.contains("fuel"), "{}", problems[0].message);
+    }
+}
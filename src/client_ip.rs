@@ -0,0 +1,193 @@
+//! The address of the client, taken from `X-Forwarded-For`, or `Forwarded` with
+//! `--forwarded-header forwarded`, when the peer is one of the `--trusted-proxies`, e.g. nginx
+//! on the same host. Otherwise every request would seem to come from the proxy. Only the
+//! header the proxies set is read, as clients can send the other one.
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, web, HttpMessage, HttpRequest};
+use actix_web::http::header::{HeaderMap, FORWARDED};
+use actix_web::middleware::Next;
+use clap::ValueEnum;
+
+
+/// A network like `10.0.0.0/8`, or a single address.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("`{s}` is not an address or a network like 10.0.0.0/8");
+        let (address, prefix) = s.trim().split_once('/').map_or((s.trim(), None), |(a, p)| (a, Some(p)));
+        let network: IpAddr = address.parse().map_err(|_| invalid())?;
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p.parse().ok().filter(|p| *p <= bits).ok_or_else(invalid)?,
+            None => bits,
+        };
+        Ok(Cidr { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let mask = |bits: u32| u128::MAX.checked_shl(bits - u32::from(self.prefix)).unwrap_or(0);
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = mask(32) as u32;
+                u32::from(network) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = mask(128);
+                u128::from(network) & mask == u128::from(ip) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+/// The header the trusted proxies name the client in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For`
+    #[default]
+    Xff,
+    /// `Forwarded` of RFC 7239
+    Forwarded,
+}
+
+#[derive(Default)]
+pub struct TrustedProxies {
+    pub proxies: Vec<Cidr>,
+    pub header: ForwardedHeader,
+}
+
+impl TrustedProxies {
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.proxies.iter().any(|x| x.contains(ip))
+    }
+}
+
+/// The address of a `for=` parameter of `Forwarded` or an entry of `X-Forwarded-For`,
+/// which may have a port, and brackets around IPv6 addresses.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse::<IpAddr>().ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|x| x.ip()))
+        .or_else(|| node.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+/// The hops a request was forwarded through according to `header`, the closest last. `None`
+/// stands for an obfuscated or unknown node.
+fn forwarded_chain(headers: &HeaderMap, header: ForwardedHeader) -> Vec<Option<IpAddr>> {
+    match header {
+        ForwardedHeader::Forwarded => headers.get_all(FORWARDED)
+            .filter_map(|x| x.to_str().ok())
+            .flat_map(|x| x.split(','))
+            .filter_map(|element| element.split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                .map(|(_, value)| parse_node(value)))
+            .collect(),
+        ForwardedHeader::Xff => headers.get_all("x-forwarded-for")
+            .filter_map(|x| x.to_str().ok())
+            .flat_map(|x| x.split(','))
+            .map(parse_node)
+            .collect(),
+    }
+}
+
+/// The first address of the chain ending with `peer`, from the closest one, which is not
+/// a trusted proxy.
+fn resolve(peer: IpAddr, headers: &HeaderMap, trusted: &TrustedProxies) -> IpAddr {
+    let mut client = peer;
+    for hop in forwarded_chain(headers, trusted.header).into_iter().rev() {
+        if !trusted.trusts(client) {
+            break;
+        }
+        match hop {
+            Some(ip) => client = ip,
+            None => break,
+        }
+    }
+    client
+}
+
+#[derive(Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+/// The address of the client of `req`, as resolved by [`assign`].
+pub fn get(req: &HttpRequest) -> Option<IpAddr> {
+    req.extensions().get::<ClientIp>().map(|x| x.0)
+        .or_else(|| req.peer_addr().map(|x| x.ip()))
+}
+
+/// Middleware adding the [`ClientIp`] to the request extensions, using the
+/// [`TrustedProxies`] found in the app data.
+pub async fn assign(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if let Some(peer) = req.peer_addr() {
+        let client = match req.app_data::<web::Data<TrustedProxies>>() {
+            Some(trusted) if !trusted.proxies.is_empty() => resolve(peer.ip(), req.headers(), trusted),
+            _ => peer.ip(),
+        };
+        req.extensions_mut().insert(ClientIp(client));
+    }
+    next.call(req).await
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn headers(name: &'static str, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(HeaderName::from_static(name), HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn networks_contain_their_addresses() {
+        let network: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(network.contains("10.20.30.40".parse().unwrap()));
+        assert!(!network.contains("11.0.0.1".parse().unwrap()));
+        assert!(network.contains("::ffff:10.0.0.1".parse().unwrap()));
+        assert!("::1".parse::<Cidr>().unwrap().contains("::1".parse().unwrap()));
+        assert!("0.0.0.0/0".parse::<Cidr>().unwrap().contains("1.2.3.4".parse().unwrap()));
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn forwarded_addresses_are_taken_from_trusted_peers_only() {
+        let proxies = vec!["127.0.0.1".parse().unwrap(), "10.0.0.0/8".parse().unwrap()];
+        let trusted = TrustedProxies { proxies: proxies.clone(), header: ForwardedHeader::Xff };
+        let local = "127.0.0.1".parse().unwrap();
+        let chain = headers("x-forwarded-for", "6.6.6.6, 1.2.3.4, 10.0.0.2");
+        assert_eq!(resolve(local, &chain, &trusted), "1.2.3.4".parse::<IpAddr>().unwrap());
+        assert_eq!(resolve("5.5.5.5".parse().unwrap(), &chain, &trusted), "5.5.5.5".parse::<IpAddr>().unwrap());
+
+        let forwarded = headers("forwarded", "for=1.2.3.4;proto=https, for=\"[2001:db8::1]:4711\"");
+        // clients behind a proxy setting `X-Forwarded-For` cannot spoof their address with `Forwarded`.
+        assert_eq!(resolve(local, &forwarded, &trusted), local);
+        let trusted = TrustedProxies { proxies, header: ForwardedHeader::Forwarded };
+        assert_eq!(resolve(local, &forwarded, &trusted), "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(resolve(local, &chain, &trusted), local);
+        let hidden = headers("forwarded", "for=_hidden");
+        assert_eq!(resolve(local, &hidden, &trusted), local);
+    }
+}
@@ -0,0 +1,88 @@
+//! The `color_fn` config option, a [rhai](https://rhai.rs) script choosing the progress color
+//! in place of the fixed thresholds of [`progress_color`](crate::progress_color), e.g.
+//!
+//! ```toml
+//! color_fn = 'if ratio < 0.5 { "#c00" } else { "#0c0" }'
+//! ```
+//!
+//! The script sees `ratio`, `value`, `min` and `max` as floats and returns the color.
+use std::fmt;
+use anyhow::Context;
+use rhai::{Engine, Scope, AST};
+use crate::spec::{is_color, SpecError};
+
+
+/// Operations a script may take per bar, so a loop cannot stall rendering.
+const MAX_OPERATIONS: u64 = 10_000;
+
+#[derive(Debug)]
+pub struct ColorScriptError(String);
+
+impl fmt::Display for ColorScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to evaluate `color_fn`: {}", self.0)
+    }
+}
+
+impl std::error::Error for ColorScriptError {}
+
+pub struct ColorScript {
+    engine: Engine,
+    ast: AST,
+}
+
+impl ColorScript {
+    /// Compiles `source`, trying it on a few bars so that scripts not returning a color
+    /// fail at startup rather than on requests.
+    pub fn compile(source: &str) -> anyhow::Result<Self> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        let ast = engine.compile(source).context("invalid `color_fn`")?;
+        let script = ColorScript { engine, ast };
+        for ratio in [0.0, 0.5, 1.0] {
+            script.color(ratio, ratio * 100.0, 0.0, 100.0)?;
+        }
+        Ok(script)
+    }
+
+    /// The color of a bar at `ratio`, with `value` in `[min, max]`.
+    pub fn color(&self, ratio: f32, value: f32, min: f32, max: f32) -> Result<String, ColorScriptError> {
+        let mut scope = Scope::new();
+        scope.push("ratio", ratio as f64)
+            .push("value", value as f64)
+            .push("min", min as f64)
+            .push("max", max as f64);
+        let color = self.engine.eval_ast_with_scope::<rhai::Dynamic>(&mut scope, &self.ast)
+            .map_err(|e| ColorScriptError(e.to_string()))?;
+        let color = color.into_string()
+            .map_err(|type_name| ColorScriptError(format!("expected a color string, got {type_name}")))?;
+        // the color is placed into attributes as it is.
+        match is_color(&color) {
+            true => Ok(color),
+            false => Err(ColorScriptError(SpecError::InvalidColor(color).to_string())),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_maps_ratio_to_color() {
+        let script = ColorScript::compile(r##"if ratio < 0.5 { "#c00" } else { "#0c0" }"##).unwrap();
+        assert_eq!(script.color(0.2, 20.0, 0.0, 100.0).unwrap(), "#c00");
+        assert_eq!(script.color(0.8, 80.0, 0.0, 100.0).unwrap(), "#0c0");
+    }
+
+    #[test]
+    fn scripts_must_return_a_color() {
+        assert!(ColorScript::compile("ratio * 2").is_err());
+        assert!(ColorScript::compile(r#""oops""#).is_err());
+        assert!(ColorScript::compile(r##"if ratio < 1.0 { "red" } else { "#0c0\" onload=\"x" }"##).is_err());
+        assert!(ColorScript::compile(r#"if ratio < 0.5 { "Crimson" } else { "rgb(0, 200, 0)" }"#).is_ok());
+        assert!(ColorScript::compile("loop {}").is_err());
+        assert!(ColorScript::compile("if ratio <").is_err());
+    }
+}
@@ -0,0 +1,225 @@
+//! The color of the progress, picked from stops placed along the range, e.g. `color_stops=
+//! 0:#d9534f,100:#5cb85c` for a bar from red to green. With `color_mode=interpolate` the colors
+//! are blended between the stops, in Lab by default so that the lightness changes evenly, or in
+//! HSL going round the hue circle. Otherwise each stop colors the progress up to the next one.
+use crate::spec::{ColorMode, ColorSpace, Palette, SpecError};
+
+
+/// A color taking effect at `at` percent of the range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ColorStop {
+    pub at: f32,
+    pub rgb: [f64; 3],
+}
+
+/// The components of `#rgb` or `#rrggbb` in `[0, 1]`.
+pub(crate) fn parse_hex(color: &str) -> Option<[f64; 3]> {
+    let hex = color.strip_prefix('#')?;
+    let digits: Vec<u32> = hex.chars().map(|c| c.to_digit(16)).collect::<Option<_>>()?;
+    let channel = |hi: u32, lo: u32| f64::from(hi * 16 + lo) / 255.0;
+    match digits[..] {
+        [r, g, b] => Some([channel(r, r), channel(g, g), channel(b, b)]),
+        [r1, r2, g1, g2, b1, b2] => Some([channel(r1, r2), channel(g1, g2), channel(b1, b2)]),
+        _ => None,
+    }
+}
+
+fn to_hex(rgb: [f64; 3]) -> String {
+    let [r, g, b] = rgb.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+/// The stops of `text` like `0:#d9534f,100:#5cb85c`, ordered by their position.
+pub(crate) fn parse_stops(text: &str) -> Result<Vec<ColorStop>, SpecError> {
+    let invalid = || SpecError::InvalidColorStops(text.to_string());
+    let mut stops = text.split(',')
+        .map(|stop| {
+            let (at, color) = stop.trim().split_once(':').ok_or_else(invalid)?;
+            let at = at.trim().parse::<f32>().ok().filter(|x| x.is_finite()).ok_or_else(invalid)?;
+            Ok(ColorStop { at, rgb: parse_hex(color.trim()).ok_or_else(invalid)? })
+        })
+        .collect::<Result<Vec<_>, SpecError>>()?;
+    stops.sort_by(|a, b| a.at.total_cmp(&b.at));
+    Ok(stops)
+}
+
+/// Checks that `text` is a list of color stops like `0:#d9534f,100:#5cb85c`.
+pub fn check_color_stops(text: &str) -> Result<(), SpecError> {
+    parse_stops(text).map(|_| ())
+}
+
+/// The stops drawing the bands of `palette`.
+fn palette_stops(palette: Palette) -> Vec<ColorStop> {
+    palette.stops().iter()
+        .map(|(at, color)| ColorStop { at: *at, rgb: parse_hex(color).expect("palette colors are hex") })
+        .collect()
+}
+
+fn srgb_to_lab(rgb: [f64; 3]) -> [f64; 3] {
+    let [r, g, b] = rgb.map(|c| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) });
+    let x = (0.4124564 * r + 0.3575761 * g + 0.1804375 * b) / 0.95047;
+    let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+    let z = (0.0193339 * r + 0.1191920 * g + 0.9503041 * b) / 1.08883;
+    let f = |t: f64| if t > 216.0 / 24389.0 { t.cbrt() } else { t * 841.0 / 108.0 + 4.0 / 29.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn lab_to_srgb([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let finv = |t: f64| if t > 6.0 / 29.0 { t.powi(3) } else { (t - 4.0 / 29.0) * 108.0 / 841.0 };
+    let (x, y, z) = (finv(fy + a / 500.0) * 0.95047, finv(fy), finv(fy - b / 200.0) * 1.08883);
+    [
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    ].map(|c| if c <= 0.0031308 { 12.92 * c } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 })
+}
+
+/// Hue in degrees, saturation and lightness.
+fn srgb_to_hsl([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let l = (max + min) / 2.0;
+    let d = max - min;
+    if d == 0.0 {
+        return [0.0, 0.0, l];
+    }
+    let s = d / (1.0 - (2.0 * l - 1.0).abs());
+    let h = if max == r {
+        ((g - b) / d).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / d + 2.0
+    } else {
+        (r - g) / d + 4.0
+    };
+    [h * 60.0, s, l]
+}
+
+fn hsl_to_srgb([h, s, l]: [f64; 3]) -> [f64; 3] {
+    let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let [r, g, b] = match h as u32 {
+        0 => [c, x, 0.0],
+        1 => [x, c, 0.0],
+        2 => [0.0, c, x],
+        3 => [0.0, x, c],
+        4 => [x, 0.0, c],
+        _ => [c, 0.0, x],
+    };
+    let m = l - c / 2.0;
+    [r + m, g + m, b + m]
+}
+
+/// The color `t` of the way from `from` to `to`.
+fn blend(from: [f64; 3], to: [f64; 3], t: f64, space: ColorSpace) -> [f64; 3] {
+    let mix = |a: f64, b: f64| a + (b - a) * t;
+    match space {
+        ColorSpace::Lab => {
+            let (from, to) = (srgb_to_lab(from), srgb_to_lab(to));
+            lab_to_srgb([mix(from[0], to[0]), mix(from[1], to[1]), mix(from[2], to[2])])
+        },
+        ColorSpace::Hsl => {
+            let (mut from, mut to) = (srgb_to_hsl(from), srgb_to_hsl(to));
+            // grays have no hue of their own, and take the one of the other color.
+            if from[1] == 0.0 {
+                from[0] = to[0];
+            } else if to[1] == 0.0 {
+                to[0] = from[0];
+            }
+            // the shorter way round the hue circle.
+            let turn = (to[0] - from[0] + 180.0).rem_euclid(360.0) - 180.0;
+            hsl_to_srgb([from[0] + turn * t, mix(from[1], to[1]), mix(from[2], to[2])])
+        },
+    }
+}
+
+/// The color of `stops` at `ratio` of the range.
+pub(crate) fn color_at(stops: &[ColorStop], ratio: f32, mode: ColorMode, space: ColorSpace) -> String {
+    let at = ratio * 100.0;
+    let next = stops.iter().position(|x| x.at > at).unwrap_or(stops.len());
+    let rgb = match (next, mode) {
+        (0, _) => stops[0].rgb,
+        (i, ColorMode::Bands) => stops[i - 1].rgb,
+        (i, ColorMode::Interpolate) if i == stops.len() => stops[i - 1].rgb,
+        (i, ColorMode::Interpolate) => {
+            let (from, to) = (stops[i - 1], stops[i]);
+            blend(from.rgb, to.rgb, f64::from((at - from.at) / (to.at - from.at)), space)
+        },
+    };
+    to_hex(rgb)
+}
+
+/// The color of the progress at `ratio` with the `stops` given as text, those of `palette`
+/// without.
+pub(crate) fn progress_color(
+    stops: Option<&str>,
+    palette: Palette,
+    mode: ColorMode,
+    space: ColorSpace,
+    ratio: f32,
+) -> Result<String, SpecError> {
+    let stops = match stops {
+        Some(text) => parse_stops(text)?,
+        None => palette_stops(palette),
+    };
+    Ok(color_at(&stops, ratio, mode, space))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_are_parsed_in_order() {
+        let stops = parse_stops("100:#5cb85c, 0:#d9534f").unwrap();
+        assert_eq!(stops.iter().map(|x| x.at).collect::<Vec<_>>(), [0.0, 100.0]);
+        assert_eq!(to_hex(stops[0].rgb), "#d9534f");
+        for text in ["", "0:red", "#d9534f", "x:#d9534f", "0:#d9534f,"] {
+            assert!(matches!(parse_stops(text), Err(SpecError::InvalidColorStops(_))), "{text}");
+        }
+    }
+
+    #[test]
+    fn bands_keep_the_palette_colors() {
+        for (ratio, color) in [(0.0, "#d9534f"), (0.29, "#d9534f"), (0.3, "#f0ad4e"), (0.69, "#f0ad4e"), (1.2, "#5cb85c")] {
+            let mapped = progress_color(None, Palette::Default, ColorMode::Bands, ColorSpace::Lab, ratio).unwrap();
+            assert_eq!((ratio, mapped.as_str()), (ratio, color));
+            assert_eq!(Palette::Default.color(ratio), color);
+        }
+    }
+
+    #[test]
+    fn interpolation_blends_between_stops() {
+        let stops = parse_stops("0:#000,100:#fff").unwrap();
+        let lab = |ratio| color_at(&stops, ratio, ColorMode::Interpolate, ColorSpace::Lab);
+        assert_eq!((lab(-0.5).as_str(), lab(0.0).as_str(), lab(1.0).as_str(), lab(2.0).as_str()),
+            ("#000000", "#000000", "#ffffff", "#ffffff"));
+        // half the lightness, which is brighter than half the intensity.
+        assert_eq!(lab(0.5), "#777777");
+
+        let stops = parse_stops("0:#ff0000,100:#0000ff").unwrap();
+        // from red backwards round the hue circle to blue, via magenta.
+        assert_eq!(color_at(&stops, 0.5, ColorMode::Interpolate, ColorSpace::Hsl), "#ff00ff");
+        assert_eq!(color_at(&stops, 0.5, ColorMode::Bands, ColorSpace::Hsl), "#ff0000");
+
+        // a 49% and a 51% bar look alike.
+        let stops = parse_stops("0:#d9534f,100:#5cb85c").unwrap();
+        let distance = |a: &str, b: &str| {
+            let (a, b) = (srgb_to_lab(parse_hex(a).unwrap()), srgb_to_lab(parse_hex(b).unwrap()));
+            a.iter().zip(b).map(|(a, b)| (a - b).powi(2)).sum::<f64>().sqrt()
+        };
+        let at = |ratio| color_at(&stops, ratio, ColorMode::Interpolate, ColorSpace::Lab);
+        assert!(distance(&at(0.49), &at(0.51)) < 3.0);
+    }
+
+    #[test]
+    fn conversions_round_trip() {
+        for color in ["#d9534f", "#f0ad4e", "#5cb85c", "#0072b2", "#808080", "#ffffff", "#000000"] {
+            let rgb = parse_hex(color).unwrap();
+            assert_eq!(to_hex(lab_to_srgb(srgb_to_lab(rgb))), color);
+            assert_eq!(to_hex(hsl_to_srgb(srgb_to_hsl(rgb))), color);
+        }
+    }
+}
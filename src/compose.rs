@@ -0,0 +1,211 @@
+//! Stored bars defined as the weighted average of other bars and sources, e.g. a release
+//! readiness roll-up of tests passing, docs coverage and issues closed. The value is
+//! recomputed whenever the bar is read.
+use std::fmt;
+use actix_web::http::StatusCode;
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use reqwest::Client;
+use progress_bar::{resolve_value, BarSpec, Component, ProgressBarRenderer, SpecError};
+use crate::bars::BarStore;
+use crate::config::SourcesConfig;
+use crate::health::HealthRegistry;
+use crate::sources::{self, SourceError};
+
+
+#[derive(Debug)]
+pub enum ComposeError {
+    Empty,
+    InvalidWeight(f32),
+    AmbiguousComponent(usize),
+    Conflicting(&'static str),
+    MissingBar(String),
+    Cycle(String),
+    EmptyRange { min: f32, max: f32 },
+    Query(String, SpecError),
+    Source(SourceError),
+}
+
+impl fmt::Display for ComposeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ComposeError::Empty => write!(f, "`components` must not be empty"),
+            ComposeError::InvalidWeight(weight) => write!(f, "weight {weight} is not a positive number"),
+            ComposeError::AmbiguousComponent(i) =>
+                write!(f, "component {i} must have exactly one of `bar` and `source`"),
+            ComposeError::Conflicting(field) => write!(f, "`{field}` cannot be combined with `components`"),
+            ComposeError::MissingBar(id) => write!(f, "component bar `{id}` does not exist"),
+            ComposeError::Cycle(id) => write!(f, "bar `{id}` is a component of itself"),
+            ComposeError::EmptyRange { min, max } =>
+                write!(f, "`max` ({max}) of a component must be greater than `min` ({min})"),
+            ComposeError::Query(id, e) => write!(f, "component bar `{id}`: {e}"),
+            ComposeError::Source(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ComposeError {}
+
+impl ComposeError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ComposeError::Source(e) => e.status(),
+            ComposeError::MissingBar(_) | ComposeError::Cycle(_) | ComposeError::Query(..) => StatusCode::CONFLICT,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// Checks the components of `args` when a bar is stored. Referenced bars may be created later.
+pub fn validate(args: &BarSpec) -> Result<(), ComposeError> {
+    let Some(components) = &args.components else { return Ok(()) };
+    if components.is_empty() {
+        return Err(ComposeError::Empty);
+    }
+    let conflicting = [
+        ("value", args.value.is_some()),
+        ("progress", args.progress.is_some()),
+        ("source", args.source.is_some()),
+        ("start", args.start.is_some() || args.end.is_some()),
+        ("mode", args.mode.is_some()),
+    ];
+    if let Some((field, _)) = conflicting.iter().find(|(_, given)| *given) {
+        return Err(ComposeError::Conflicting(field));
+    }
+    for (i, component) in components.iter().enumerate() {
+        if !(component.weight.is_finite() && component.weight > 0.0) {
+            return Err(ComposeError::InvalidWeight(component.weight));
+        }
+        if component.bar.is_some() == component.source.is_some() {
+            return Err(ComposeError::AmbiguousComponent(i));
+        }
+        let (min, max) = (component.min.unwrap_or(0.0), component.max.unwrap_or(100.0));
+        if max <= min {
+            return Err(ComposeError::EmptyRange { min, max });
+        }
+    }
+    Ok(())
+}
+
+/// Everything needed to compute the value of composed bars.
+pub struct Composer<'a> {
+    pub store: &'a BarStore,
+    pub client: &'a Client,
+    pub sources: &'a SourcesConfig,
+    pub health: &'a HealthRegistry,
+    pub renderer: &'a ProgressBarRenderer,
+}
+
+impl Composer<'_> {
+    /// Replaces the components of `args` by their weighted average, in percent.
+    /// Bars without components are returned as they are.
+    pub async fn resolve(&self, mut args: BarSpec) -> Result<BarSpec, ComposeError> {
+        let Some(components) = args.components.take() else { return Ok(args) };
+        let ratio = self.weighted(&components, &mut Vec::new()).await?;
+        args.value = Some(ratio * 100.0);
+        (args.min, args.max, args.scale) = (Some(0.0), Some(100.0), None);
+        Ok(args)
+    }
+
+    /// The weighted average of the ratios of `components`. `path` holds the bars being
+    /// resolved, to detect cycles.
+    fn weighted<'b>(
+        &'b self,
+        components: &'b [Component],
+        path: &'b mut Vec<String>,
+    ) -> LocalBoxFuture<'b, Result<f32, ComposeError>> {
+        async move {
+            let mut total = 0.0;
+            let mut weights = 0.0;
+            for (i, component) in components.iter().enumerate() {
+                let ratio = match (&component.bar, &component.source) {
+                    (Some(id), _) => self.bar_ratio(id, path).await?,
+                    (None, Some(url)) => {
+                        let value_path = component.value_path.as_deref().unwrap_or("$");
+                        let value = sources::fetch_value(self.client, self.sources, self.health, url, value_path)
+                            .await
+                            .map_err(ComposeError::Source)?;
+                        let (min, max) = (component.min.unwrap_or(0.0), component.max.unwrap_or(100.0));
+                        (value - min) / (max - min)
+                    },
+                    (None, None) => return Err(ComposeError::AmbiguousComponent(i)),
+                };
+                total += component.weight * ratio.clamp(0.0, 1.0);
+                weights += component.weight;
+            }
+            if weights > 0.0 { Ok(total / weights) } else { Err(ComposeError::Empty) }
+        }.boxed_local()
+    }
+
+    async fn bar_ratio(&self, id: &str, path: &mut Vec<String>) -> Result<f32, ComposeError> {
+        if path.iter().any(|x| x == id) {
+            return Err(ComposeError::Cycle(id.to_string()));
+        }
+        let mut spec = self.store.get(id).ok_or_else(|| ComposeError::MissingBar(id.to_string()))?.spec;
+        if let Some(components) = spec.components.take() {
+            path.push(id.to_string());
+            let ratio = self.weighted(&components, path).await;
+            path.pop();
+            return ratio;
+        }
+
+        if let Some(url) = &spec.source {
+            let value_path = spec.value_path.as_deref().unwrap_or("$");
+            let value = sources::fetch_value(self.client, self.sources, self.health, url, value_path)
+                .await
+                .map_err(ComposeError::Source)?;
+            spec.value = Some(value);
+        }
+        let query_error = |e| ComposeError::Query(id.to_string(), e);
+        self.renderer.apply_transform(&mut spec).map_err(query_error)?;
+        let (value, min, max, _) = resolve_value(&spec).map_err(query_error)?;
+        if max <= min {
+            return Err(query_error(SpecError::EmptyRange { min, max }));
+        }
+        Ok((value - min) / (max - min))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn composed(bars: &[(&str, f32)]) -> BarSpec {
+        let components = bars.iter()
+            .map(|(id, weight)| Component {
+                weight: *weight,
+                bar: Some(id.to_string()),
+                source: None,
+                value_path: None,
+                min: None,
+                max: None,
+            })
+            .collect();
+        BarSpec { components: Some(components), ..Default::default() }
+    }
+
+    #[actix_web::test]
+    async fn components_are_averaged_unless_they_cycle() {
+        let store = BarStore::open(None, 0).unwrap();
+        store.put("tests", BarSpec { progress: Some(90.0), ..Default::default() }).unwrap();
+        store.put("docs", BarSpec { value: Some(3.0), max: Some(10.0), ..Default::default() }).unwrap();
+        store.put("release", composed(&[("tests", 3.0), ("docs", 1.0)])).unwrap();
+        store.put("roll-up", composed(&[("release", 1.0)])).unwrap();
+        store.put("ping", composed(&[("pong", 1.0)])).unwrap();
+        store.put("pong", composed(&[("tests", 1.0), ("ping", 1.0)])).unwrap();
+        store.put("itself", composed(&[("itself", 1.0)])).unwrap();
+        let (client, sources, health) = (Client::new(), SourcesConfig::default(), HealthRegistry::new([]));
+        let renderer = ProgressBarRenderer::new(Default::default()).unwrap();
+        let composer = Composer { store: &store, client: &client, sources: &sources, health: &health, renderer: &renderer };
+        let resolve = |id: &str| composer.resolve(store.get(id).unwrap().spec);
+
+        let value = |spec: BarSpec| spec.value.unwrap();
+        assert!((value(resolve("release").await.unwrap()) - (3.0 * 90.0 + 30.0) / 4.0).abs() < 1e-3);
+        // bars composed of composed bars are fine, as long as none contains itself.
+        assert!((value(resolve("roll-up").await.unwrap()) - 75.0).abs() < 1e-3);
+        assert!(matches!(resolve("ping").await, Err(ComposeError::Cycle(id)) if id == "pong"));
+        assert!(matches!(resolve("pong").await, Err(ComposeError::Cycle(id)) if id == "ping"));
+        assert!(matches!(resolve("itself").await, Err(ComposeError::Cycle(id)) if id == "itself"));
+        assert_eq!(ComposeError::Cycle("ping".into()).status(), StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,135 @@
+//! `--compression`: gzip or brotli encoding of the textual responses, as negotiated with
+//! `Accept-Encoding`. SVGs shrink to a fraction of their size. The compress middleware of
+//! actix leaves every `image/*` alone, SVGs included, so this one picks the types itself.
+//! Caches are told with `Vary: Accept-Encoding` that the body depends on the header, and the
+//! encoded bodies get entity tags of their own, suffixed with the coding.
+use actix_http::encoding::Encoder;
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, HttpMessage};
+use actix_web::http::{header::{self, AcceptEncoding, ContentEncoding, Encoding, HeaderValue}, StatusCode};
+use actix_web::middleware::Next;
+use crate::output;
+
+
+/// Whether bodies of `content_type` are worth compressing. Event streams are left alone,
+/// as compressing would hold back their events.
+fn is_compressible(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime == "image/svg+xml"
+        || mime == "application/json"
+        || mime == "application/xml"
+        || (mime.starts_with("text/") && mime != "text/event-stream")
+}
+
+pub async fn compress(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let supported = [Encoding::brotli(), Encoding::gzip(), Encoding::identity()];
+    let encoding = match req.get_header::<AcceptEncoding>().and_then(|x| x.negotiate(supported.iter())) {
+        Some(Encoding::Known(encoding)) => encoding,
+        // clients refusing every encoding still get the identity.
+        _ => ContentEncoding::Identity,
+    };
+    let response = next.call(req).await?;
+    Ok(response.map_body(move |head, body| {
+        let compressible = head.headers().get(header::CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .is_some_and(is_compressible);
+        // bodies of 304 responses are those the client has, which it got encoded if it could.
+        if head.status == StatusCode::NOT_MODIFIED {
+            suffix_etag(head.headers_mut(), encoding);
+        }
+        if !compressible {
+            return Encoder::response(ContentEncoding::Identity, head, body);
+        }
+        if encoding == ContentEncoding::Identity {
+            head.headers_mut().append(header::VARY, HeaderValue::from_static("accept-encoding"));
+        }
+        let encoded = head.headers().contains_key(header::CONTENT_ENCODING);
+        // the encoder adds `Vary` itself when encoding.
+        let body = Encoder::response(encoding, head, body);
+        if !encoded && head.headers().contains_key(header::CONTENT_ENCODING) {
+            suffix_etag(head.headers_mut(), encoding);
+        }
+        body
+    }))
+}
+
+/// Suffixes the strong entity tag in `headers` with `encoding`, one of [`output::CODINGS`].
+fn suffix_etag(headers: &mut header::HeaderMap, encoding: ContentEncoding) {
+    if !output::CODINGS.contains(&encoding.as_str()) {
+        return;
+    }
+    let tag = headers.get(header::ETAG)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix('"')?.strip_suffix('"'))
+        .map(|x| format!("\"{x}-{}\"", encoding.as_str()));
+    if let Some(value) = tag.and_then(|x| HeaderValue::from_str(&x).ok()) {
+        headers.insert(header::ETAG, value);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{middleware::from_fn, test, web, App, HttpResponse};
+    use super::*;
+
+    fn content(content_type: &'static str) -> impl Fn() -> std::future::Ready<HttpResponse> + Clone {
+        move || std::future::ready(HttpResponse::Ok()
+            .content_type(content_type)
+            .insert_header((header::ETAG, "\"abc\""))
+            .body("<svg>".repeat(100)))
+    }
+
+    #[actix_web::test]
+    async fn bodies_are_encoded_as_accepted() {
+        let app = test::init_service(App::new()
+            .wrap(from_fn(compress))
+            .route("/bar.svg", web::get().to(content("image/svg+xml")))
+            .route("/bar.png", web::get().to(content("image/png")))
+            .route("/events", web::get().to(content("text/event-stream")))).await;
+        let get = |uri: &'static str, accept: Option<&'static str>| {
+            let mut request = test::TestRequest::get().uri(uri);
+            if let Some(accept) = accept {
+                request = request.insert_header((header::ACCEPT_ENCODING, accept));
+            }
+            test::call_service(&app, request.to_request())
+        };
+        let header = |headers: &header::HeaderMap, name| headers.get(name).map(|x| x.to_str().unwrap().to_string());
+
+        for (accept, coding, etag) in [
+            (Some("gzip, br"), Some("br"), "\"abc-br\""),
+            (Some("br;q=0.5, gzip"), Some("gzip"), "\"abc-gzip\""),
+            (Some("identity"), None, "\"abc\""),
+            (Some("br;q=0, gzip;q=0"), None, "\"abc\""),
+            (None, None, "\"abc\""),
+        ] {
+            let response = get("/bar.svg", accept).await;
+            assert_eq!(header(response.headers(), header::CONTENT_ENCODING).as_deref(), coding, "{accept:?}");
+            assert_eq!(header(response.headers(), header::VARY).as_deref(), Some("accept-encoding"), "{accept:?}");
+            assert_eq!(header(response.headers(), header::ETAG).as_deref(), Some(etag), "{accept:?}");
+        }
+        for uri in ["/bar.png", "/events"] {
+            let response = get(uri, Some("gzip")).await;
+            assert_eq!(header(response.headers(), header::CONTENT_ENCODING), None, "{uri}");
+            assert_eq!(header(response.headers(), header::VARY), None, "{uri}");
+            assert_eq!(header(response.headers(), header::ETAG).as_deref(), Some("\"abc\""), "{uri}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn encoded_tags_revalidate_the_identity() {
+        let etag = header::EntityTag::new_strong("abc".to_string());
+        for (sent, fresh) in [("\"abc\"", true), ("\"abc-gzip\"", true), ("W/\"abc-br\"", true), ("\"abc-zstd\"", false)] {
+            let req = test::TestRequest::get().insert_header((header::IF_NONE_MATCH, sent)).to_http_request();
+            assert_eq!(output::is_fresh(&req, &etag), fresh, "{sent}");
+        }
+        let app = test::init_service(App::new()
+            .wrap(from_fn(compress))
+            .route("/", web::get().to(|| async { HttpResponse::NotModified().insert_header((header::ETAG, "\"abc\"")).finish() }))).await;
+        let request = test::TestRequest::get().insert_header((header::ACCEPT_ENCODING, "gzip")).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.headers().get(header::ETAG).unwrap(), "\"abc-gzip\"");
+    }
+}
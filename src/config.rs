@@ -0,0 +1,379 @@
+//! The optional TOML configuration file passed via `--config`.
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::fs::read_to_string;
+use std::time::Duration;
+use anyhow::Context;
+use serde::Deserialize;
+use progress_bar::{BarDefaults, RenderLimits};
+use progress_bar::postprocess::PostProcessor;
+use progress_bar::transforms::TransformStep;
+
+
+const DEFAULTS_PREFIX: &str = "PBAR_DEFAULT_";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Per-route overrides keyed by route name, e.g. `[routes.render]`.
+    pub routes: HashMap<String, RouteConfig>,
+    /// Credentials of upstream services keyed by name, e.g. `github = { env = "GITHUB_TOKEN" }`.
+    pub secrets: HashMap<String, SecretSource>,
+    /// Proxy used by every request to an upstream service.
+    pub proxy: ProxyConfig,
+    /// Remote JSON documents progress values are read from.
+    pub sources: SourcesConfig,
+    pub github: GithubConfig,
+    pub packages: PackagesConfig,
+    /// Value pipelines selected with `?transform=<name>`.
+    pub transforms: HashMap<String, Vec<TransformStep>>,
+    /// Steps applied in order to every rendered SVG, e.g. `["minify"]`.
+    pub postprocess: Vec<PostProcessor>,
+    /// Constants available to templates, e.g. `[globals] brand = "#e05d44"`.
+    pub globals: BTreeMap<String, serde_json::Value>,
+    /// A rhai script returning the progress color from `ratio`, `value`, `min` and `max`.
+    pub color_fn: Option<String>,
+    /// Presentation of the bars unless requested otherwise, e.g. `[defaults] suffix = " %"`,
+    /// overridden in turn by `PBAR_DEFAULT_<FIELD>` environment variables.
+    pub defaults: BarDefaults,
+    pub bars: BarsConfig,
+    pub statsd: StatsdConfig,
+    pub mqtt: MqttConfig,
+    /// Webhooks setting stored bars keyed by bar id, e.g. `[hooks.coverage]`.
+    pub hooks: HashMap<String, HookConfig>,
+    /// YAML file of named badges served at `/b/{name}.svg`.
+    pub badges: Option<PathBuf>,
+    pub stats: StatsConfig,
+    pub views: ViewsConfig,
+    pub chaos: ChaosConfig,
+    /// Directory of the templates selected with `?template=<name>`, one `<name>.svg` file each,
+    /// and of the `*.j2` partials they may extend, include or import.
+    pub templates: Option<PathBuf>,
+    pub admin: AdminConfig,
+    /// Bounds of the work of templates, e.g. `[render] timeout = 2.0`.
+    pub render: RenderConfig,
+    /// Defaults of bars embedded on matching sites, e.g. `[[referrers]]`. The first match wins.
+    pub referrers: Vec<ReferrerRule>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    /// Maximum number of requests of this route handled at the same time, across all workers.
+    pub concurrency: Option<usize>,
+    /// Seconds a request of this route may take, including waiting for a free slot.
+    pub timeout: Option<f64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourcesConfig {
+    /// Hosts which may be fetched from, `*.example.com` matching all its subdomains.
+    /// Remote sources are disabled while this is empty.
+    pub allowed_hosts: Vec<String>,
+    /// Seconds to wait for a source.
+    pub timeout: f64,
+    /// Documents of sources larger than this are rejected before being parsed.
+    pub max_body_bytes: usize,
+}
+
+impl Default for SourcesConfig {
+    fn default() -> Self {
+        SourcesConfig {
+            allowed_hosts: Vec::new(),
+            timeout: 5.0,
+            max_body_bytes: 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GithubConfig {
+    /// Base URL of the API, to be changed for GitHub Enterprise.
+    pub api_url: String,
+    /// Name of the secret holding the API token. Requests are anonymous without it.
+    pub token: Option<String>,
+    /// Seconds API responses are reused, keeping within the rate limits.
+    pub cache_ttl: u64,
+}
+
+impl Default for GithubConfig {
+    fn default() -> Self {
+        GithubConfig {
+            api_url: "https://api.github.com".to_string(),
+            token: None,
+            cache_ttl: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarsConfig {
+    /// JSON file the stored bars are persisted in. They are lost on restart without.
+    pub path: Option<PathBuf>,
+    /// Name of the secret clients must send as bearer token to modify bars.
+    /// Anybody may modify them without.
+    pub token: Option<String>,
+    /// Values kept per bar for `/bars/{id}/trend.svg`, 0 to keep none.
+    pub history: usize,
+}
+
+impl Default for BarsConfig {
+    fn default() -> Self {
+        BarsConfig {
+            path: None,
+            token: None,
+            history: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsdConfig {
+    /// UDP address of the StatsD listener, e.g. `127.0.0.1:8125`. Disabled without.
+    pub bind: Option<String>,
+    /// Prefix of the gauges setting stored bars, as in `pbar.<id>.progress:72|g`.
+    pub prefix: String,
+    /// Bars gauges may create. Gauges of other bars only update them once they exist, as
+    /// anybody reaching the listener can send gauges.
+    pub create_bars: Vec<String>,
+}
+
+impl Default for StatsdConfig {
+    fn default() -> Self {
+        StatsdConfig {
+            bind: None,
+            prefix: "pbar.".to_string(),
+            create_bars: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    /// Broker whose messages set stored bars. Disabled without.
+    pub host: Option<String>,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    /// Name of the secret holding the password of `username`.
+    pub password: Option<String>,
+    pub subscriptions: Vec<MqttSubscription>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            host: None,
+            port: 1883,
+            client_id: "progress-bar".to_string(),
+            username: None,
+            password: None,
+            subscriptions: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttSubscription {
+    /// Topic filter, possibly with `+` and `#` wildcards.
+    pub topic: String,
+    /// ID of the bar set by the messages, `{1}` standing for the level matched by the first `+`.
+    pub bar: String,
+    /// JSONPath of the value in JSON payloads. Payloads are plain numbers without.
+    pub value_path: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReferrerRule {
+    /// Hosts of the `Referer`, `*.example.com` matching every subdomain.
+    pub hosts: Vec<String>,
+    pub title_color: Option<String>,
+    pub progress_color: Option<String>,
+    pub progress_width: Option<i32>,
+    pub suffix: Option<String>,
+    /// Replaces the `Cache-Control` of the bars.
+    pub cache_control: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    /// Name of the secret the payloads are signed with.
+    pub secret: String,
+    /// JSONPath of the value in the payloads, e.g. `$.coverage.percent`.
+    pub value_path: String,
+    /// Header of the signature, `X-Hub-Signature-256` without.
+    pub signature_header: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChaosConfig {
+    /// Name of the secret clients must send as bearer token to use `?simulate=`.
+    /// Simulations are disabled without.
+    pub token: Option<String>,
+    /// Seconds `?simulate=slow` delays requests.
+    pub slow_secs: u64,
+    /// Fraction of the simulations carried out, the other requests passing through, e.g. `0.1`
+    /// for the intermittent failures alerts should tolerate.
+    pub rate: f64,
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        ChaosConfig {
+            token: None,
+            slow_secs: 30,
+            rate: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderConfig {
+    /// Instructions a template may execute for a bar, the default template needing less
+    /// than a thousand.
+    pub fuel: Option<u64>,
+    /// Seconds a bar may take to render, failing with 503 afterwards. It requires `fuel`,
+    /// which stops the renders given up on.
+    pub timeout: Option<f64>,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            fuel: Some(1_000_000),
+            timeout: Some(5.0),
+        }
+    }
+}
+
+impl RenderConfig {
+    pub fn limits(&self) -> anyhow::Result<RenderLimits> {
+        let timeout = self.timeout
+            .map(|secs| Duration::try_from_secs_f64(secs)
+                .map_err(|e| anyhow::anyhow!("invalid render timeout {}: {}", secs, e)))
+            .transpose()?;
+        if timeout.is_some() && self.fuel.is_none() {
+            anyhow::bail!("a render timeout requires fuel, which stops the renders given up on");
+        }
+        Ok(RenderLimits { fuel: self.fuel, timeout })
+    }
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Name of the secret clients must send as bearer token to use `/admin/*`, which is
+    /// not served without.
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ViewsConfig {
+    /// Counts the views and referring hosts of every badge and stored bar, served at
+    /// `/b/{name}/stats` and `/bars/{id}/stats` to clients sending the bearer token of
+    /// `[stats]` or `[bars]`. They are not served when neither is configured.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsConfig {
+    /// Name of the secret clients must send as bearer token to read `/stats`.
+    /// The statistics are not served without.
+    pub token: Option<String>,
+    /// Seconds of requests summarized.
+    pub window: u64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        StatsConfig {
+            token: None,
+            window: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PackagesConfig {
+    pub crates_api_url: String,
+    pub npm_api_url: String,
+    /// Seconds download counts are reused.
+    pub cache_ttl: u64,
+}
+
+impl Default for PackagesConfig {
+    fn default() -> Self {
+        PackagesConfig {
+            crates_api_url: "https://crates.io".to_string(),
+            npm_api_url: "https://api.npmjs.org".to_string(),
+            cache_ttl: 3600,
+        }
+    }
+}
+
+/// Without a `url`, the usual `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY`
+/// environment variables are honored.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// e.g. `http://proxy.corp:3128`.
+    pub url: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<SecretSource>,
+    /// Comma separated hosts which are connected directly, like `NO_PROXY`.
+    pub no_proxy: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecretSource {
+    /// The secret itself.
+    Value(String),
+    /// Name of the environment variable holding the secret.
+    Env(String),
+    /// File holding the secret, trailing whitespace is stripped.
+    File(PathBuf),
+}
+
+impl Config {
+    /// The defaults of the `PBAR_DEFAULT_<FIELD>` environment variables, e.g.
+    /// `PBAR_DEFAULT_SUFFIX=" pts"`.
+    pub fn env_defaults() -> anyhow::Result<BarDefaults> {
+        let fields: Vec<_> = std::env::vars()
+            .filter_map(|(name, value)| Some((name.strip_prefix(DEFAULTS_PREFIX)?.to_ascii_lowercase(), value)))
+            .collect();
+        let query = serde_urlencoded::to_string(&fields)?;
+        serde_urlencoded::from_str(&query)
+            .with_context(|| format!("invalid {DEFAULTS_PREFIX}* environment variable"))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        let config: Config = toml::from_str(&text)
+            .with_context(|| format!("failed to parse config file {}", path.display()))?;
+        if !(config.sources.timeout.is_finite() && config.sources.timeout > 0.0) {
+            anyhow::bail!("`sources.timeout` must be a positive number of seconds");
+        }
+        if config.stats.window == 0 {
+            anyhow::bail!("`stats.window` must be a positive number of seconds");
+        }
+        if !(0.0..=1.0).contains(&config.chaos.rate) {
+            anyhow::bail!("`chaos.rate` must be between 0 and 1");
+        }
+        Ok(config)
+    }
+}
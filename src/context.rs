@@ -0,0 +1,634 @@
+//! The template context of a bar: its value placed within the range, colors and widths.
+use serde_json::json;
+use crate::builder::Theme;
+use crate::color_script::ColorScript;
+use crate::colors;
+use crate::filters;
+use crate::logos;
+use crate::numbers;
+use crate::spec::{BarSpec, Direction, LabelPosition, Mode, Palette, OverflowPolicy, Preset, SpecError, State};
+use crate::timespan;
+
+
+/// The height of the bars drawn by the default template.
+pub const BAR_HEIGHT: u32 = 20;
+/// The space between a bar and the chip of its delta.
+const DELTA_GAP: i32 = 3;
+
+/// The color of the progress at `ratio` unless `progress_color` or a palette is given.
+pub fn progress_color(ratio: f32) -> &'static str {
+    Palette::Default.color(ratio)
+}
+
+/// Resolves `(value, min, max)` of the bar, plus a label replacing the formatted value, like
+/// the time left of countdowns and the byte counts of `done` and `total`.
+pub fn resolve_value(spec: &BarSpec) -> Result<(f32, f32, f32, Option<String>), SpecError> {
+    let now = spec.as_of.unwrap_or_else(chrono::Utc::now);
+    if spec.mode == Some(Mode::Countdown) {
+        let tz = timespan::parse_timezone(spec.tz.as_deref())?;
+        let until = timespan::parse_instant(
+            spec.until.as_deref().ok_or(SpecError::MissingDeadline)?, tz)?;
+        let remaining = until - now;
+        let label = if remaining > chrono::TimeDelta::zero() {
+            format!("{} left", timespan::humanize(remaining))
+        } else {
+            "expired".to_string()
+        };
+        return match &spec.start {
+            Some(start) => {
+                let start = timespan::parse_instant(start, tz)?;
+                let left = 100.0 - timespan::elapsed_percent(start, until, now)?;
+                Ok((left, 0.0, 100.0, Some(label)))
+            },
+            // without a start the bar shrinks over the last `max` days, 30 by default.
+            None => {
+                let days = remaining.num_seconds() as f32 / 86400.0;
+                Ok((days, spec.min.unwrap_or(0.0), spec.max.or(spec.scale).unwrap_or(30.0), Some(label)))
+            },
+        };
+    }
+
+    match (spec.done, spec.total) {
+        (Some(done), Some(total)) if total > 0.0 => {
+            let units = spec.units.unwrap_or_default();
+            let label = format!("{} / {}", units.format(done), units.format(total));
+            return Ok(((done / total * 100.0) as f32, 0.0, 100.0, Some(label)));
+        },
+        (None, None) => {},
+        _ => return Err(SpecError::IncompleteByteCount),
+    }
+
+    match (&spec.start, &spec.end) {
+        (Some(start), Some(end)) => {
+            let tz = timespan::parse_timezone(spec.tz.as_deref())?;
+            let start = timespan::parse_instant(start, tz)?;
+            let end = timespan::parse_instant(end, tz)?;
+            Ok((timespan::elapsed_percent(start, end, now)?, 0.0, 100.0, None))
+        },
+        (None, None) => Ok((
+            spec.value.or(spec.progress).ok_or(SpecError::MissingValue)?,
+            spec.min.unwrap_or(0.0),
+            spec.max.or(spec.scale).unwrap_or(spec.preset.map_or(100.0, Preset::default_max)),
+            None,
+        )),
+        _ => Err(SpecError::IncompleteTimespan),
+    }
+}
+
+/// The sizes of a bar, from which [`place`] positions its parts.
+struct Layout {
+    dir: Direction,
+    label_position: LabelPosition,
+    title_width: i32,
+    progress_width: i32,
+    /// The width of the filled part of the progress.
+    fill_width: i32,
+    /// The width the formatted value takes up.
+    text_width: i32,
+    /// The width of the chip of the delta to the baseline, 0 without.
+    delta_width: i32,
+    has_logo: bool,
+}
+
+/// The pixels of a raster image of an SVG declaring `width`×`height`, at `density` pixels
+/// per CSS pixel.
+pub fn pixel_size(width: f32, height: f32, density: u32) -> (u32, u32) {
+    let scale = |x: f32| (x * density as f32).ceil() as u32;
+    (scale(width), scale(height))
+}
+
+/// Adds the positions of the parts of the bar, which are mirrored for right-to-left bars:
+/// the total `width`, the `bar_x` and `bar_width` of the bar without an outside label, the
+/// `logo_x`, the `title_x` the title text is anchored at with `title_anchor`, and the
+/// `value_x` of the formatted value anchored with `value_anchor`, drawn in `value_color`,
+/// the `text_width` the value takes up and the `delta_x` of the chip of the delta, which
+/// follows the bar.
+fn place(args: &mut serde_json::Value, layout: Layout, progress_color: &str) {
+    let Layout { dir, label_position, title_width, progress_width, fill_width, text_width, delta_width, has_logo } = layout;
+    let bar_width = title_width + progress_width;
+    let outside_width = if label_position == LabelPosition::Outside { text_width } else { 0 };
+    let delta_space = if delta_width > 0 { DELTA_GAP + delta_width } else { 0 };
+    // the positions left to right, with the text anchored at its start or the middle.
+    let title_x = if has_logo { 21 } else { 4 };
+    let (value_x, value_anchor, value_color) = match label_position {
+        LabelPosition::Inside if fill_width >= text_width =>
+            (title_width + fill_width / 2, "middle", filters::contrast(progress_color).unwrap_or("#fff")),
+        // a value not fitting into the fill follows it.
+        LabelPosition::Inside => (title_width + fill_width + 4, "start", "#fff"),
+        LabelPosition::Outside => (bar_width + 4, "start", "#555"),
+        LabelPosition::Center | LabelPosition::None => (title_width + progress_width / 2, "middle", "#fff"),
+    };
+    // the end of the text is anchored explicitly, as renderers disagree on what the start of
+    // right-to-left text is.
+    let (bar_x, logo_x, title_x, title_anchor, value_x, value_anchor, delta_x) = match dir {
+        Direction::Ltr => (0, 4, title_x, "start", value_x, value_anchor, bar_width + outside_width + DELTA_GAP),
+        Direction::Rtl => {
+            let mirror = |x: i32| delta_space + outside_width + bar_width - x;
+            let value_anchor = if value_anchor == "start" { "end" } else { value_anchor };
+            (delta_space + outside_width, mirror(18), mirror(title_x), "end", mirror(value_x), value_anchor, 0)
+        },
+    };
+    args["dir"] = serde_json::to_value(dir).unwrap();
+    args["label_position"] = serde_json::to_value(label_position).unwrap();
+    args["width"] = (bar_width + outside_width + delta_space).into();
+    args["bar_x"] = bar_x.into();
+    args["bar_width"] = bar_width.into();
+    args["fill_width"] = fill_width.into();
+    args["logo_x"] = logo_x.into();
+    args["title_x"] = title_x.into();
+    args["title_anchor"] = title_anchor.into();
+    args["value_x"] = value_x.into();
+    args["value_anchor"] = value_anchor.into();
+    args["value_color"] = value_color.into();
+    args["text_width"] = text_width.into();
+    if delta_width > 0 {
+        args["delta_x"] = delta_x.into();
+        args["delta_width"] = delta_width.into();
+    }
+}
+
+/// Builds the template context of `spec`. Its transform is not applied.
+pub fn build_context(spec: BarSpec) -> Result<minijinja::value::Value, SpecError> {
+    build_context_with(spec, None)
+}
+
+/// Builds the template context of `spec`, its default progress color chosen by `color_script`
+/// if given.
+pub(crate) fn build_context_with(
+    spec: BarSpec,
+    color_script: Option<&ColorScript>,
+) -> Result<minijinja::value::Value, SpecError> {
+    spec.validate()?;
+    let (mut value, min, max, label) = resolve_value(&spec)?;
+    let mut args = json!({});
+    let mut progress_width = 90;
+    let mut title_width = 0;
+
+    if let Some(title) = spec.title {
+        progress_width = 60;
+        title_width = 10 + 6 * title.chars().count() as i32;
+        args["title"] = title.into();
+    }
+    if let Some(logo) = logos::resolve(spec.logo.as_deref(), spec.logo_data.as_deref())? {
+        // the 14px icon with a gap to the title, or centered without one.
+        title_width += if title_width > 0 { 17 } else { 22 };
+        args["logo"] = logo.into();
+    }
+
+    if max <= min {
+        return Err(SpecError::EmptyRange { min, max });
+    }
+    if !(min..=max).contains(&value) {
+        match spec.overflow.unwrap_or_default() {
+            OverflowPolicy::Clamp => value = value.clamp(min, max),
+            OverflowPolicy::Error => return Err(SpecError::OutOfRange { value, min, max }),
+            OverflowPolicy::Allow => {},
+        }
+    }
+    let ratio = (value - min) / (max - min);
+    let state = spec.states.as_deref().and_then(|states| State::reached(states, value));
+    let suffix = spec.suffix.unwrap_or_else(|| spec.preset.map_or("%", Preset::default_suffix).into());
+    let separator = spec.locale.as_deref().map(numbers::decimal_separator).transpose()?.unwrap_or('.');
+    // a preset replaces the labels of byte counts and countdowns, a locale alone does not.
+    let formatted = (spec.preset.is_some() || spec.locale.is_some())
+        .then(|| numbers::format_value(spec.preset, value, ratio, max, &suffix, separator));
+    let (preferred, fallback) = if spec.preset.is_some() { (formatted, label) } else { (label, formatted) };
+    let label = spec.label.or_else(|| state.map(|x| x.name.clone())).or(preferred).or(fallback);
+    if let Some(state) = state {
+        args["state"] = state.name.as_str().into();
+    }
+
+    for (key, link) in [("link", spec.link), ("link2", spec.link2)] {
+        if let Some(link) = link {
+            args[key] = link.into();
+        }
+    }
+
+    if let Some(template) = spec.template {
+        args["template"] = template.into();
+    }
+    if spec.adaptive == Some(true) {
+        args["adaptive"] = true.into();
+        if spec.title_color.is_none() {
+            args["dark_title_color"] = Theme::Dark.title_color().into();
+        }
+    }
+    args["title_color"] = spec.title_color.unwrap_or_else(|| "#428bca".into()).into();
+    args["value"] = value.into();
+    args["min"] = min.into();
+    args["max"] = max.into();
+    args["ratio"] = ratio.into();
+    // the filled part never leaves the bar, even if overflowing values are allowed.
+    args["fill_ratio"] = ratio.clamp(0.0, 1.0).into();
+    args["overflow"] = (ratio > 1.0).into();
+    // `progress` and `scale` are kept for templates written before ranges existed.
+    args["progress"] = value.into();
+    args["scale"] = (max - min).into();
+    args["progress_color"] = match (spec.progress_color, color_script) {
+        (Some(color), _) => color,
+        // stops or a mode asked for by the spec take precedence over the configured script.
+        (None, _) if spec.color_stops.is_some() || spec.color_mode.is_some() => colors::progress_color(
+            spec.color_stops.as_deref(),
+            spec.palette.unwrap_or_default(),
+            spec.color_mode.unwrap_or_default(),
+            spec.color_space.unwrap_or_default(),
+            ratio,
+        )?.into(),
+        (None, Some(script)) => script.color(ratio, value, min, max)?.into(),
+        (None, None) => spec.palette.unwrap_or_default().color(ratio).into(),
+    }.into();
+    if spec.patterns == Some(true) && ratio < 0.7 {
+        args["fill_pattern"] = if ratio < 0.3 { "dense" } else { "sparse" }.into();
+    }
+    let shown = label.clone().unwrap_or_else(|| format!("{value}{suffix}"));
+    // as long as the text the template draws, which formats the value as a float.
+    let shown_len = match &label {
+        Some(label) => label.chars().count(),
+        None => minijinja::value::Value::from(f64::from(value)).to_string().chars().count() + suffix.chars().count(),
+    };
+    // the delta is rounded as shown, so that `±0.0` is never colored as a change.
+    let delta = spec.baseline.map(|baseline| (baseline, ((value - baseline) * 10.0).round() / 10.0));
+    let delta_text = delta.map(|(_, delta)| {
+        let (sign, delta) = if delta > 0.0 { ("+", delta) } else if delta < 0.0 { ("", delta) } else { ("±", 0.0) };
+        format!("{sign}{}{suffix}", numbers::number(delta, 1, separator, false))
+    });
+    let shown = match (&delta_text, delta) {
+        (Some(text), Some((baseline, _))) => format!("{shown}, {text} from {baseline}{suffix}"),
+        _ => shown,
+    };
+    args["aria_label"] = match (spec.aria_label, &args["title"]) {
+        (Some(aria_label), _) => aria_label,
+        (None, serde_json::Value::String(title)) => format!("{title}: {shown}"),
+        (None, _) => shown,
+    }.into();
+    let mut delta_width = 0;
+    if let (Some(text), Some((baseline, delta))) = (delta_text, delta) {
+        let arrow = if delta > 0.0 { "▲" } else if delta < 0.0 { "▼" } else { "" };
+        // the arrow and a space before the text.
+        delta_width = 10 + 6 * text.chars().count() as i32 + if arrow.is_empty() { 0 } else { 12 };
+        args["baseline"] = baseline.into();
+        args["delta"] = delta.into();
+        args["delta_arrow"] = arrow.into();
+        args["delta_text"] = text.into();
+        args["delta_color"] = spec.palette.unwrap_or_default().delta_color(delta).into();
+    }
+    args["description"] = format!("{:.0}% of the range from {min} to {max}", ratio * 100.0).into();
+    args["suffix"] = suffix.into();
+    if let Some(label) = label {
+        progress_width = progress_width.max(10 + 6 * label.chars().count() as i32);
+        args["label"] = label.into();
+    }
+    let progress_width = spec.progress_width.unwrap_or(progress_width);
+    let title_width = spec.title_width.unwrap_or(title_width);
+    args["title_width"] = title_width.into();
+    args["progress_width"] = progress_width.into();
+    let layout = Layout {
+        dir: spec.dir.unwrap_or_default(),
+        label_position: spec.label_position.unwrap_or_default(),
+        title_width,
+        progress_width,
+        fill_width: (ratio.clamp(0.0, 1.0) * progress_width as f32) as i32,
+        text_width: 10 + 6 * shown_len as i32,
+        delta_width,
+        has_logo: args.get("logo").is_some(),
+    };
+    let progress_color = args["progress_color"].as_str().unwrap_or_default().to_string();
+    place(&mut args, layout, &progress_color);
+    let density = spec.density.unwrap_or(1);
+    let width = args["width"].as_f64().unwrap_or_default() as f32;
+    let (pixel_width, pixel_height) = pixel_size(width, BAR_HEIGHT as f32, density);
+    args["density"] = density.into();
+    args["pixel_width"] = pixel_width.into();
+    args["pixel_height"] = pixel_height.into();
+
+    Ok(minijinja::value::Value::from_serializable(&args))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::is_color;
+    use crate::spec::{ColorMode, Units};
+
+    fn attr(ctx: &minijinja::value::Value, key: &str) -> f64 {
+        f64::try_from(ctx.get_attr(key).unwrap()).unwrap()
+    }
+
+    fn spec(progress: f32, overflow: OverflowPolicy) -> BarSpec {
+        BarSpec {
+            progress: Some(progress),
+            overflow: Some(overflow),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn overflow_clamp_limits_value_to_range() {
+        let ctx = build_context(spec(150.0, OverflowPolicy::Clamp)).unwrap();
+        assert_eq!(attr(&ctx, "value"), 100.0);
+        assert_eq!(attr(&ctx, "ratio"), 1.0);
+        assert!(!ctx.get_attr("overflow").unwrap().is_true());
+
+        let ctx = build_context(spec(-20.0, OverflowPolicy::Clamp)).unwrap();
+        assert_eq!(attr(&ctx, "value"), 0.0);
+        assert_eq!(attr(&ctx, "fill_ratio"), 0.0);
+    }
+
+    #[test]
+    fn overflow_error_rejects_out_of_range_values() {
+        assert!(matches!(build_context(spec(150.0, OverflowPolicy::Error)),
+                         Err(SpecError::OutOfRange { .. })));
+        assert!(matches!(build_context(spec(-1.0, OverflowPolicy::Error)),
+                         Err(SpecError::OutOfRange { .. })));
+        assert!(build_context(spec(100.0, OverflowPolicy::Error)).is_ok());
+    }
+
+    #[test]
+    fn countdowns_show_the_time_left() {
+        let countdown = |as_of: &str, start: Option<&str>, overflow| BarSpec {
+            mode: Some(Mode::Countdown),
+            until: Some("2024-03-11T00:00:00Z".into()),
+            start: start.map(Into::into),
+            as_of: Some(as_of.parse().unwrap()),
+            overflow: Some(overflow),
+            ..Default::default()
+        };
+        let ctx = build_context(countdown("2024-03-01T00:00:00Z", None, OverflowPolicy::Clamp)).unwrap();
+        assert_eq!((attr(&ctx, "value"), attr(&ctx, "max")), (10.0, 30.0));
+        assert_eq!(ctx.get_attr("label").unwrap().as_str(), Some("10 days left"));
+        let ctx = build_context(countdown("2024-03-06T00:00:00Z", Some("2024-03-01"), OverflowPolicy::Clamp)).unwrap();
+        assert_eq!((attr(&ctx, "value"), attr(&ctx, "max")), (50.0, 100.0));
+        assert_eq!(ctx.get_attr("label").unwrap().as_str(), Some("5 days left"));
+        let ctx = build_context(countdown("2024-03-10T21:30:00Z", None, OverflowPolicy::Clamp)).unwrap();
+        assert_eq!(ctx.get_attr("label").unwrap().as_str(), Some("2 hours left"));
+
+        // expired countdowns fall below the range.
+        let expired = |overflow| countdown("2024-03-12T00:00:00Z", None, overflow);
+        let ctx = build_context(expired(OverflowPolicy::Clamp)).unwrap();
+        assert_eq!(attr(&ctx, "value"), 0.0);
+        assert_eq!(ctx.get_attr("label").unwrap().as_str(), Some("expired"));
+        assert!(matches!(build_context(expired(OverflowPolicy::Error)),
+                         Err(SpecError::OutOfRange { value, .. }) if value == -1.0));
+        let ctx = build_context(expired(OverflowPolicy::Allow)).unwrap();
+        assert_eq!(attr(&ctx, "value"), -1.0);
+        let started = countdown("2024-03-12T00:00:00Z", Some("2024-03-01"), OverflowPolicy::Error);
+        assert!(matches!(build_context(started), Err(SpecError::OutOfRange { .. })));
+        assert!(matches!(build_context(BarSpec { until: None, ..expired(OverflowPolicy::Clamp) }),
+                         Err(SpecError::MissingDeadline)));
+    }
+
+    #[test]
+    fn overflow_allow_keeps_value_and_marks_overflow() {
+        let ctx = build_context(spec(150.0, OverflowPolicy::Allow)).unwrap();
+        assert_eq!(attr(&ctx, "value"), 150.0);
+        assert_eq!(attr(&ctx, "ratio"), 1.5);
+        assert_eq!(attr(&ctx, "fill_ratio"), 1.0);
+        assert!(ctx.get_attr("overflow").unwrap().is_true());
+    }
+
+    #[test]
+    fn clamp_is_the_default_policy() {
+        let ctx = build_context(BarSpec {
+            progress: Some(120.0),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(attr(&ctx, "value"), 100.0);
+    }
+
+    #[test]
+    fn states_replace_the_formatted_value() {
+        let states = ["design", "build", "shipped"].iter().zip([0.0, 40.0, 100.0])
+            .map(|(name, from)| State { name: name.to_string(), from })
+            .collect();
+        let ctx = build_context(BarSpec {
+            progress: Some(70.0),
+            states: Some(states),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(ctx.get_attr("label").unwrap().as_str(), Some("build"));
+        assert_eq!(ctx.get_attr("state").unwrap().as_str(), Some("build"));
+    }
+
+    #[test]
+    fn value_is_placed_within_the_range() {
+        let ctx = build_context(BarSpec {
+            value: Some(70.0),
+            min: Some(50.0),
+            max: Some(90.0),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(attr(&ctx, "ratio"), 0.5);
+        assert_eq!(ctx.get_attr("progress_color").unwrap().as_str(), Some(progress_color(0.5)));
+    }
+
+    #[test]
+    fn links_must_be_plain_http_urls() {
+        let link = |url: &str| build_context(BarSpec {
+            progress: Some(50.0),
+            link: Some(url.to_string()),
+            ..Default::default()
+        });
+        assert!(link("https://example.com/build?id=1&x=2").is_ok());
+        for url in ["javascript:alert(1)", "https://", "//example.com", "https://example.com/\"onload=\"x"] {
+            assert!(matches!(link(url), Err(SpecError::InvalidLink(_))), "{url}");
+        }
+    }
+
+    #[test]
+    fn json_specs_are_validated_like_queries() {
+        let spec: BarSpec = serde_json::from_value(json!({
+            "progress": 50, "title_color": "red\"/><script>", "progress_width": 100000, "scale": 0,
+        })).unwrap();
+        let fields: Vec<_> = spec.invalid_fields().into_iter().map(|(field, _)| field).collect();
+        assert_eq!(fields, ["scale", "progress_width", "title_color"]);
+        assert!(matches!(build_context(spec), Err(SpecError::InvalidScale(_))));
+        assert!(is_color("rgb(1, 2, 3)") && is_color("#12345678") && !is_color("#12") && !is_color("url(#a)"));
+        assert!(is_color("SteelBlue") && !is_color("oops"));
+    }
+
+    #[test]
+    fn aria_label_describes_the_bar() {
+        let ctx = build_context(BarSpec {
+            title: Some("coverage".into()),
+            progress: Some(73.0),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(ctx.get_attr("aria_label").unwrap().as_str(), Some("coverage: 73%"));
+        let ctx = build_context(BarSpec {
+            progress: Some(73.0),
+            aria_label: Some("tests".into()),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(ctx.get_attr("aria_label").unwrap().as_str(), Some("tests"));
+    }
+
+    #[test]
+    fn labels_widen_the_bar_by_their_characters() {
+        let ctx = build_context(BarSpec {
+            progress: Some(50.0),
+            label: Some("✓✓✓✓✓✓✓✓✓✓✓✓✓✓".into()),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(attr(&ctx, "progress_width"), 94.0);
+    }
+
+    #[test]
+    fn rtl_bars_are_mirrored() {
+        let ctx = build_context(BarSpec {
+            title: Some("build".into()),
+            progress: Some(50.0),
+            dir: Some(Direction::Rtl),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(attr(&ctx, "width"), 100.0);
+        assert_eq!(attr(&ctx, "title_x"), 96.0);
+        assert_eq!(attr(&ctx, "value_x"), 30.0);
+        assert_eq!(ctx.get_attr("title_anchor").unwrap().as_str(), Some("end"));
+
+        // widths count characters, not the bytes of their UTF-8 encoding.
+        let hebrew = build_context(BarSpec {
+            title: Some("בנייה".into()),
+            progress: Some(50.0),
+            dir: Some(Direction::Rtl),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(attr(&hebrew, "title_width"), 40.0);
+    }
+
+    #[test]
+    fn label_positions_move_the_value() {
+        let ctx = |progress: f32, label_position| build_context(BarSpec {
+            progress: Some(progress),
+            progress_color: Some("#f0e68c".into()),
+            label_position: Some(label_position),
+            ..Default::default()
+        }).unwrap();
+        let inside = ctx(80.0, LabelPosition::Inside);
+        assert_eq!(attr(&inside, "value_x"), 36.0);
+        assert_eq!(inside.get_attr("value_color").unwrap().as_str(), Some("#333"));
+        let narrow = ctx(10.0, LabelPosition::Inside);
+        assert_eq!(narrow.get_attr("value_anchor").unwrap().as_str(), Some("start"));
+        let outside = ctx(50.0, LabelPosition::Outside);
+        assert_eq!(attr(&outside, "bar_width"), 90.0);
+        assert_eq!(attr(&outside, "width"), 90.0 + 10.0 + 6.0 * 5.0);
+    }
+
+    #[test]
+    fn colorblind_palette_with_patterns() {
+        let ctx = |progress: f32| build_context(BarSpec {
+            progress: Some(progress),
+            palette: Some(Palette::Colorblind),
+            patterns: Some(true),
+            ..Default::default()
+        }).unwrap();
+        let low = ctx(10.0);
+        assert_eq!(low.get_attr("progress_color").unwrap().as_str(), Some("#d55e00"));
+        assert_eq!(low.get_attr("fill_pattern").unwrap().as_str(), Some("dense"));
+        assert_eq!(ctx(50.0).get_attr("fill_pattern").unwrap().as_str(), Some("sparse"));
+        let high = ctx(90.0);
+        assert_eq!(high.get_attr("progress_color").unwrap().as_str(), Some("#0072b2"));
+        assert!(high.get_attr("fill_pattern").unwrap().is_undefined());
+    }
+
+    #[test]
+    fn adaptive_bars_keep_explicit_title_colors() {
+        let ctx = |title_color: Option<&'static str>| build_context(BarSpec {
+            progress: Some(50.0),
+            title_color: title_color.map(Into::into),
+            adaptive: Some(true),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(ctx(None).get_attr("dark_title_color").unwrap().as_str(), Some("#30363d"));
+        let explicit = ctx(Some("#123456"));
+        assert!(explicit.get_attr("adaptive").unwrap().is_true());
+        assert!(explicit.get_attr("dark_title_color").unwrap().is_undefined());
+    }
+
+    #[test]
+    fn density_scales_the_pixels() {
+        let ctx = |density| build_context(BarSpec {
+            progress: Some(50.0),
+            density: Some(density),
+            ..Default::default()
+        });
+        let ctx2 = ctx(2).unwrap();
+        assert_eq!(attr(&ctx2, "width"), 90.0);
+        assert_eq!(attr(&ctx2, "pixel_width"), 180.0);
+        assert_eq!(attr(&ctx2, "pixel_height"), 40.0);
+        assert!(matches!(ctx(0), Err(SpecError::InvalidDensity(0))));
+        assert_eq!(pixel_size(82.5, 20.0, 3), (248, 60));
+    }
+
+    #[test]
+    fn baseline_adds_a_delta_chip() {
+        let ctx = |progress, dir| build_context(BarSpec {
+            progress: Some(progress),
+            baseline: Some(79.0),
+            dir: Some(dir),
+            ..Default::default()
+        }).unwrap();
+        let better = ctx(82.0, Direction::Ltr);
+        assert_eq!(better.get_attr("delta_text").unwrap().as_str(), Some("+3.0%"));
+        assert_eq!(better.get_attr("delta_color").unwrap().as_str(), Some("#4c1"));
+        assert_eq!(better.get_attr("aria_label").unwrap().as_str(), Some("82%, +3.0% from 79%"));
+        assert_eq!(attr(&better, "delta_x"), 93.0);
+        assert_eq!(attr(&better, "width"), 93.0 + attr(&better, "delta_width"));
+
+        let worse = ctx(76.5, Direction::Rtl);
+        assert_eq!(worse.get_attr("delta_text").unwrap().as_str(), Some("-2.5%"));
+        assert_eq!(worse.get_attr("delta_arrow").unwrap().as_str(), Some("▼"));
+        assert_eq!((attr(&worse, "delta_x"), attr(&worse, "bar_x")), (0.0, attr(&worse, "delta_width") + 3.0));
+        assert_eq!(ctx(79.04, Direction::Ltr).get_attr("delta_text").unwrap().as_str(), Some("±0.0%"));
+    }
+
+    #[test]
+    fn byte_counts_are_labeled_in_units() {
+        let spec = |total, units| BarSpec { done: Some(734003200.0), total, units, ..Default::default() };
+        let (value, min, max, label) = resolve_value(&spec(Some(2147483648.0), None)).unwrap();
+        assert_eq!((value, min, max), (34.179688, 0.0, 100.0));
+        assert_eq!(label.as_deref(), Some("700 MiB / 2 GiB"));
+        let (_, _, _, label) = resolve_value(&spec(Some(2e9), Some(Units::Si))).unwrap();
+        assert_eq!(label.as_deref(), Some("734 MB / 2 GB"));
+        assert_eq!(Units::Bytes.format(1536.0), "1.5 KiB");
+        assert_eq!(Units::Bytes.format(1023.0), "1023 B");
+        assert_eq!(Units::Bytes.format(1048575.0), "1 MiB");
+        assert_eq!(Units::Si.format(999_960.0), "1 MB");
+        assert_eq!(Units::Si.format(999_940.0), "999.9 kB");
+        assert!(matches!(resolve_value(&spec(None, None)), Err(SpecError::IncompleteByteCount)));
+        assert!(matches!(resolve_value(&spec(Some(0.0), None)), Err(SpecError::IncompleteByteCount)));
+    }
+
+    #[test]
+    fn presets_label_the_value() {
+        let ctx = |progress, preset, locale: Option<&str>| build_context(BarSpec {
+            progress: Some(progress),
+            preset: Some(preset),
+            locale: locale.map(Into::into),
+            ..Default::default()
+        }).unwrap();
+        let ratio = ctx(0.73, Preset::Ratio, Some("de-DE"));
+        assert_eq!(ratio.get_attr("label").unwrap().as_str(), Some("0,73"));
+        assert_eq!(ctx(73.5, Preset::Count, Some("fr")).get_attr("label").unwrap().as_str(), Some("73,5/100"));
+        assert!(matches!(
+            build_context(BarSpec { locale: Some("german".into()), ..spec(50.0, OverflowPolicy::Clamp) }),
+            Err(SpecError::InvalidLocale(_)),
+        ));
+    }
+
+    #[test]
+    fn color_stops_take_precedence_over_the_script() {
+        let script = ColorScript::compile(r##""#c00""##).unwrap();
+        let ctx = |color_mode, color_stops: Option<&str>| build_context_with(BarSpec {
+            color_mode,
+            color_stops: color_stops.map(Into::into),
+            ..spec(50.0, OverflowPolicy::Clamp)
+        }, Some(&script));
+        let color = |ctx: Result<minijinja::value::Value, SpecError>| ctx.unwrap().get_attr("progress_color").unwrap().to_string();
+        assert_eq!(color(ctx(None, None)), "#c00");
+        assert_eq!(color(ctx(None, Some("0:#000,50:#fff"))), "#ffffff");
+        assert_eq!(color(ctx(Some(ColorMode::Interpolate), Some("0:#000,100:#fff"))), "#777777");
+        assert_eq!(color(ctx(Some(ColorMode::Bands), None)), "#f0ad4e");
+        assert!(matches!(ctx(None, Some("0:red")), Err(SpecError::InvalidColorStops(_))));
+    }
+}
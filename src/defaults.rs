@@ -0,0 +1,107 @@
+//! Defaults of the presentation of every bar, set by the operator instead of forking the
+//! template. They fill the fields a spec leaves out, so every request can still override them.
+use std::borrow::Cow;
+use serde::Deserialize;
+use crate::builder::Theme;
+use crate::spec::{BarSpec, ColorMode, ColorSpace, Direction, LabelPosition, OverflowPolicy, Palette};
+
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BarDefaults {
+    pub title_color: Option<String>,
+    pub title_width: Option<i32>,
+    pub progress_color: Option<String>,
+    pub progress_width: Option<i32>,
+    pub palette: Option<Palette>,
+    pub color_mode: Option<ColorMode>,
+    pub color_space: Option<ColorSpace>,
+    pub patterns: Option<bool>,
+    pub suffix: Option<String>,
+    pub locale: Option<String>,
+    pub scale: Option<f32>,
+    pub tz: Option<String>,
+    pub overflow: Option<OverflowPolicy>,
+    pub label_position: Option<LabelPosition>,
+    pub dir: Option<Direction>,
+    pub adaptive: Option<bool>,
+    /// Colors used unless `title_color` is given.
+    pub theme: Option<Theme>,
+}
+
+impl BarDefaults {
+    /// These defaults, with the fields they leave out taken from `fallback`.
+    pub fn or(self, fallback: BarDefaults) -> BarDefaults {
+        BarDefaults {
+            title_color: self.title_color.or(fallback.title_color),
+            title_width: self.title_width.or(fallback.title_width),
+            progress_color: self.progress_color.or(fallback.progress_color),
+            progress_width: self.progress_width.or(fallback.progress_width),
+            palette: self.palette.or(fallback.palette),
+            color_mode: self.color_mode.or(fallback.color_mode),
+            color_space: self.color_space.or(fallback.color_space),
+            patterns: self.patterns.or(fallback.patterns),
+            suffix: self.suffix.or(fallback.suffix),
+            locale: self.locale.or(fallback.locale),
+            scale: self.scale.or(fallback.scale),
+            tz: self.tz.or(fallback.tz),
+            overflow: self.overflow.or(fallback.overflow),
+            label_position: self.label_position.or(fallback.label_position),
+            dir: self.dir.or(fallback.dir),
+            adaptive: self.adaptive.or(fallback.adaptive),
+            theme: self.theme.or(fallback.theme),
+        }
+    }
+
+    /// Fills the fields `spec` leaves out.
+    pub fn apply(&self, spec: &mut BarSpec) {
+        let theme_color = self.theme.and_then(Theme::title_color).map(String::from);
+        if spec.title_color.is_none() {
+            spec.title_color = self.title_color.clone().or(theme_color).map(Cow::from);
+        }
+        if spec.progress_color.is_none() {
+            spec.progress_color = self.progress_color.clone().map(Cow::from);
+        }
+        if spec.suffix.is_none() {
+            spec.suffix = self.suffix.clone().map(Cow::from);
+        }
+        // a range given by the spec replaces the default scale.
+        if spec.max.is_none() {
+            spec.scale = spec.scale.or(self.scale);
+        }
+        spec.title_width = spec.title_width.or(self.title_width);
+        spec.progress_width = spec.progress_width.or(self.progress_width);
+        spec.palette = spec.palette.or(self.palette);
+        spec.color_mode = spec.color_mode.or(self.color_mode);
+        spec.color_space = spec.color_space.or(self.color_space);
+        spec.patterns = spec.patterns.or(self.patterns);
+        spec.tz = spec.tz.take().or_else(|| self.tz.clone());
+        spec.locale = spec.locale.take().or_else(|| self.locale.clone());
+        spec.overflow = spec.overflow.or(self.overflow);
+        spec.label_position = spec.label_position.or(self.label_position);
+        spec.dir = spec.dir.or(self.dir);
+        spec.adaptive = spec.adaptive.or(self.adaptive);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_fill_only_missing_fields() {
+        let defaults = BarDefaults {
+            suffix: Some(" pts".into()),
+            scale: Some(10.0),
+            theme: Some(Theme::Dark),
+            ..Default::default()
+        }.or(BarDefaults { suffix: Some("!".into()), progress_width: Some(120), ..Default::default() });
+        let mut spec = BarSpec { max: Some(50.0), progress_width: Some(80), ..Default::default() };
+        defaults.apply(&mut spec);
+        assert_eq!(spec.suffix.as_deref(), Some(" pts"));
+        assert_eq!(spec.title_color.as_deref(), Some("#30363d"));
+        assert_eq!(spec.progress_width, Some(80));
+        assert_eq!(spec.scale, None);
+    }
+}
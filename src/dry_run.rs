@@ -0,0 +1,47 @@
+//! `?dry_run=true` on the routes rendering bars, reporting what would be rendered as JSON
+//! instead, so integrators can debug URL construction cheaply.
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use actix_web::{web, HttpRequest};
+use progress_bar::{BarSpec, Format, ProgressBarRenderer};
+
+
+#[derive(Deserialize)]
+struct DryRunQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+pub fn requested(req: &HttpRequest) -> bool {
+    web::Query::<DryRunQuery>::from_query(req.query_string()).is_ok_and(|x| x.dry_run)
+}
+
+/// Identifies the output of the bar, changing whenever the rendered bytes could.
+fn cache_key(args: &BarSpec, format: Format, renderer: &ProgressBarRenderer) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(&(args, format, &args.label)).unwrap_or_default());
+    hasher.update([renderer.has_custom_template() as u8]);
+    hasher.finalize().iter()
+        .take(16)
+        .map(|x| format!("{x:02x}"))
+        .collect()
+}
+
+/// The report of a bar whose template context is `layout`.
+pub fn report(
+    args: &BarSpec,
+    format: Format,
+    layout: &minijinja::value::Value,
+    renderer: &ProgressBarRenderer,
+    cache_control: Option<&str>,
+) -> serde_json::Value {
+    json!({
+        "parameters": args,
+        "template": if renderer.has_custom_template() { "custom" } else { "default" },
+        "format": format,
+        "layout": layout,
+        "cache_key": cache_key(args, format, renderer),
+        "cache_control": cache_control,
+    })
+}
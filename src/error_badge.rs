@@ -0,0 +1,256 @@
+//! Failures of the routes serving bars as a small red badge instead of plain text, so that
+//! a broken bar embedded in a README shows what went wrong instead of a broken-image icon.
+//! The status stays 4xx or 5xx. `?error=json|svg|text` picks the representation, which
+//! defaults to SVG for the bar routes and leaves the text of every other route alone.
+//! JSON errors with an `error` message, like those of invalid queries, are turned into
+//! badges as well.
+use std::fmt;
+use actix_web::{body::{self, BoxBody, MessageBody}, dev::{ServiceRequest, ServiceResponse}, web, HttpResponse, ResponseError};
+use actix_web::http::{header::{self, HeaderValue}, StatusCode};
+use actix_web::middleware::Next;
+use serde::{Deserialize, Serialize};
+use crate::query::FieldError;
+use crate::request_id;
+
+
+/// Routes responding with bars, whose errors are badges by default.
+const BAR_ROUTES: &[&str] = &[
+    "render", "github_milestone", "crate_downloads", "npm_downloads", "shields", "bar", "badge", "snapshot", "bar_trend",
+    "path_bar", "titled_path_bar",
+];
+/// Approximate advance of a character of the 11px sans-serif font the bars use.
+const CHAR_WIDTH: usize = 7;
+const LABEL: &str = "error";
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ErrorFormat {
+    Svg,
+    Json,
+    Text,
+}
+
+#[derive(Deserialize)]
+struct ErrorQuery {
+    error: Option<ErrorFormat>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ErrorBody {
+    #[serde(default)]
+    status: u16,
+    error: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<FieldError>,
+    #[serde(default)]
+    request_id: Option<String>,
+}
+
+impl ErrorBody {
+    /// The message with the problems of the fields, one per line.
+    fn describe(&self) -> String {
+        let mut text = self.error.clone();
+        for field in &self.fields {
+            text.push_str(&format!("\n`{}` {}", field.field, field.message));
+        }
+        text
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// The short text shown on the badge for `status`.
+fn summary(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => "not allowed",
+        StatusCode::NOT_FOUND => "not found",
+        StatusCode::NOT_ACCEPTABLE => "unsupported format",
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => "unavailable",
+        StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT => "upstream error",
+        x if x.is_client_error() => "invalid params",
+        _ => "render error",
+    }
+}
+
+/// A badge reading `error | <summary>`, with the full `message` as tooltip.
+fn badge(status: StatusCode, message: &str) -> String {
+    let text = summary(status);
+    let label_width = LABEL.len() * CHAR_WIDTH + 10;
+    let text_width = text.len() * CHAR_WIDTH + 10;
+    let width = label_width + text_width;
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <svg width=\"{width}\" height=\"20\" version=\"1.1\" xmlns=\"http://www.w3.org/2000/svg\" \
+         role=\"img\" aria-label=\"{LABEL}: {text}\">\n\
+         <title>{}</title>\n\
+         <rect rx=\"4\" width=\"{width}\" height=\"20\" fill=\"#555\" />\n\
+         <rect rx=\"4\" x=\"{label_width}\" width=\"{text_width}\" height=\"20\" fill=\"#d9534f\" />\n\
+         <path fill=\"#d9534f\" d=\"M{label_width} 0h4v20h-4z\" />\n\
+         <g fill=\"#fff\" font-family=\"DejaVu Sans,Verdana,Geneva,sans-serif\" font-size=\"11\" text-anchor=\"middle\">\n\
+         <text x=\"{}\" y=\"14\">{LABEL}</text>\n\
+         <text x=\"{}\" y=\"14\">{text}</text>\n\
+         </g>\n\
+         </svg>\n",
+        escape(message), label_width / 2, label_width + text_width / 2)
+}
+
+/// The body replacing the error `body` of a response with `status`, if any.
+fn replace(
+    format: ErrorFormat,
+    status: StatusCode,
+    headers: &header::HeaderMap,
+    body: &[u8],
+    id: Option<String>,
+) -> Option<(&'static str, String)> {
+    if !is_replaced(format, status) {
+        return None;
+    }
+    let mut error = match is_json(headers) {
+        // other JSON is left as it is.
+        true => serde_json::from_slice::<ErrorBody>(body).ok()?,
+        false => ErrorBody {
+            status: 0,
+            error: String::from_utf8_lossy(body).into_owned(),
+            fields: Vec::new(),
+            request_id: None,
+        },
+    };
+    error.status = status.as_u16();
+    error.request_id = id;
+    Some(match (format, &error.request_id) {
+        (ErrorFormat::Svg, Some(id)) =>
+            ("image/svg+xml; charset=utf-8", badge(status, &format!("{}\n(request ID {id})", error.describe()))),
+        (ErrorFormat::Svg, None) => ("image/svg+xml; charset=utf-8", badge(status, &error.describe())),
+        _ => ("application/json", serde_json::to_string(&error).ok()?),
+    })
+}
+
+/// Whether responses with `status` are replaced when `format` is requested.
+fn is_replaced(format: ErrorFormat, status: StatusCode) -> bool {
+    format != ErrorFormat::Text && (status.is_client_error() || status.is_server_error())
+}
+
+fn content_type(headers: &header::HeaderMap) -> &str {
+    headers.get(header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or_default()
+}
+
+fn is_json(headers: &header::HeaderMap) -> bool {
+    content_type(headers).starts_with("application/json")
+}
+
+/// Whether the body is an error message which can be replaced.
+fn is_replaceable(headers: &header::HeaderMap) -> bool {
+    content_type(headers).starts_with("text/plain") || is_json(headers)
+}
+
+/// An error of the inner middleware, e.g. a timeout, with its replaced response.
+#[derive(Debug)]
+struct Replaced {
+    status: StatusCode,
+    content_type: &'static str,
+    body: String,
+}
+
+impl fmt::Display for Replaced {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.status)
+    }
+}
+
+impl ResponseError for Replaced {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status)
+            .content_type(self.content_type)
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .body(self.body.clone())
+    }
+}
+
+/// Middleware replacing the error responses as requested by `?error=`.
+///
+/// It has to run inside of [`request_id::assign`], which appends the request ID to the
+/// plain text left.
+pub async fn render(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let requested = web::Query::<ErrorQuery>::from_query(req.query_string()).ok().and_then(|x| x.error);
+    let is_bar_route = req.match_name().is_some_and(|name| BAR_ROUTES.contains(&name));
+    let format = requested.unwrap_or(if is_bar_route { ErrorFormat::Svg } else { ErrorFormat::Text });
+    let id = request_id::get(req.request());
+
+    // the request must not be cloned before the routing, so errors of the inner middleware
+    // are replaced by another error instead of a response.
+    let response = match next.call(req).await {
+        Ok(response) => response.map_into_boxed_body(),
+        Err(e) => {
+            let response = e.error_response();
+            if !is_replaced(format, response.status()) || !is_replaceable(response.headers()) {
+                return Err(e);
+            }
+            let (status, headers) = (response.status(), response.headers().clone());
+            let message = body::to_bytes(response.into_body()).await.unwrap_or_default();
+            return match replace(format, status, &headers, &message, id) {
+                Some((content_type, body)) => Err(Replaced { status, content_type, body }.into()),
+                None => Err(e),
+            };
+        }
+    };
+    // successful bodies are passed on as they are streamed, without being buffered.
+    if !is_replaced(format, response.status()) || !is_replaceable(response.headers()) {
+        return Ok(response);
+    }
+
+    let status = response.status();
+    let (req, response) = response.into_parts();
+    let (mut response, body) = response.into_parts();
+    let message = body::to_bytes(body).await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let Some((content_type, body)) = replace(format, status, response.headers(), &message, id) else {
+        return Ok(ServiceResponse::new(req, response.set_body(BoxBody::new(message))));
+    };
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    // caches like GitHub's image proxy would keep showing the failure otherwise.
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    Ok(ServiceResponse::new(req, response.set_body(BoxBody::new(body))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn badge_summarizes_and_escapes() {
+        let svg = badge(StatusCode::BAD_REQUEST, "`progress` must be < 100 & \"finite\"");
+        assert!(svg.contains(">invalid params</text>"));
+        assert!(svg.contains("<title>`progress` must be &lt; 100 &amp; &quot;finite&quot;</title>"));
+        assert!(badge(StatusCode::SERVICE_UNAVAILABLE, "").contains(">unavailable</text>"));
+    }
+
+    #[actix_web::test]
+    async fn only_errors_are_replaced() {
+        use actix_web::{middleware::from_fn, test, App};
+        let text = |status: StatusCode, body: &'static str|
+            move || async move { HttpResponse::build(status).content_type("text/plain; charset=utf-8").body(body) };
+        let app = test::init_service(App::new()
+            .wrap(from_fn(render))
+            .route("/ok", web::get().to(text(StatusCode::OK, "fine")))
+            .route("/broken", web::get().to(text(StatusCode::INTERNAL_SERVER_ERROR, "broken template")))).await;
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/ok?error=svg").to_request()).await;
+        assert_eq!(test::read_body(response).await, "fine");
+        let response = test::call_service(&app, test::TestRequest::get().uri("/broken?error=svg").to_request()).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(content_type(response.headers()), "image/svg+xml; charset=utf-8");
+        assert!(String::from_utf8_lossy(&test::read_body(response).await).contains(">render error</text>"));
+    }
+}
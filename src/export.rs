@@ -0,0 +1,160 @@
+//! ZIP archives of rendered bars served at `/export.zip`, for embedding static copies into
+//! slide decks and reports. The entries are stored uncompressed, as PNGs are compressed
+//! already, and written one at a time so the archive is streamed while the bars render.
+use minijinja::value::Value;
+use progress_bar::ProgressBarRenderer;
+use crate::output::{self, Format};
+
+
+/// CRC-32 as used by ZIP, with the reflected polynomial `0xEDB88320`.
+fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 == 1 { 0xEDB8_8320 ^ (crc >> 1) } else { crc >> 1 };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+    !data.iter().fold(!0u32, |crc, b| TABLE[((crc ^ u32::from(*b)) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// Time and date of an entry in MS-DOS format, in UTC.
+fn dos_time(time: chrono::DateTime<chrono::Utc>) -> (u16, u16) {
+    use chrono::{Datelike, Timelike};
+    let clock = (time.hour() << 11) | (time.minute() << 5) | (time.second() / 2);
+    let date = (((time.year().clamp(1980, 2107) - 1980) as u32) << 9) | (time.month() << 5) | time.day();
+    (clock as u16, date as u16)
+}
+
+struct CentralEntry {
+    name: String,
+    crc: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes the parts of an archive in order: [`ZipWriter::entry`] per file, then
+/// [`ZipWriter::finish`]. The archive must stay below 4 GiB, there being no ZIP64 support.
+pub struct ZipWriter {
+    offset: u32,
+    time: (u16, u16),
+    entries: Vec<CentralEntry>,
+}
+
+impl ZipWriter {
+    pub fn new() -> Self {
+        ZipWriter { offset: 0, time: dos_time(chrono::Utc::now()), entries: Vec::new() }
+    }
+
+    /// The local header and data of the file `name`.
+    pub fn entry(&mut self, name: &str, data: &[u8]) -> Vec<u8> {
+        let entry = CentralEntry {
+            name: name.to_string(),
+            crc: crc32(data),
+            size: data.len() as u32,
+            offset: self.offset,
+        };
+        let mut out = Vec::with_capacity(30 + name.len() + data.len());
+        out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        out.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        out.extend_from_slice(&0x0800u16.to_le_bytes()); // names in UTF-8
+        out.extend_from_slice(&0u16.to_le_bytes()); // stored
+        out.extend_from_slice(&self.time.0.to_le_bytes());
+        out.extend_from_slice(&self.time.1.to_le_bytes());
+        out.extend_from_slice(&entry.crc.to_le_bytes());
+        out.extend_from_slice(&entry.size.to_le_bytes());
+        out.extend_from_slice(&entry.size.to_le_bytes());
+        out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes()); // extra field
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(data);
+        self.offset += out.len() as u32;
+        self.entries.push(entry);
+        out
+    }
+
+    /// The central directory, ending the archive.
+    pub fn finish(self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for entry in &self.entries {
+            out.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+            out.extend_from_slice(&20u16.to_le_bytes()); // version made by
+            out.extend_from_slice(&20u16.to_le_bytes()); // version needed
+            out.extend_from_slice(&0x0800u16.to_le_bytes());
+            out.extend_from_slice(&0u16.to_le_bytes());
+            out.extend_from_slice(&self.time.0.to_le_bytes());
+            out.extend_from_slice(&self.time.1.to_le_bytes());
+            out.extend_from_slice(&entry.crc.to_le_bytes());
+            out.extend_from_slice(&entry.size.to_le_bytes());
+            out.extend_from_slice(&entry.size.to_le_bytes());
+            out.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+            // extra field, comment, disk, internal and external attributes.
+            out.extend_from_slice(&[0; 12]);
+            out.extend_from_slice(&entry.offset.to_le_bytes());
+            out.extend_from_slice(entry.name.as_bytes());
+        }
+        let count = self.entries.len() as u16;
+        let directory_size = out.len() as u32;
+        out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+        out.extend_from_slice(&[0; 4]); // disk numbers
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&directory_size.to_le_bytes());
+        out.extend_from_slice(&self.offset.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes()); // comment
+        out
+    }
+}
+
+/// Extension of the entries of bars in `format`.
+pub fn extension(format: Format) -> &'static str {
+    match format {
+        Format::Svg => "svg",
+        Format::Png => "png",
+        Format::Json => "json",
+    }
+}
+
+/// The file of a bar with the template context `ctx`.
+pub fn render(renderer: &ProgressBarRenderer, ctx: &Value, format: Format) -> anyhow::Result<Vec<u8>> {
+    Ok(match format {
+        Format::Json => serde_json::to_vec_pretty(ctx)?,
+        Format::Svg => renderer.render_context(ctx)?.into_bytes(),
+        Format::Png => {
+            let density = ctx.get_attr("density").ok().and_then(|x| u32::try_from(x).ok()).unwrap_or(1);
+            output::rasterize(&renderer.render_context(ctx)?, density)?
+        },
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_the_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn archive_ends_with_the_central_directory() {
+        let mut zip = ZipWriter::new();
+        let first = zip.entry("a.svg", b"<svg/>");
+        let second = zip.entry("b.svg", b"");
+        let end = zip.finish();
+        assert_eq!(first.len(), 30 + 5 + 6);
+        assert_eq!(end.len(), 2 * (46 + 5) + 22);
+        let directory_offset = u32::from_le_bytes(end[end.len() - 6..end.len() - 2].try_into().unwrap());
+        assert_eq!(directory_offset as usize, first.len() + second.len());
+    }
+}
@@ -0,0 +1,111 @@
+//! Filters registered for templates besides the minijinja builtins like `round`, e.g.
+//! `{{ "%.1f%%" | format(value) }}` or `fill="{{ progress_color | contrast }}"`.
+use minijinja::value::{Rest, Value};
+use minijinja::{Environment, Error, ErrorKind};
+use crate::colors::parse_hex;
+
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidOperation, message)
+}
+
+/// Truncates a number, as the bar widths are integers.
+fn int(value: f32) -> i32 {
+    value as i32
+}
+
+fn clamp(value: f64, min: f64, max: f64) -> Result<f64, Error> {
+    if min > max {
+        return Err(invalid(format!("clamp minimum {min} exceeds the maximum {max}")));
+    }
+    Ok(value.clamp(min, max))
+}
+
+/// printf-style formatting of the arguments, supporting `%s`, `%d`, `%f`, `%.<n>f` and `%%`.
+fn format(template: &str, args: Rest<Value>) -> Result<String, Error> {
+    let mut out = String::with_capacity(template.len());
+    let mut args = args.iter();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut precision = None;
+        if chars.peek() == Some(&'.') {
+            chars.next();
+            let mut digits = String::new();
+            while let Some(d) = chars.peek().filter(|d| d.is_ascii_digit()) {
+                digits.push(*d);
+                chars.next();
+            }
+            precision = Some(digits.parse::<usize>().map_err(|_| invalid("missing precision after `%.`".into()))?);
+        }
+        let conversion = chars.next().ok_or_else(|| invalid("incomplete `%` at the end".into()))?;
+        if conversion == '%' {
+            out.push('%');
+            continue;
+        }
+        let arg = args.next().ok_or_else(|| invalid(format!("no argument left for `%{conversion}`")))?;
+        let number = || f64::try_from(arg.clone()).map_err(|_| invalid(format!("`{arg}` is not a number")));
+        match conversion {
+            's' => out.push_str(&arg.to_string()),
+            'd' => out.push_str(&(number()? as i64).to_string()),
+            'f' => out.push_str(&format!("{:.*}", precision.unwrap_or(6), number()?)),
+            _ => return Err(invalid(format!("unsupported conversion `%{conversion}`"))),
+        }
+    }
+    Ok(out)
+}
+
+/// The relative luminance of a hex color as defined by WCAG, from 0 for black to 1 for white.
+fn luminance(color: &str) -> Result<f64, Error> {
+    let rgb = parse_hex(color).ok_or_else(|| invalid(format!("`{color}` is not a #rgb or #rrggbb color")))?;
+    let [r, g, b] = rgb.map(|c| if c <= 0.03928 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) });
+    Ok(0.2126 * r + 0.7152 * g + 0.0722 * b)
+}
+
+/// A text color readable on the background `color`, white or dark gray.
+pub(crate) fn contrast(color: &str) -> Result<&'static str, Error> {
+    // the luminance where white and #333 text have the same contrast ratio.
+    Ok(if luminance(color)? > 0.245 { "#333" } else { "#fff" })
+}
+
+fn urlencode(value: &str) -> String {
+    value.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+pub fn register(env: &mut Environment<'_>) {
+    env.add_filter("int", int);
+    env.add_filter("clamp", clamp);
+    env.add_filter("format", format);
+    env.add_filter("luminance", luminance);
+    env.add_filter("contrast", contrast);
+    env.add_filter("urlencode", urlencode);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_supports_printf_conversions() {
+        let args = Rest(vec![Value::from(42.26), Value::from("x"), Value::from(7.9)]);
+        assert_eq!(format("%.1f%% %s %d", args).unwrap(), "42.3% x 7");
+        assert!(format("%s", Rest(vec![])).is_err());
+    }
+
+    #[test]
+    fn contrast_picks_readable_text() {
+        assert_eq!(contrast("#fff").unwrap(), "#333");
+        assert_eq!(contrast("#428bca").unwrap(), "#fff");
+        assert_eq!(contrast("#f0ad4e").unwrap(), "#333");
+        assert!(contrast("blue").is_err());
+    }
+}
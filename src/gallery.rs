@@ -0,0 +1,80 @@
+//! A fixed matrix of bars rendered into one sheet, served at `/selftest/gallery`, so the
+//! output of versions and custom templates can be compared before a rollout.
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use progress_bar::{BarSpec, ProgressBarRenderer};
+use crate::render_spec;
+
+
+/// Query strings of the rendered bars. Time dependent parameters are left out, so the
+/// output only changes with the template and the rendering code.
+const CASES: &[&str] = &[
+    "progress=0",
+    "progress=25",
+    "progress=50",
+    "progress=75",
+    "progress=100",
+    "progress=150&overflow=allow",
+    "progress=42&title=build",
+    "progress=42&title=a%20rather%20long%20title",
+    "value=73&min=50&max=90&suffix=%C2%B0C&title=temp",
+    "progress=3&scale=10&suffix=%2F10",
+    "progress=60&title=custom&title_color=%23333&progress_color=%23a0f",
+    "progress=60&progress_width=200&title_width=80&title=widths",
+];
+
+const ROW_HEIGHT: usize = 24;
+const CAPTION_WIDTH: usize = 480;
+
+#[derive(Serialize)]
+pub struct Case {
+    pub query: &'static str,
+    pub sha256: String,
+}
+
+#[derive(Serialize)]
+pub struct Gallery {
+    pub cases: Vec<Case>,
+    pub sheet_sha256: String,
+    #[serde(skip)]
+    pub sheet: String,
+}
+
+fn sha256(data: &str) -> String {
+    Sha256::digest(data.as_bytes()).iter()
+        .map(|x| format!("{x:02x}"))
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+pub fn render(renderer: &ProgressBarRenderer) -> anyhow::Result<Gallery> {
+    let mut rows = String::new();
+    let mut cases = Vec::with_capacity(CASES.len());
+    let mut width = 0;
+    for (i, query) in CASES.iter().enumerate() {
+        let args = actix_web::web::Query::<BarSpec>::from_query(query)?.into_inner();
+        let (ctx, svg) = render_spec(renderer, &args)?;
+        let bar_width = ctx.get_attr("title_width").ok().and_then(|x| i64::try_from(x).ok()).unwrap_or(0)
+            + ctx.get_attr("progress_width").ok().and_then(|x| i64::try_from(x).ok()).unwrap_or(0);
+        width = width.max(bar_width as usize);
+
+        let y = i * ROW_HEIGHT;
+        // nested documents must not repeat the XML declaration.
+        let bar = svg.find("<svg").map_or(svg.as_str(), |start| &svg[start..]);
+        rows.push_str(&format!(
+            "<text x=\"4\" y=\"{}\" font-family=\"DejaVu Sans Mono,monospace\" font-size=\"11\">{}</text>\n\
+             <svg x=\"{CAPTION_WIDTH}\" y=\"{y}\">{bar}</svg>\n",
+            y + 14, escape(query)));
+        cases.push(Case { query, sha256: sha256(&svg) });
+    }
+
+    let sheet = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <svg width=\"{}\" height=\"{}\" version=\"1.1\" xmlns=\"http://www.w3.org/2000/svg\" \
+         xmlns:xlink=\"http://www.w3.org/1999/xlink\">\n{rows}</svg>\n",
+        CAPTION_WIDTH + width, CASES.len() * ROW_HEIGHT);
+    Ok(Gallery { cases, sheet_sha256: sha256(&sheet), sheet })
+}
@@ -0,0 +1,91 @@
+//! Progress of GitHub milestones, served at `/github/{owner}/{repo}/milestone/{number}`.
+use std::time::Duration;
+use actix_web::http::StatusCode;
+use reqwest::Client;
+use serde::Deserialize;
+use crate::cache::TtlCache;
+use crate::config::GithubConfig;
+use crate::health::HealthRegistry;
+use crate::secrets::SecretStore;
+
+
+/// Name of the integration in the [`HealthRegistry`].
+pub const HEALTH_NAME: &str = "github";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Milestone {
+    pub title: String,
+    pub open_issues: u64,
+    pub closed_issues: u64,
+}
+
+impl Milestone {
+    /// Percentage of closed issues, 0 for milestones without issues.
+    pub fn percent(&self) -> f32 {
+        let total = self.open_issues + self.closed_issues;
+        if total == 0 {
+            0.0
+        } else {
+            100.0 * self.closed_issues as f32 / total as f32
+        }
+    }
+}
+
+pub struct Github {
+    config: GithubConfig,
+    milestones: TtlCache<(String, String, u64), Milestone>,
+}
+
+impl Github {
+    pub fn new(config: GithubConfig) -> Self {
+        let milestones = TtlCache::new(Duration::from_secs(config.cache_ttl));
+        Github { config, milestones }
+    }
+
+    /// Seconds fetched data is reused, also suitable as `max-age` of the rendered bars.
+    pub fn cache_ttl(&self) -> u64 {
+        self.milestones.ttl().as_secs()
+    }
+
+    pub async fn milestone(
+        &self,
+        client: &Client,
+        secrets: &SecretStore,
+        health: &HealthRegistry,
+        owner: &str,
+        repo: &str,
+        number: u64,
+    ) -> reqwest::Result<Milestone> {
+        let key = (owner.to_string(), repo.to_string(), number);
+        if let Some(x) = self.milestones.get(&key) {
+            return Ok(x);
+        }
+
+        let url = format!("{}/repos/{owner}/{repo}/milestones/{number}",
+                          self.config.api_url.trim_end_matches('/'));
+        let mut request = client.get(&url)
+            .header("Accept", "application/vnd.github+json")
+            .header("X-GitHub-Api-Version", "2022-11-28");
+        if let Some(token) = self.config.token.as_deref().and_then(|name| secrets.get(name)) {
+            request = request.bearer_auth(token);
+        }
+        let result = async {
+            request.send().await?
+                .error_for_status()?
+                .json::<Milestone>().await
+        }.await;
+        health.record(HEALTH_NAME, &url, result.as_ref().map(|_| ()));
+
+        let milestone = result?;
+        self.milestones.insert(key, milestone.clone());
+        Ok(milestone)
+    }
+}
+
+/// Status of the response when fetching from GitHub failed with `e`.
+pub fn error_status(e: &reqwest::Error) -> StatusCode {
+    match e.status() {
+        Some(reqwest::StatusCode::NOT_FOUND) => StatusCode::NOT_FOUND,
+        _ => StatusCode::BAD_GATEWAY,
+    }
+}
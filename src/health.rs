@@ -0,0 +1,68 @@
+//! Outcome of the latest fetch of every upstream integration, served at `/integrations/health`.
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Nothing was fetched yet.
+    Pending,
+    Ok,
+    Error,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Health {
+    pub status: Status,
+    /// What was fetched last.
+    pub url: Option<String>,
+    pub last_fetch: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+}
+
+impl Health {
+    fn pending() -> Self {
+        Health { status: Status::Pending, url: None, last_fetch: None, last_success: None, error: None }
+    }
+}
+
+pub struct HealthRegistry {
+    entries: Mutex<BTreeMap<String, Health>>,
+}
+
+impl HealthRegistry {
+    /// Lists `names` as pending until their first fetch.
+    pub fn new(names: impl IntoIterator<Item = String>) -> Self {
+        let entries = names.into_iter()
+            .map(|name| (name, Health::pending()))
+            .collect();
+        HealthRegistry { entries: Mutex::new(entries) }
+    }
+
+    pub fn record<E: ToString>(&self, name: &str, url: &str, result: Result<(), E>) {
+        let now = Utc::now();
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(name.to_string()).or_insert_with(Health::pending);
+        entry.url = Some(url.to_string());
+        entry.last_fetch = Some(now);
+        match result {
+            Ok(()) => {
+                entry.status = Status::Ok;
+                entry.last_success = Some(now);
+                entry.error = None;
+            },
+            Err(e) => {
+                entry.status = Status::Error;
+                entry.error = Some(e.to_string());
+            },
+        }
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, Health> {
+        self.entries.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,153 @@
+//! Webhooks setting stored bars, for CI systems which can only send generic webhooks, e.g.
+//!
+//! ```toml
+//! [secrets]
+//! coverage-hook = { env = "COVERAGE_HOOK_SECRET" }
+//! [hooks.coverage]
+//! secret = "coverage-hook"
+//! value_path = "$.coverage.percent"
+//! ```
+//!
+//! sets bar `coverage` from the payloads posted to `/hooks/coverage`, which are signed like
+//! the webhooks of GitHub: `X-Hub-Signature-256: sha256=<hex HMAC-SHA256 of the body>`.
+use std::collections::HashMap;
+use std::fmt;
+use actix_web::http::StatusCode;
+use actix_web::HttpRequest;
+use anyhow::bail;
+use serde_json_path::JsonPath;
+use sha2::{Digest, Sha256};
+use crate::auth;
+use crate::config::HookConfig;
+use crate::secrets::SecretStore;
+use crate::sources::{self, SourceError};
+
+
+/// Header of the signature unless configured otherwise.
+const SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
+
+/// Block size of SHA-256 in bytes.
+const BLOCK_SIZE: usize = 64;
+
+#[derive(Debug)]
+pub enum HookError {
+    UnknownSecret(String),
+    MissingSignature(String),
+    BadSignature,
+    InvalidPayload(String),
+    Value(SourceError),
+}
+
+impl fmt::Display for HookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookError::UnknownSecret(name) => write!(f, "unknown secret `{name}`"),
+            HookError::MissingSignature(header) => write!(f, "the `{header}` signature is missing"),
+            HookError::BadSignature => write!(f, "the signature does not match the payload"),
+            HookError::InvalidPayload(e) => write!(f, "the payload is no JSON document: {e}"),
+            HookError::Value(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for HookError {}
+
+impl HookError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            HookError::UnknownSecret(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            HookError::MissingSignature(_) | HookError::BadSignature => StatusCode::UNAUTHORIZED,
+            HookError::InvalidPayload(_) | HookError::Value(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// The HMAC-SHA256 of `message` with `key`, as in RFC 2104.
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let pad = |x: u8| block.map(|b| b ^ x);
+    let inner = Sha256::new().chain_update(pad(0x36)).chain_update(message).finalize();
+    Sha256::new().chain_update(pad(0x5c)).chain_update(inner).finalize().into()
+}
+
+/// Whether `signature`, hex digits optionally prefixed by `sha256=`, is the HMAC of `body` with `secret`.
+fn is_signed(secret: &str, body: &[u8], signature: &str) -> bool {
+    let signature = signature.trim();
+    let signature = signature.strip_prefix("sha256=").unwrap_or(signature).to_ascii_lowercase();
+    let expected: String = hmac_sha256(secret.as_bytes(), body).iter().map(|x| format!("{x:02x}")).collect();
+    auth::constant_time_eq(signature.as_bytes(), expected.as_bytes())
+}
+
+/// The webhooks keyed by the ids of the bars they set.
+#[derive(Default)]
+pub struct Hooks(HashMap<String, HookConfig>);
+
+impl Hooks {
+    /// Checks that the secrets of `hooks` exist and their value paths are valid.
+    pub fn new(hooks: HashMap<String, HookConfig>, secrets: &SecretStore) -> anyhow::Result<Self> {
+        for (id, hook) in &hooks {
+            if id.is_empty() || !id.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+                bail!("`{id}` is no valid bar id for a hook");
+            }
+            if secrets.get(&hook.secret).is_none() {
+                bail!("unknown secret `{}` for the hook of bar {id}", hook.secret);
+            }
+            if let Err(e) = JsonPath::parse(&hook.value_path) {
+                bail!("invalid `value_path` for the hook of bar {id}: {e}");
+            }
+        }
+        Ok(Hooks(hooks))
+    }
+
+    pub fn get(&self, id: &str) -> Option<&HookConfig> {
+        self.0.get(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+/// The value of the payload `body` of `req`, once its signature is verified.
+pub fn value(hook: &HookConfig, secrets: &SecretStore, req: &HttpRequest, body: &[u8]) -> Result<f32, HookError> {
+    let secret = secrets.get(&hook.secret).ok_or_else(|| HookError::UnknownSecret(hook.secret.clone()))?;
+    let header = hook.signature_header.as_deref().unwrap_or(SIGNATURE_HEADER);
+    let signature = req.headers().get(header)
+        .and_then(|x| x.to_str().ok())
+        .ok_or_else(|| HookError::MissingSignature(header.to_string()))?;
+    if !is_signed(&secret, body, signature) {
+        return Err(HookError::BadSignature);
+    }
+    let document = serde_json::from_slice(body).map_err(|e| HookError::InvalidPayload(e.to_string()))?;
+    let value = sources::extract_value(&document, &hook.value_path).map_err(HookError::Value)?;
+    if !value.is_finite() {
+        return Err(HookError::Value(SourceError::NotANumber(value.to_string())));
+    }
+    Ok(value)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_are_hmac_sha256_of_the_body() {
+        let hex = |x: [u8; 32]| x.iter().map(|x| format!("{x:02x}")).collect::<String>();
+        // RFC 4231, test cases 2 and 6.
+        assert_eq!(hex(hmac_sha256(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+        assert_eq!(hex(hmac_sha256(&[0xaa; 131], b"Test Using Larger Than Block-Size Key - Hash Key First")),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+        // the example of the GitHub documentation.
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(is_signed("It's a Secret to Everybody", b"Hello, World!", signature));
+        assert!(!is_signed("It's a Secret to Everybody", b"Hello, World?", signature));
+        assert!(!is_signed("It's a Secret to Everybody", b"Hello, World!", "sha256="));
+    }
+}
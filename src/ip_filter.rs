@@ -0,0 +1,74 @@
+//! `--allow-cidr` and `--deny-cidr`, rejecting the requests of clients outside of the allowed
+//! networks or inside of the denied ones before any route runs, e.g. for internal-only
+//! deployments. Denied networks take precedence. Requests without a client address, like
+//! those over a Unix socket, are let through.
+use std::net::IpAddr;
+use actix_web::{body::{BoxBody, MessageBody}, dev::{ServiceRequest, ServiceResponse}, web, HttpResponse};
+use actix_web::middleware::Next;
+use log::warn;
+use crate::client_ip::{self, Cidr};
+use crate::stats::UsageStats;
+
+
+#[derive(Default)]
+pub struct IpFilter {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl IpFilter {
+    /// Allows the clients in `allow`, or any client if it is empty, unless they are in `deny`.
+    pub fn new(allow: Vec<Cidr>, deny: Vec<Cidr>) -> Self {
+        IpFilter { allow, deny }
+    }
+
+    /// The rule rejecting `ip`, if any, e.g. `deny 10.0.0.0/8`.
+    fn rejecting(&self, ip: IpAddr) -> Option<String> {
+        if let Some(network) = self.deny.iter().find(|x| x.contains(ip)) {
+            return Some(format!("deny {network}"));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|x| x.contains(ip)) {
+            return Some("not allowed".to_string());
+        }
+        None
+    }
+}
+
+/// Middleware rejecting the clients of the [`IpFilter`] found in the app data with 403, which
+/// has to run inside of [`client_ip::assign`]. Rejections are counted in the [`UsageStats`].
+pub async fn enforce(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let rejected = req.app_data::<web::Data<IpFilter>>()
+        .zip(client_ip::get(req.request()))
+        .and_then(|(filter, ip)| filter.rejecting(ip).map(|rule| (ip, rule)));
+    let Some((ip, rule)) = rejected else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    warn!("Denied {} {} to {} ({}).", req.method(), req.uri(), ip, rule);
+    if let Some(stats) = req.app_data::<web::Data<UsageStats>>() {
+        stats.record_denied(rule);
+    }
+    Ok(req.into_response(HttpResponse::Forbidden()
+        .content_type("text/plain; charset=utf-8")
+        .body("Requests from this address are not allowed")))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deny_takes_precedence_over_allow() {
+        let cidr = |x: &str| x.parse::<Cidr>().unwrap();
+        let filter = IpFilter::new(vec![cidr("10.0.0.0/8"), cidr("::1")], vec![cidr("10.1.0.0/16")]);
+        let ip = |x: &str| x.parse::<IpAddr>().unwrap();
+        assert_eq!(filter.rejecting(ip("10.2.3.4")), None);
+        assert_eq!(filter.rejecting(ip("::1")), None);
+        assert_eq!(filter.rejecting(ip("10.1.3.4")).as_deref(), Some("deny 10.1.0.0/16"));
+        assert_eq!(filter.rejecting(ip("192.168.0.1")).as_deref(), Some("not allowed"));
+        assert_eq!(IpFilter::default().rejecting(ip("192.168.0.1")), None);
+    }
+}
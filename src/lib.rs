@@ -0,0 +1,29 @@
+//! Rendering of the progress bars served by the `progress-bar` binary, usable on its own to
+//! embed the bars in other services. A [`BarSpec`] holds the same fields as the query of
+//! `/render`, and a [`ProgressBarRenderer`] turns it into an SVG. [`ProgressBar::builder`]
+//! fills a spec with typed options and renders it with the default template.
+mod builder;
+mod check;
+pub mod color_script;
+mod colors;
+mod context;
+mod defaults;
+mod filters;
+pub mod logos;
+mod numbers;
+pub mod postprocess;
+mod render;
+mod spec;
+pub mod timespan;
+pub mod transforms;
+mod trend;
+
+pub use builder::{ProgressBar, ProgressBarBuilder, Theme};
+pub use check::{check_template, check_template_with, Problem};
+pub use colors::check_color_stops;
+pub use context::{build_context, pixel_size, progress_color, resolve_value, BAR_HEIGHT};
+pub use defaults::BarDefaults;
+pub use numbers::decimal_separator;
+pub use render::{is_partial_name, is_template_name, ProgressBarRenderer, RenderError, RenderLimits, RendererOptions, TemplateError, DEFAULT_NAME, DEFAULT_TEMPLATE, MACROS, MACROS_NAME};
+pub use spec::{check_link, is_color, BarSpec, ColorMode, ColorSpace, Component, Direction, Format, LabelPosition, Mode, OverflowPolicy, Palette, Preset, SpecError, State, Units, MAX_DENSITY, MAX_WIDTH};
+pub use trend::{CHART_WIDTH, TREND_TEMPLATE};
@@ -0,0 +1,140 @@
+//! Per-route concurrency caps and timeouts, and the slots rendering the bars.
+//!
+//! Routes are identified by their resource name, e.g. `#[get("/render", name = "render")]`.
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, error, rt, web, HttpRequest};
+use actix_web::middleware::Next;
+use log::warn;
+use progress_bar::RenderError;
+use tokio::sync::Semaphore;
+use crate::config::RouteConfig;
+
+
+pub struct RouteLimit {
+    permits: Option<Semaphore>,
+    timeout: Option<Duration>,
+}
+
+#[derive(Default)]
+pub struct RouteLimits(HashMap<String, Arc<RouteLimit>>);
+
+impl RouteLimits {
+    pub fn new(routes: &HashMap<String, RouteConfig>, known: &[&str]) -> anyhow::Result<Self> {
+        let mut limits = HashMap::new();
+        for (name, config) in routes {
+            if !known.contains(&name.as_str()) {
+                warn!("Ignoring limits of unknown route `{}`. Known routes: {}.", name, known.join(", "));
+                continue;
+            }
+            let timeout = match config.timeout {
+                Some(secs) => Some(Duration::try_from_secs_f64(secs).map_err(|e|
+                    anyhow::anyhow!("invalid timeout {} for route `{}`: {}", secs, name, e))?),
+                None => None,
+            };
+            if config.concurrency == Some(0) {
+                anyhow::bail!("the concurrency of route `{}` must be at least 1", name);
+            }
+            let limit = RouteLimit {
+                permits: config.concurrency.map(Semaphore::new),
+                timeout,
+            };
+            limits.insert(name.clone(), Arc::new(limit));
+        }
+        Ok(RouteLimits(limits))
+    }
+}
+
+/// Middleware applying the [`RouteLimits`] found in the app data to the matched route.
+pub async fn enforce(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let limit = req.app_data::<web::Data<RouteLimits>>()
+        .zip(req.match_name())
+        .and_then(|(limits, name)| limits.0.get(name))
+        .cloned();
+    let Some(limit) = limit else {
+        return next.call(req).await;
+    };
+
+    let work = async {
+        let _permit = match &limit.permits {
+            Some(permits) => Some(permits.acquire().await.map_err(error::ErrorInternalServerError)?),
+            None => None,
+        };
+        next.call(req).await
+    };
+    match limit.timeout {
+        Some(timeout) => rt::time::timeout(timeout, work).await
+            .unwrap_or_else(|_| Err(error::ErrorServiceUnavailable(
+                format!("Request timed out after {:?}.", timeout)))),
+        None => work.await,
+    }
+}
+
+/// Runs the renders on the blocking threads, keeping the workers free, at most one per CPU at
+/// once. A render taking longer than the timeout, the wait for its slot included, fails with
+/// [`RenderError::Timeout`], while it keeps its slot until its fuel runs out.
+pub struct RenderSlots {
+    permits: Arc<Semaphore>,
+    timeout: Option<Duration>,
+}
+
+impl RenderSlots {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        let slots = std::thread::available_parallelism().map_or(1, usize::from);
+        RenderSlots { permits: Arc::new(Semaphore::new(slots)), timeout }
+    }
+
+    pub async fn run<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> Result<T, RenderError> {
+        let failed = |e: String| RenderError::Template(minijinja::Error::new(
+            minijinja::ErrorKind::InvalidOperation, format!("failed to start rendering: {e}")));
+        let work = async {
+            let permit = self.permits.clone().acquire_owned().await.map_err(|e| failed(e.to_string()))?;
+            web::block(move || {
+                let _permit = permit;
+                job()
+            }).await.map_err(|e| failed(e.to_string()))
+        };
+        match self.timeout {
+            Some(timeout) => rt::time::timeout(timeout, work).await.unwrap_or(Err(RenderError::Timeout(timeout))),
+            None => work.await,
+        }
+    }
+}
+
+/// Runs `job` in the [`RenderSlots`] of the app of `req`, or right away if it has none.
+pub async fn render<T: Send + 'static>(
+    req: &HttpRequest,
+    job: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, RenderError> {
+    match req.app_data::<web::Data<RenderSlots>>() {
+        Some(slots) => slots.run(job).await,
+        None => Ok(job()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_admit_at_least_one_request() {
+        let routes = |concurrency| HashMap::from([
+            ("render".to_string(), RouteConfig { concurrency: Some(concurrency), timeout: None }),
+        ]);
+        assert!(RouteLimits::new(&routes(0), &["render"]).is_err());
+        assert_eq!(RouteLimits::new(&routes(1), &["render"]).unwrap().0.len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn slow_renders_time_out() {
+        let slots = RenderSlots::new(Some(Duration::from_millis(50)));
+        assert_eq!(slots.run(|| 42).await.unwrap(), 42);
+        let slow = slots.run(|| std::thread::sleep(Duration::from_millis(500))).await;
+        assert!(matches!(slow, Err(RenderError::Timeout(_))));
+    }
+}
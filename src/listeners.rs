@@ -0,0 +1,125 @@
+//! The sockets the server listens on: those passed by systemd socket activation
+//! (`LISTEN_FDS`) if any, otherwise the Unix domain socket given with `--unix-socket`, or
+//! every `--ip` on `--port`, the latter two queueing up to `--backlog` pending connections.
+use std::fmt;
+use std::io;
+use std::net::{TcpListener, ToSocketAddrs};
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use anyhow::Context;
+use socket2::{Domain, Protocol, SockAddr, Socket, Type};
+
+
+/// Where to listen when systemd passes no sockets.
+pub enum BindSource {
+    /// Addresses or host names, IPv6 ones with or without brackets, sharing a port.
+    Tcp(Vec<String>, u16),
+    Unix(PathBuf),
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Tcp(x) => match x.local_addr() {
+                Ok(addr) => write!(f, "{addr}"),
+                Err(_) => write!(f, "<TCP socket>"),
+            },
+            Listener::Unix(x) => match x.local_addr().ok().as_ref().and_then(|x| x.as_pathname()) {
+                Some(path) => write!(f, "unix:{}", path.display()),
+                None => write!(f, "<Unix socket>"),
+            },
+        }
+    }
+}
+
+/// The listening sockets passed by systemd, TCP or Unix domain ones.
+fn inherited() -> anyhow::Result<Vec<Listener>> {
+    let fds = sd_notify::listen_fds().context("invalid socket activation environment")?;
+    fds.map(|fd| {
+        // SAFETY: systemd passes the descriptors to this process only, which takes them once.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let tcp = TcpListener::from(fd);
+        // only internet sockets have an address std understands.
+        let listener = if tcp.local_addr().is_ok() {
+            Listener::Tcp(tcp)
+        } else {
+            // SAFETY: the descriptor is moved out of the listener it was wrapped in.
+            let unix = unsafe { UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+            unix.local_addr().context("an inherited socket is neither TCP nor a Unix domain socket")?;
+            Listener::Unix(unix)
+        };
+        Ok(listener)
+    }).collect()
+}
+
+fn bind_tcp(ip: &str, port: u16, backlog: i32) -> io::Result<TcpListener> {
+    let addr = (ip, port).to_socket_addrs()?.next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the address resolves to nothing"))?;
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    // `::` would take the IPv4 port as well, which `0.0.0.0` may be given for.
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(backlog)?;
+    Ok(socket.into())
+}
+
+fn bind_unix(path: &Path, backlog: i32) -> io::Result<UnixListener> {
+    let socket = Socket::new(Domain::UNIX, Type::STREAM, None)?;
+    socket.bind(&SockAddr::unix(path)?)?;
+    socket.listen(backlog)?;
+    Ok(socket.into())
+}
+
+/// The sockets passed by systemd, whose backlog it sets, or else those of `fallback`.
+pub fn open(fallback: BindSource, backlog: u32) -> anyhow::Result<Vec<Listener>> {
+    let backlog = i32::try_from(backlog).unwrap_or(i32::MAX);
+    let listeners = inherited()?;
+    if !listeners.is_empty() {
+        return Ok(listeners);
+    }
+    match fallback {
+        BindSource::Tcp(ips, port) => ips.iter()
+            .map(|ip| {
+                let host = ip.strip_prefix('[').and_then(|x| x.strip_suffix(']')).unwrap_or(ip);
+                let shown = if host.contains(':') { format!("[{host}]:{port}") } else { format!("{host}:{port}") };
+                bind_tcp(host, port, backlog).map(Listener::Tcp).with_context(|| format!("failed to bind {shown}"))
+            })
+            .collect(),
+        BindSource::Unix(path) => {
+            // a socket left behind by a previous run would make binding fail.
+            if std::fs::metadata(&path).is_ok_and(|x| x.file_type().is_socket()) {
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("failed to remove the stale socket {}", path.display()))?;
+            }
+            let listener = bind_unix(&path, backlog)
+                .with_context(|| format!("failed to bind {}", path.display()))?;
+            Ok(vec![Listener::Unix(listener)])
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_address_is_bound() {
+        let ips = vec!["127.0.0.1".to_string(), "[::1]".to_string()];
+        let listeners = open(BindSource::Tcp(ips, 0), 16).unwrap();
+        let addrs: Vec<_> = listeners.iter()
+            .map(|x| x.to_string().rsplit_once(':').unwrap().0.to_string())
+            .collect();
+        assert_eq!(addrs, ["127.0.0.1", "[::1]"]);
+    }
+}
@@ -0,0 +1,37 @@
+//! A page showing a stored bar, served at `/bars/{id}/live`, which swaps in the new SVG
+//! whenever the bar is updated. The updates are pushed as Server-Sent Events from
+//! `/bars/{id}/events`.
+use std::time::Duration;
+
+
+const PAGE: &str = include_str!("../resources/live.html");
+
+/// How often a comment is sent while nothing changes, keeping proxies from closing the stream.
+pub const KEEPALIVE: Duration = Duration::from_secs(15);
+
+/// The page of bar `id`, which must be a valid id, so it needs no escaping.
+pub fn page(id: &str) -> String {
+    PAGE.replace("{{ id }}", id)
+        .replace("{{ events_url }}", &format!("/bars/{id}/events"))
+}
+
+/// Formats an event, prefixing every line of `data`.
+pub fn event(name: &str, data: &str) -> String {
+    let mut event = format!("event: {name}\n");
+    for line in data.lines() {
+        event.push_str("data: ");
+        event.push_str(line);
+        event.push('\n');
+    }
+    if data.is_empty() {
+        event.push_str("data:\n");
+    }
+    event.push('\n');
+    event
+}
+
+/// The SVG of an update, without the XML declaration, which is invalid inside HTML.
+pub fn update(svg: &str) -> String {
+    let svg = svg.find("<svg").map_or(svg, |start| &svg[start..]);
+    event("update", svg)
+}
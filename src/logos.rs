@@ -0,0 +1,116 @@
+//! Icons drawn left of the title, either one of the bundled [`NAMES`] given as `?logo=github`
+//! or an image of its own given as `?logo_data=data:image/svg+xml;base64,...`.
+use std::fmt;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+
+/// Logo images larger than this are rejected, as every bar would carry them.
+pub const MAX_LOGO_BYTES: usize = 8 * 1024;
+
+const BUNDLED: &[(&str, &str)] = &[
+    ("check", include_str!("../resources/logos/check.svg")),
+    ("clock", include_str!("../resources/logos/clock.svg")),
+    ("cross", include_str!("../resources/logos/cross.svg")),
+    ("download", include_str!("../resources/logos/download.svg")),
+    ("github", include_str!("../resources/logos/github.svg")),
+    ("heart", include_str!("../resources/logos/heart.svg")),
+    ("info", include_str!("../resources/logos/info.svg")),
+    ("star", include_str!("../resources/logos/star.svg")),
+    ("warning", include_str!("../resources/logos/warning.svg")),
+];
+
+/// Names of the bundled logos.
+pub const NAMES: [&str; BUNDLED.len()] = {
+    let mut names = [""; BUNDLED.len()];
+    let mut i = 0;
+    while i < BUNDLED.len() {
+        names[i] = BUNDLED[i].0;
+        i += 1;
+    }
+    names
+};
+
+#[derive(Debug)]
+pub enum LogoError {
+    Unknown(String),
+    UnsupportedType,
+    InvalidBase64,
+    TooLarge(usize),
+    NotAnImage(&'static str),
+}
+
+impl fmt::Display for LogoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogoError::Unknown(name) =>
+                write!(f, "there is no logo `{name}`, known are {}", NAMES.join(", ")),
+            LogoError::UnsupportedType =>
+                write!(f, "logo data must be a data:image/svg+xml;base64 or data:image/png;base64 URI"),
+            LogoError::InvalidBase64 => write!(f, "the logo data is not valid base64"),
+            LogoError::TooLarge(size) =>
+                write!(f, "the logo has {size} bytes, at most {MAX_LOGO_BYTES} are allowed"),
+            LogoError::NotAnImage(mime) => write!(f, "the logo data is no {mime} image"),
+        }
+    }
+}
+
+impl std::error::Error for LogoError {}
+
+/// The data URI of the bundled logo `name`.
+pub fn named(name: &str) -> Result<String, LogoError> {
+    let (_, svg) = BUNDLED.iter()
+        .find(|(x, _)| x.eq_ignore_ascii_case(name))
+        .ok_or_else(|| LogoError::Unknown(name.to_string()))?;
+    Ok(format!("data:image/svg+xml;base64,{}", STANDARD.encode(svg)))
+}
+
+/// Checks that `uri` is a base64 data URI of a small SVG or PNG image.
+pub fn check_data(uri: &str) -> Result<(), LogoError> {
+    let (mime, data) = ["image/svg+xml", "image/png"].iter()
+        .find_map(|mime| uri.strip_prefix("data:")?.strip_prefix(mime)?.strip_prefix(";base64,").map(|x| (*mime, x)))
+        .ok_or(LogoError::UnsupportedType)?;
+    // base64 grows the data by a third, so larger URIs need not be decoded.
+    if data.len() > MAX_LOGO_BYTES / 3 * 4 + 4 {
+        return Err(LogoError::TooLarge(data.len() / 4 * 3));
+    }
+    let bytes = STANDARD.decode(data).map_err(|_| LogoError::InvalidBase64)?;
+    if bytes.len() > MAX_LOGO_BYTES {
+        return Err(LogoError::TooLarge(bytes.len()));
+    }
+    let is_image = match mime {
+        "image/png" => bytes.starts_with(b"\x89PNG\r\n\x1a\n"),
+        _ => std::str::from_utf8(&bytes).is_ok_and(|x| x.contains("<svg")),
+    };
+    if !is_image {
+        return Err(LogoError::NotAnImage(mime));
+    }
+    Ok(())
+}
+
+/// The data URI of the logo of a bar, `logo_data` taking precedence over `logo`.
+pub fn resolve(logo: Option<&str>, logo_data: Option<&str>) -> Result<Option<String>, LogoError> {
+    match (logo_data, logo) {
+        (Some(uri), _) => check_data(uri).map(|()| Some(uri.to_string())),
+        (None, Some(name)) => named(name).map(Some),
+        (None, None) => Ok(None),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_uris_are_checked() {
+        let svg = named("github").unwrap();
+        assert!(check_data(&svg).is_ok());
+        assert!(matches!(named("nope"), Err(LogoError::Unknown(_))));
+        assert!(matches!(check_data("data:text/html;base64,PGI+"), Err(LogoError::UnsupportedType)));
+        assert!(matches!(check_data("data:image/png;base64,PHN2Zz4="), Err(LogoError::NotAnImage(_))));
+        assert!(matches!(check_data("data:image/svg+xml;base64,!!"), Err(LogoError::InvalidBase64)));
+        let large = format!("data:image/svg+xml;base64,{}", STANDARD.encode("<svg>".repeat(2000)));
+        assert!(matches!(check_data(&large), Err(LogoError::TooLarge(_))));
+    }
+}
@@ -1,189 +1,2005 @@
 use std::borrow::Cow;
-use std::fs::read_to_string;
 use std::path::PathBuf;
-use minijinja::{self, Environment, Source};
-use actix_web::{get, web, App, HttpServer, Responder, HttpResponse, http, HttpRequest};
-use serde::{Deserialize, Serialize};
-use serde_json;
+use std::str::FromStr;
+use actix_web::{delete, get, post, put, web, App, HttpServer, Responder, HttpResponse, http, HttpRequest};
+use actix_web::http::KeepAlive;
+use actix_web::middleware::{from_fn, Condition};
+use serde::Deserialize;
 use serde_json::json;
-use clap::{Parser};
-use anyhow;
-use log::{debug, error, info};
-use env_logger::{self, Env};
+use tokio::sync::broadcast;
+use clap::{Parser, Subcommand};
+use log::{debug, error, info, warn};
 
+mod auth;
+mod bars;
+mod access_log;
+mod badges;
+mod batch;
+mod cache;
+mod chaos;
+mod client_ip;
+mod compose;
+mod compression;
+mod config;
+mod dry_run;
+mod error_badge;
+mod export;
+mod gallery;
+mod github;
+mod health;
+mod hooks;
+mod ip_filter;
+mod limits;
+mod mqtt;
+mod listeners;
+mod live;
+mod offline;
+mod openapi;
+mod output;
+mod packages;
+mod query;
+mod referrers;
+mod request_id;
+mod secrets;
+mod shields;
+mod shutdown;
+mod sources;
+mod stats;
+mod statsd;
+mod systemd;
+mod telemetry;
+mod templates;
+mod upstream;
+mod views;
 
-const TEMPLATE_NAME: &'static str = "pbar_template";
+use access_log::{ContextHash, LogFormat};
+use badges::Badges;
+use bars::BarStore;
+use client_ip::{Cidr, ForwardedHeader, TrustedProxies};
+use compose::Composer;
+use config::{AdminConfig, BarsConfig, Config, SourcesConfig, StatsConfig};
+use github::Github;
+use health::HealthRegistry;
+use hooks::Hooks;
+use ip_filter::IpFilter;
+use limits::{RenderSlots, RouteLimits};
+use listeners::{BindSource, Listener};
+use output::{Body, Format, Rendered};
+use packages::{Packages, Period, Registry};
+use progress_bar::{BarSpec, ProgressBarRenderer, RenderError, RenderLimits, RendererOptions, TemplateError};
+use query::{QueryConfig, SpecQuery};
+use referrers::ReferrerRules;
+use secrets::SecretStore;
+use stats::UsageStats;
+use telemetry::Tracer;
+use templates::TemplateSources;
+use views::ViewCounter;
+
+/// Names of the routes which can be tuned in the `[routes.<name>]` config sections.
+const ROUTES: &[&str] = &[
+    "render", "context", "integrations_health", "github_milestone", "shields", "posted_shields", "selftest_gallery",
+    "bar", "snapshot", "crate_downloads", "npm_downloads", "batch", "export", "badge", "badge_stats", "bar_stats", "live_bar", "bar_events",
+    "bar_trend", "put_bar", "delete_bar", "create_snapshot", "stats", "admin_templates", "admin_template", "admin_reload", "path_bar",
+    "titled_path_bar", "hook", "openapi", "docs",
+];
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
-    /// Sets a custom config file
-    #[arg(short='f', long)]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Sets a custom template file
+    #[arg(short='f', long, global = true)]
     template_file: Option<PathBuf>,
 
-    #[clap(short, long, value_parser, default_value="127.0.0.1")]
-    /// Bind address.
-    ip: String,
+    /// Sets the TOML config file
+    #[arg(short, long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Comma separated proxies, e.g. 127.0.0.1,10.0.0.0/8, whose `--forwarded-header` names
+    /// the client
+    #[arg(long, value_delimiter = ',', value_parser = Cidr::from_str)]
+    trusted_proxies: Vec<Cidr>,
+
+    /// The header the `--trusted-proxies` name the client in, the other one being ignored
+    #[arg(long, value_enum, default_value_t)]
+    forwarded_header: ForwardedHeader,
+
+    /// Addresses or networks, e.g. 10.0.0.0/8, which may send requests. Repeat it or separate
+    /// them with commas. Anybody may unless it is given.
+    #[arg(long, value_delimiter = ',', value_parser = Cidr::from_str)]
+    allow_cidr: Vec<Cidr>,
+
+    /// Addresses or networks whose requests are rejected, even if allowed by `--allow-cidr`.
+    #[arg(long, value_delimiter = ',', value_parser = Cidr::from_str)]
+    deny_cidr: Vec<Cidr>,
+
+    /// OTLP/HTTP collector the spans of the requests are exported to, e.g. http://localhost:4318
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+
+    /// Format of the log lines, `json` adding a structured record per request
+    #[arg(long, value_enum, default_value_t = LogFormat::Human, global = true)]
+    log_format: LogFormat,
+
+    #[clap(short, long, value_parser, value_delimiter = ',', default_value="127.0.0.1")]
+    /// Bind addresses, repeated or separated by commas, e.g. `127.0.0.1,[::1]` for both IPv4
+    /// and IPv6 clients.
+    ip: Vec<String>,
 
     #[clap(short, long, value_parser=clap::value_parser!(u16).range(1..), default_value_t=5005)]
     /// The port to listen on.
     port: u16,
 
-    #[clap(short, long, value_parser=clap::value_parser!(u16).range(1..), default_value_t=1)]
-    /// The port to listen on.
+    /// Unix domain socket to listen on instead of the address and port.
+    #[arg(long)]
+    unix_socket: Option<PathBuf>,
+
+    #[clap(short, long, value_parser, default_value_t=1)]
+    /// Worker threads serving requests, 0 for one per logical CPU.
     workers: u16,
+
+    /// Seconds idle connections are kept open for further requests, 0 closing them after one.
+    #[arg(long, default_value_t = 5)]
+    keep_alive: u64,
+
+    /// Connections waiting to be accepted before further ones are refused.
+    #[arg(long, default_value_t = 2048)]
+    backlog: u32,
+
+    /// Connections each worker serves at once, further ones waiting in the backlog.
+    #[arg(long, default_value_t = 25_000)]
+    max_connections: usize,
+
+    /// Rejects rendered bars larger than this many bytes, guarding against runaway templates.
+    #[arg(long, global = true)]
+    max_body_bytes: Option<usize>,
+
+    /// Rejects query parameters the route does not know, instead of ignoring them.
+    #[arg(long)]
+    strict: bool,
+
+    /// Compresses SVG, JSON and text responses with gzip or brotli, as the client accepts.
+    #[arg(long)]
+    compression: bool,
+
+    /// Serves Swagger UI at /docs, showing /openapi.json. The page loads it from unpkg.com.
+    #[arg(long)]
+    docs: bool,
+
+    /// Seconds the requests in flight may take to finish after SIGTERM or SIGINT.
+    #[arg(long, default_value_t = 30)]
+    shutdown_timeout: u64,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Renders one bar to a file or stdout instead of starting the server.
+    Render(Box<offline::RenderArgs>),
+    /// Checks the template given with `-f` against representative bars.
+    CheckTemplate,
 }
 
 #[actix_web::main]
 async fn main() -> anyhow::Result<()> {
-    // env_logger::init();
-    env_logger::Builder::from_env(Env::default()
-        .default_filter_or(concat!(module_path!(), "=info")))
-        .init();
-
     let cli = Cli::parse();
+    access_log::init_logger(cli.log_format);
 
-    let mut env = Environment::new();
-    match &cli.template_file {
-        Some(file) => {
-            let mut source = Source::new();
-            source.add_template(TEMPLATE_NAME, read_to_string(file)?)?;
-            env.set_source(source);
-        },
-        None => {
-            let template = include_str!("../resources/default.svg");
-            env.add_template(TEMPLATE_NAME, template)?;
-        },
+    let config = match &cli.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
     };
-    env.add_filter("int", |x: f32| x as i32);
 
-    info!("{} {} at {}:{}.",
-        cli.workers, if cli.workers > 1 { "workers serve" } else { "worker serves" },
-        cli.ip, cli.port);
+    let template_sources = TemplateSources { file: cli.template_file.clone(), dir: config.templates.clone() };
+    if let Some(Command::CheckTemplate) = cli.command {
+        return offline::check_template(&template_sources, &config.globals, config.render.limits()?);
+    }
+    // the server times the renders itself, without holding up its workers.
+    let render_limits = config.render.limits()?;
+    let templates = template_sources.read()?;
+    let renderer = ProgressBarRenderer::new(RendererOptions {
+        template: templates.default,
+        named_templates: templates.named,
+        partials: templates.partials,
+        transforms: config.transforms,
+        post_processors: config.postprocess,
+        globals: config.globals,
+        color_fn: config.color_fn,
+        defaults: Config::env_defaults()?.or(config.defaults),
+        max_body_bytes: cli.max_body_bytes,
+        limits: RenderLimits { timeout: None, ..render_limits },
+    })?;
+    if let Some(Command::Render(args)) = cli.command {
+        return offline::run(&renderer, *args);
+    }
+    self_test(&renderer)?;
+
+    let listeners = listeners::open(match cli.unix_socket {
+        Some(path) => BindSource::Unix(path),
+        None => BindSource::Tcp(cli.ip, cli.port),
+    }, cli.backlog)?;
+    let workers = match cli.workers {
+        0 => std::thread::available_parallelism().map_or(1, usize::from),
+        n => n as usize,
+    };
+    info!("{} {} at {}.",
+        workers, if workers > 1 { "workers serve" } else { "worker serves" },
+        listeners.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "));
 
-    let data = web::Data::new(env);
-    HttpServer::new(move ||
+    let renderer = web::Data::new(renderer);
+    let watchdog_renderer = renderer.clone();
+    let limits = web::Data::new(RouteLimits::new(&config.routes, ROUTES)?);
+    let render_slots = web::Data::new(RenderSlots::new(render_limits.timeout));
+    let secrets = web::Data::new(SecretStore::new(cli.config.clone(), &config.secrets)?);
+    secrets::reload_on_sighup(secrets.clone())?;
+    let clients = upstream::ClientFactory::new(&config.proxy, &config.sources.allowed_hosts)?;
+    let health = web::Data::new(HealthRegistry::new(config.sources.allowed_hosts.iter()
+        .filter(|host| !host.starts_with("*."))
+        .map(|host| sources::health_name(host))));
+    let sources_config = web::Data::new(config.sources);
+    let github = web::Data::new(Github::new(config.github));
+    let packages = web::Data::new(Packages::new(config.packages));
+    let store = web::Data::new(BarStore::open(config.bars.path.clone(), config.bars.history)?);
+    if config.bars.token.is_none() {
+        warn!("No `bars.token` is configured, anybody may modify the stored bars.");
+    }
+    BarStore::spawn_flusher(store.clone());
+    let flush_store = store.clone();
+    statsd::spawn(&config.statsd, store.clone()).await?;
+    mqtt::spawn(config.mqtt, &secrets, store.clone(), health.clone())?;
+    let hooks = web::Data::new(Hooks::new(config.hooks, &secrets)?);
+    if hooks.len() > 0 {
+        info!("Accepting webhooks for {} bar(s).", hooks.len());
+    }
+    let bars_config = web::Data::new(config.bars);
+    let badges = web::Data::new(match &config.badges {
+        Some(path) => Badges::load(path)?,
+        None => Badges::default(),
+    });
+    if config.badges.is_some() {
+        info!("Loaded {} badge(s).", badges.len());
+    }
+    if config.stats.token.is_none() {
+        info!("No `stats.token` is configured, /stats is disabled.");
+    }
+    let usage = web::Data::new(UsageStats::new(std::time::Duration::from_secs(config.stats.window)));
+    let stats_config = web::Data::new(config.stats);
+    let views = web::Data::new(ViewCounter::new(config.views.enabled));
+    let chaos_config = web::Data::new(config.chaos);
+    if config.admin.token.is_none() {
+        info!("No `admin.token` is configured, /admin is disabled.");
+    }
+    let admin_config = web::Data::new(config.admin);
+    let template_sources = web::Data::new(template_sources);
+    let query_config = web::Data::new(QueryConfig { strict: cli.strict });
+    let docs = cli.docs;
+    let referrer_rules = web::Data::new(ReferrerRules::new(config.referrers));
+    let log_format = web::Data::new(cli.log_format);
+    let trusted_proxies = web::Data::new(TrustedProxies { proxies: cli.trusted_proxies, header: cli.forwarded_header });
+    let ip_filter = web::Data::new(IpFilter::new(cli.allow_cidr, cli.deny_cidr));
+    let tracer = web::Data::new(match &cli.otlp_endpoint {
+        Some(endpoint) => Tracer::export_to(endpoint)?,
+        None => Tracer::default(),
+    });
+    let mut server = HttpServer::new(move ||
         App::new()
-            .app_data(data.clone())
-            .service(serve_progress_svg_image))
-        .workers(cli.workers as usize)
-        .bind((cli.ip, cli.port))?
-        .run()
-        .await?;
+            .app_data(renderer.clone())
+            .app_data(limits.clone())
+            .app_data(render_slots.clone())
+            .app_data(secrets.clone())
+            .app_data(web::Data::new(clients.build()))
+            .app_data(sources_config.clone())
+            .app_data(health.clone())
+            .app_data(github.clone())
+            .app_data(packages.clone())
+            .app_data(store.clone())
+            .app_data(bars_config.clone())
+            .app_data(hooks.clone())
+            .app_data(badges.clone())
+            .app_data(usage.clone())
+            .app_data(stats_config.clone())
+            .app_data(views.clone())
+            .app_data(chaos_config.clone())
+            .app_data(admin_config.clone())
+            .app_data(template_sources.clone())
+            .app_data(query_config.clone())
+            .app_data(referrer_rules.clone())
+            .app_data(log_format.clone())
+            .app_data(tracer.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(ip_filter.clone())
+            .wrap(from_fn(chaos::simulate))
+            .wrap(from_fn(limits::enforce))
+            .wrap(from_fn(stats::record))
+            .wrap(from_fn(access_log::record))
+            .wrap(from_fn(ip_filter::enforce))
+            .wrap(from_fn(client_ip::assign))
+            .wrap(from_fn(telemetry::trace))
+            .wrap(from_fn(error_badge::render))
+            .wrap(from_fn(request_id::assign))
+            .wrap(Condition::new(cli.compression, from_fn(compression::compress)))
+            .configure(routes)
+            .configure(|cfg| if docs {
+                cfg.service(serve_docs);
+            }))
+        .workers(workers)
+        .keep_alive(match cli.keep_alive {
+            0 => KeepAlive::Disabled,
+            secs => KeepAlive::Timeout(std::time::Duration::from_secs(secs)),
+        })
+        .max_connections(cli.max_connections)
+        .shutdown_timeout(cli.shutdown_timeout)
+        .disable_signals();
+    for listener in listeners {
+        server = match listener {
+            Listener::Tcp(x) => server.listen(x)?,
+            Listener::Unix(x) => server.listen_uds(x)?,
+        };
+    }
+    let server = server.run();
+    shutdown::on_signal(server.handle(), cli.shutdown_timeout)?;
+
+    systemd::notify_ready();
+    systemd::spawn_watchdog(move || self_test(&watchdog_renderer).map(|_| ()));
+    server.await?;
+    flush_store.flush()?;
+    info!("Stopped.");
     Ok(())
 }
 
+/// Renders a representative bar, making sure the template is usable.
+fn self_test(renderer: &ProgressBarRenderer) -> anyhow::Result<String> {
+    Ok(renderer.render(&BarSpec {
+        title: Some("self-test".into()),
+        progress: Some(50.0),
+        ..Default::default()
+    })?)
+}
+
+/// The status of a bar failing to render: 400 for a bad spec, 503 beyond the render limits,
+/// and 500 for a broken template or a bar exceeding `--max-body-bytes`.
+fn render_status(e: &RenderError) -> http::StatusCode {
+    match e {
+        RenderError::Spec(_) => http::StatusCode::BAD_REQUEST,
+        RenderError::OutOfFuel(_) | RenderError::Timeout(_) | RenderError::Busy => http::StatusCode::SERVICE_UNAVAILABLE,
+        RenderError::Template(_) | RenderError::TooLarge { .. } => http::StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// Renders `args`, returning the context along with the SVG.
+fn render_spec(
+    renderer: &ProgressBarRenderer,
+    args: &BarSpec,
+) -> Result<(minijinja::value::Value, String), RenderError> {
+    let ctx = renderer.context(args)?;
+    let svg = renderer.render_context(&ctx)?;
+    Ok((ctx, svg))
+}
+
 
-#[derive(Deserialize, Serialize)]
-struct QueryArgs {
-    title: Option<String>,
-    title_width: Option<i32>,
-    title_color: Option<Cow<'static, str>>,
-    scale: Option<f32>,
-    progress: f32,
-    progress_width: Option<i32>,
-    progress_color: Option<Cow<'static, str>>,
-    suffix: Option<Cow<'static, str>>,
-    // a workaround to handle that quarto adds an image extension to the URL automatically.
-    // In this case, use the url like: https://ip:port/render?progress=39&title=xxx&blackhole=1
-    // By this way, even if the url is modified to something like
-    // https://ip:port/render?progress=39&title=xxx&blackhole=1.png
-    // it will not affect the other TRUE query parameters. 
-    blackhole: Option<String>,
+/// Registers the routes in the order they are matched, the catch-all path bars coming last.
+fn routes(cfg: &mut web::ServiceConfig) {
+    cfg
+        .service(serve_progress_svg_image)
+        .service(serve_context)
+        .service(serve_integrations_health)
+        .service(serve_stats)
+        .service(serve_github_milestone)
+        .service(serve_crate_downloads)
+        .service(serve_npm_downloads)
+        .service(serve_shields_endpoint)
+        .service(serve_posted_shields_endpoint)
+        .service(serve_gallery)
+        .service(serve_batch)
+        .service(serve_export)
+        .service(serve_stored_bar)
+        .service(serve_badge)
+        .service(serve_badge_stats)
+        .service(serve_bar_stats)
+        .service(serve_bar_trend)
+        .service(serve_live_bar)
+        .service(serve_bar_events)
+        .service(put_stored_bar)
+        .service(delete_stored_bar)
+        .service(create_snapshot)
+        .service(receive_hook)
+        .service(serve_snapshot)
+        .service(list_templates)
+        .service(put_template)
+        .service(reload_templates)
+        .service(serve_openapi)
+        .service(serve_path_bar)
+        .service(serve_titled_path_bar);
 }
 
-#[get("/render")]
+#[get("/render", name = "render")]
 async fn serve_progress_svg_image(
-    args: web::Query<QueryArgs>,
-    env: web::Data<Environment<'_>>,
+    args: SpecQuery,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    render_query(args.into_inner(), &renderer, &client, &sources_config, &health, &req).await
+}
+
+/// Returns the fully resolved template context of the bar `/render` would draw with the
+/// same query, for debugging templates or drawing the bar on the client.
+#[get("/context", name = "context")]
+async fn serve_context(
+    args: SpecQuery,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let mut args = args.into_inner();
+    args.format = Some(Format::Json);
+    render_query(args, &renderer, &client, &sources_config, &health, &req).await
+}
+
+/// Renders the bar of a `/render` query, fetching its value from the `source` if given.
+async fn render_query(
+    mut args: BarSpec,
+    renderer: &web::Data<ProgressBarRenderer>,
+    client: &reqwest::Client,
+    sources_config: &SourcesConfig,
+    health: &HealthRegistry,
+    req: &HttpRequest,
+) -> HttpResponse {
+    if let Some(url) = &args.source {
+        let path = args.value_path.as_deref().unwrap_or("$");
+        let span = telemetry::span(req, "fetch source");
+        let value = sources::fetch_value(client, sources_config, health, url, path).await;
+        drop(span);
+        match value {
+            Ok(x) => args.value = Some(x),
+            Err(e) => {
+                error!("{} - Failed to read the value from the source: {}", log_header(req), e);
+                return HttpResponse::build(e.status())
+                    .content_type("text/plain; charset=utf-8")
+                    .body(format!("Failed to read the value from the source: {e}"))
+            }
+        }
+    }
+
+    render_bar(req, renderer, args, None).await
+}
+
+/// `/{progress}`, e.g. `/73`, like `/render?progress=73`, for places mangling query strings.
+/// It is registered last, so the other routes keep their paths.
+#[get("/{progress:-?[0-9]+(\\.[0-9]+)?}{ext:(@[1-9]x)?(\\.(svg|png|json))?}", name = "path_bar")]
+async fn serve_path_bar(
+    path: web::Path<(String, String)>,
+    args: SpecQuery,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let (progress, ext) = path.into_inner();
+    let args = path_spec(args.into_inner(), None, &progress, &ext);
+    render_query(args, &renderer, &client, &sources_config, &health, &req).await
+}
+
+/// `/{title}/{progress}`, e.g. `/coverage/73`, like `/render?title=coverage&progress=73`.
+/// Titles naming other routes, like `crates`, are taken by them.
+#[get("/{title}/{progress:-?[0-9]+(\\.[0-9]+)?}{ext:(@[1-9]x)?(\\.(svg|png|json))?}", name = "titled_path_bar")]
+async fn serve_titled_path_bar(
+    path: web::Path<(String, String, String)>,
+    args: SpecQuery,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let (title, progress, ext) = path.into_inner();
+    let args = path_spec(args.into_inner(), Some(title), &progress, &ext);
+    render_query(args, &renderer, &client, &sources_config, &health, &req).await
+}
+
+/// The spec of the query with the parts of the path applied, which take precedence.
+fn path_spec(mut args: BarSpec, title: Option<String>, progress: &str, ext: &str) -> BarSpec {
+    // the route only matches numbers.
+    args.value = progress.parse().ok();
+    args.title = title.or(args.title);
+    apply_extension(&mut args, ext);
+    args
+}
+
+#[get("/github/{owner}/{repo}/milestone/{number}", name = "github_milestone")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_github_milestone(
+    path: web::Path<(String, String, u64)>,
+    args: SpecQuery,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    github: web::Data<Github>,
+    secrets: web::Data<SecretStore>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let log_header = log_header(&req);
+    let (owner, repo, number) = path.into_inner();
+
+    let milestone = match github.milestone(&client, &secrets, &health, &owner, &repo, number).await {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{} - Failed to fetch the milestone: {}", log_header, e);
+            return HttpResponse::build(github::error_status(&e))
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to fetch the milestone: {e}"))
+        }
+    };
+
+    let mut args = args.into_inner();
+    let percent = milestone.percent();
+    args.value = Some(percent);
+    (args.min, args.max, args.scale) = (None, None, None);
+    args.label.get_or_insert_with(|| format!("{percent:.0}%"));
+    args.title.get_or_insert(milestone.title);
+    render_bar(&req, &renderer, args, Some(&format!("max-age={}", github.cache_ttl()))).await
+}
+
+#[derive(Deserialize)]
+struct DownloadsQuery {
+    /// Downloads to reach, the next power of ten by default.
+    goal: Option<u64>,
+    period: Option<Period>,
+}
+
+#[get("/crates/{name:[\\w-]+}", name = "crate_downloads")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_crate_downloads(
+    name: web::Path<String>,
+    query: web::Query<DownloadsQuery>,
+    args: SpecQuery,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    packages: web::Data<Packages>,
+    health: web::Data<HealthRegistry>,
     req: HttpRequest
 ) -> impl Responder {
-    let log_header = format!(
-        "request from {} with query {}",
-        req.peer_addr().map_or(Cow::from("<UNKNOWN>"),
-                               |x| x.ip().to_string().into()),
-        req.uri());
+    let request = DownloadsRequest { registry: Registry::Crates, name: &name, query: &query };
+    render_downloads(request, args.into_inner(), &renderer, &client, &packages, &health, &req).await
+}
+
+#[get("/npm/{name:(@[\\w.-]+/)?[\\w.-]+}", name = "npm_downloads")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_npm_downloads(
+    name: web::Path<String>,
+    query: web::Query<DownloadsQuery>,
+    args: SpecQuery,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    packages: web::Data<Packages>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let request = DownloadsRequest { registry: Registry::Npm, name: &name, query: &query };
+    render_downloads(request, args.into_inner(), &renderer, &client, &packages, &health, &req).await
+}
+
+struct DownloadsRequest<'a> {
+    registry: Registry,
+    name: &'a str,
+    query: &'a DownloadsQuery,
+}
 
-    let template = match env.get_template(TEMPLATE_NAME) {
+/// Renders the downloads of a package as progress towards the goal.
+#[allow(clippy::too_many_arguments)]
+async fn render_downloads(
+    request: DownloadsRequest<'_>,
+    mut args: BarSpec,
+    renderer: &web::Data<ProgressBarRenderer>,
+    client: &reqwest::Client,
+    packages: &Packages,
+    health: &HealthRegistry,
+    req: &HttpRequest,
+) -> HttpResponse {
+    let period = request.query.period.unwrap_or(Packages::default_period(request.registry));
+    let downloads = match packages.downloads(client, health, request.registry, request.name, period).await {
         Ok(x) => x,
         Err(e) => {
-            error!("{} -> Failed to find template. It probably a bug. \
-            Please report it to the Developer. {}", log_header, e);
+            error!("{} - Failed to fetch the downloads: {}", log_header(req), e);
+            return HttpResponse::build(e.status())
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to fetch the downloads: {e}"))
+        }
+    };
+
+    let goal = request.query.goal.unwrap_or_else(|| packages::next_milestone(downloads)).max(1);
+    args.value = Some(downloads as f32);
+    (args.min, args.max, args.scale) = (None, Some(goal as f32), None);
+    args.label.get_or_insert_with(|| format!("{} / {}", packages::humanize_count(downloads), packages::humanize_count(goal)));
+    args.title.get_or_insert_with(|| request.name.to_string());
+    render_bar(req, renderer, args, Some(&format!("max-age={}", packages.cache_ttl()))).await
+}
+
+#[derive(Deserialize)]
+struct ShieldsQuery {
+    /// Where the shields.io endpoint JSON is fetched from.
+    url: String,
+}
+
+/// Renders the shields.io endpoint JSON at `?url=`, styled by the other query parameters.
+#[get("/shields", name = "shields")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_shields_endpoint(
+    shields_query: web::Query<ShieldsQuery>,
+    args: SpecQuery,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let log_header = log_header(&req);
+    let mut args = args.into_inner();
+    let fetched = sources::fetch_with(&client, &sources_config, &health, &shields_query.url,
+                                      shields::Endpoint::from_document).await;
+    if let Err(e) = fetched.and_then(|endpoint| endpoint.apply(&mut args)) {
+        error!("{} - Failed to read the shields endpoint: {}", log_header, e);
+        return HttpResponse::build(e.status())
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("Failed to read the shields endpoint: {e}"))
+    }
+    render_bar(&req, &renderer, args, None).await
+}
+
+/// Renders the posted shields.io endpoint JSON, styled by the query parameters.
+#[post("/shields", name = "posted_shields")]
+async fn serve_posted_shields_endpoint(
+    endpoint: web::Json<shields::Endpoint>,
+    args: SpecQuery,
+    renderer: web::Data<ProgressBarRenderer>,
+    req: HttpRequest
+) -> impl Responder {
+    let log_header = log_header(&req);
+    let mut args = args.into_inner();
+    if let Err(e) = endpoint.into_inner().apply(&mut args) {
+        error!("{} - Bad shields endpoint: {}", log_header, e);
+        return HttpResponse::build(http::StatusCode::BAD_REQUEST)
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("Bad shields endpoint: {e}"))
+    }
+    render_bar(&req, &renderer, args, None).await
+}
+
+/// Renders the posted array of bar specs into one SVG, the bars stacked from top to bottom.
+#[post("/batch", name = "batch")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_batch(
+    specs: web::Json<Vec<BarSpec>>,
+    renderer: web::Data<ProgressBarRenderer>,
+    store: web::Data<BarStore>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let log_header = log_header(&req);
+    let specs = specs.into_inner();
+    if specs.len() > batch::MAX_BARS {
+        return HttpResponse::build(http::StatusCode::PAYLOAD_TOO_LARGE)
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("A batch holds at most {} bars", batch::MAX_BARS))
+    }
+
+    let composer = Composer { store: &store, client: &client, sources: &sources_config, health: &health, renderer: &renderer };
+    let mut bars = Vec::with_capacity(specs.len());
+    for (i, mut args) in specs.into_iter().enumerate() {
+        if let Some(url) = &args.source {
+            let path = args.value_path.as_deref().unwrap_or("$");
+            match sources::fetch_value(&client, &sources_config, &health, url, path).await {
+                Ok(x) => args.value = Some(x),
+                Err(e) => {
+                    error!("{} - Failed to read the value of bar {}: {}", log_header, i, e);
+                    return HttpResponse::build(e.status())
+                        .content_type("text/plain; charset=utf-8")
+                        .body(format!("Failed to read the value of bar {i}: {e}"))
+                }
+            }
+        }
+        let args = match compose::validate(&args).map(|()| composer.resolve(args)) {
+            Ok(resolving) => resolving.await,
+            Err(e) => Err(e),
+        };
+        let args = match args {
+            Ok(x) => x,
+            Err(e) => {
+                error!("{} - Failed to compose bar {}: {}", log_header, i, e);
+                return HttpResponse::build(e.status())
+                    .content_type("text/plain; charset=utf-8")
+                    .body(format!("Failed to compose bar {i}: {e}"))
+            }
+        };
+        let rendered = {
+            let renderer = renderer.clone();
+            limits::render(&req, move || render_spec(&renderer, &args)).await.and_then(|x| x)
+        };
+        match rendered {
+            Ok(x) => bars.push(x),
+            Err(e) => {
+                error!("{} - Bad bar {}: {:#}", log_header, i, e);
+                return HttpResponse::build(render_status(&e))
+                    .content_type("text/plain; charset=utf-8")
+                    .body(format!("Bad bar {i}: {e:#}"))
+            }
+        }
+    }
+
+    info!("{} - OK", log_header);
+    Rendered { body: Body::Svg(batch::stack(&bars)), cache_control: Some("no-cache".to_string()) }.respond_to(&req)
+}
+
+#[derive(Deserialize)]
+struct ExportQuery {
+    /// Comma separated IDs of stored bars.
+    ids: String,
+    format: Option<Format>,
+}
+
+/// Streams a ZIP of the stored bars `ids`, rendered as SVG unless `format` is given.
+#[get("/export.zip", name = "export")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_export(
+    query: web::Query<ExportQuery>,
+    store: web::Data<BarStore>,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let log_header = log_header(&req);
+    let mut ids: Vec<&str> = Vec::new();
+    for id in query.ids.split(',').filter(|x| !x.is_empty()) {
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    if ids.is_empty() {
+        return HttpResponse::build(http::StatusCode::BAD_REQUEST)
+            .content_type("text/plain; charset=utf-8")
+            .body("`ids` must name at least one bar")
+    }
+    if ids.len() > batch::MAX_BARS {
+        return HttpResponse::build(http::StatusCode::PAYLOAD_TOO_LARGE)
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("An export holds at most {} bars", batch::MAX_BARS))
+    }
+
+    // the bars are resolved before streaming, so that bad bars are still reported by the status.
+    let composer = Composer { store: &store, client: &client, sources: &sources_config, health: &health, renderer: &renderer };
+    let mut bars = Vec::with_capacity(ids.len());
+    for id in ids {
+        let Some(bar) = store.get(id) else { return not_found(&format!("bar `{id}`")) };
+        let args = match composer.resolve(bar.spec).await {
+            Ok(x) => x,
+            Err(e) => return compose_error(&req, id, e),
+        };
+        match renderer.context(&args) {
+            Ok(ctx) => bars.push((id.to_string(), ctx)),
+            Err(e) => {
+                error!("{} - Bad bar {}: {}", log_header, id, e);
+                return HttpResponse::build(http::StatusCode::BAD_REQUEST)
+                    .content_type("text/plain; charset=utf-8")
+                    .body(format!("Bad bar {id}: {e}"))
+            }
+        }
+    }
+    info!("{} - OK", log_header);
+
+    let format = query.format.unwrap_or(Format::Svg);
+    let renderer = renderer.into_inner();
+    let state = (bars.into_iter(), Some(export::ZipWriter::new()));
+    let archive = futures_util::stream::unfold(state, move |(mut bars, zip)| {
+        let renderer = renderer.clone();
+        let log_header = log_header.clone();
+        let req = req.clone();
+        async move {
+            let mut zip = zip?;
+            let Some((id, ctx)) = bars.next() else {
+                return Some((Ok(web::Bytes::from(zip.finish())), (bars, None)));
+            };
+            // rendered like single bars, off the workers and within the render timeout.
+            let data = limits::render(&req, move || export::render(&renderer, &ctx, format)).await;
+            match data.map_err(anyhow::Error::from).and_then(|x| x) {
+                Ok(data) => {
+                    let entry = zip.entry(&format!("{id}.{}", export::extension(format)), &data);
+                    Some((Ok(web::Bytes::from(entry)), (bars, Some(zip))))
+                },
+                Err(e) => {
+                    error!("{} - Failed to render bar {} of the export: {:#}", log_header, id, e);
+                    Some((Err(actix_web::error::ErrorInternalServerError(e)), (bars, None)))
+                },
+            }
+        }
+    });
+    HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header((http::header::CONTENT_DISPOSITION, "attachment; filename=\"bars.zip\""))
+        .insert_header((http::header::CACHE_CONTROL, "no-cache"))
+        .streaming(archive)
+}
+
+#[derive(Deserialize)]
+struct GalleryQuery {
+    format: Option<Format>,
+}
+
+/// Renders the fixed matrix of [`gallery`] bars, or their hashes as JSON.
+#[get("/selftest/gallery", name = "selftest_gallery")]
+async fn serve_gallery(
+    query: web::Query<GalleryQuery>,
+    renderer: web::Data<ProgressBarRenderer>,
+    req: HttpRequest
+) -> impl Responder {
+    let log_header = log_header(&req);
+    let format = match output::negotiate(query.format, &req) {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{} - {}", log_header, e);
+            return HttpResponse::build(http::StatusCode::NOT_ACCEPTABLE)
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Not acceptable: {e}"))
+        }
+    };
+
+    let gallery = {
+        let renderer = renderer.clone();
+        limits::render(&req, move || gallery::render(&renderer)).await.map_err(anyhow::Error::from).and_then(|x| x)
+    };
+    let gallery = match gallery {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{} - Failed to render the gallery: {:#}", log_header, e);
             return HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
                 .content_type("text/plain; charset=utf-8")
-                .body(format!("Failed to find template. It probably a bug. \
-                Please report it to the Developer. {e}"))
+                .body(format!("Failed to render the gallery: {e:#}"))
         }
     };
+    let body = match format {
+        Format::Svg => Body::Svg(gallery.sheet),
+        Format::Json => Body::Json(serde_json::to_value(&gallery).unwrap_or_default()),
+        Format::Png => match limits::render(&req, move || output::rasterize(&gallery.sheet, 1)).await
+            .map_err(anyhow::Error::from).and_then(|x| x) {
+            Ok(x) => Body::Png(x),
+            Err(e) => {
+                error!("{} - Failed to rasterize the gallery: {}", log_header, e);
+                return HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .content_type("text/plain; charset=utf-8")
+                    .body(format!("Failed to rasterize the gallery: {e}"))
+            }
+        },
+    };
+    info!("{} - OK", log_header);
+    Rendered { body, cache_control: Some("no-cache".to_string()) }.respond_to(&req)
+}
 
-    // println!("{template:?}");
-    // let ctx = context!{
-    //     progress => 50.0,
-    //     title_width => 0,
-    //     progress_color => "#f0ad4e",
-    //     progress_width => 90,
-    //     scale => 100.0,
-    //     suffix => "%",
-    //     title_color => "#428bca"
-    // };
-    // println!("{ctx}");
-    //
-    // let src = template.render(ctx).unwrap();
-    // println!("{src}");
+/// The format selected by the extension of a path like `/bars/{id}.svg`.
+fn format_from_extension(ext: &str) -> Option<Format> {
+    match ext {
+        ".svg" => Some(Format::Svg),
+        ".png" => Some(Format::Png),
+        ".json" => Some(Format::Json),
+        _ => None,
+    }
+}
+
+/// Applies the extension of a path like `/bars/{id}@2x.png`, whose `@2x` sets the density.
+fn apply_extension(args: &mut BarSpec, ext: &str) {
+    let (density, ext) = match ext.strip_prefix('@').and_then(|x| x.split_once('x')) {
+        Some((density, ext)) => (density.parse().ok(), ext),
+        None => (None, ext),
+    };
+    args.format = format_from_extension(ext).or(args.format);
+    args.density = density.or(args.density);
+}
+
+fn not_found(what: &str) -> HttpResponse {
+    HttpResponse::build(http::StatusCode::NOT_FOUND)
+        .content_type("text/plain; charset=utf-8")
+        .body(format!("{what} not found"))
+}
 
-    let ctx = extract_template_fields(args.into_inner());
+/// Rejects the request unless it carries the bearer token configured for modifying bars.
+fn check_bars_token(req: &HttpRequest, config: &BarsConfig, secrets: &SecretStore) -> Result<(), HttpResponse> {
+    let Some(name) = &config.token else { return Ok(()) };
+    match secrets.get(name) {
+        Some(token) if auth::has_bearer(req, &token) => Ok(()),
+        _ => Err(HttpResponse::build(http::StatusCode::UNAUTHORIZED)
+            .insert_header((http::header::WWW_AUTHENTICATE, "Bearer"))
+            .content_type("text/plain; charset=utf-8")
+            .body("A valid bearer token is required")),
+    }
+}
+
+#[get("/bars/{id:[\\w-]+}{ext:(@[1-9]x)?(\\.(svg|png|json))?}", name = "bar")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_stored_bar(
+    path: web::Path<(String, String)>,
+    store: web::Data<BarStore>,
+    views: web::Data<ViewCounter>,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let (id, ext) = path.into_inner();
+    let Some(bar) = store.get(&id) else { return not_found("bar") };
+    views.record(views::Kind::Bar, &id, &req);
+    let composer = Composer { store: &store, client: &client, sources: &sources_config, health: &health, renderer: &renderer };
+    let mut args = match composer.resolve(bar.spec).await {
+        Ok(x) => x,
+        Err(e) => return compose_error(&req, &id, e),
+    };
+    apply_extension(&mut args, &ext);
+    render_bar(&req, &renderer, args, Some("no-cache")).await
+}
+
+/// Renders a badge of the badges file, in the format selected by the extension.
+#[get("/b/{name:[\\w-]+}{ext:(@[1-9]x)?(\\.(svg|png|json))?}", name = "badge")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_badge(
+    path: web::Path<(String, String)>,
+    badges: web::Data<Badges>,
+    views: web::Data<ViewCounter>,
+    store: web::Data<BarStore>,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let (name, ext) = path.into_inner();
+    let Some(spec) = badges.get(&name) else { return not_found("badge") };
+    views.record(views::Kind::Badge, &name, &req);
+    let mut args = spec.clone();
+    if let Some(url) = &args.source {
+        let path = args.value_path.as_deref().unwrap_or("$");
+        match sources::fetch_value(&client, &sources_config, &health, url, path).await {
+            Ok(x) => args.value = Some(x),
+            Err(e) => {
+                error!("{} - Failed to read the value from the source: {}", log_header(&req), e);
+                return HttpResponse::build(e.status())
+                    .content_type("text/plain; charset=utf-8")
+                    .body(format!("Failed to read the value from the source: {e}"))
+            }
+        }
+    }
+    let composer = Composer { store: &store, client: &client, sources: &sources_config, health: &health, renderer: &renderer };
+    let mut args = match composer.resolve(args).await {
+        Ok(x) => x,
+        Err(e) => return compose_error(&req, &name, e),
+    };
+    apply_extension(&mut args, &ext);
+    render_bar(&req, &renderer, args, None).await
+}
+
+/// Rejects the request unless it carries the bearer token of `/stats` or the one for modifying
+/// bars. The views are not served when neither is configured, since they reveal the referrers.
+fn check_views_token(
+    req: &HttpRequest,
+    stats: &StatsConfig,
+    bars: &BarsConfig,
+    secrets: &SecretStore,
+) -> Result<(), HttpResponse> {
+    let names = [&stats.token, &bars.token].into_iter().flatten().collect::<Vec<_>>();
+    if names.is_empty() {
+        return Err(not_found("view statistics"))
+    }
+    match names.iter().filter_map(|x| secrets.get(x)).any(|token| auth::has_bearer(req, &token)) {
+        true => Ok(()),
+        false => Err(HttpResponse::build(http::StatusCode::UNAUTHORIZED)
+            .insert_header((http::header::WWW_AUTHENTICATE, "Bearer"))
+            .content_type("text/plain; charset=utf-8")
+            .body("A valid bearer token is required")),
+    }
+}
+
+/// The views of a badge of the badges file, if `[views]` are enabled.
+#[get("/b/{name:[\\w-]+}/stats", name = "badge_stats")]
+async fn serve_badge_stats(
+    name: web::Path<String>,
+    badges: web::Data<Badges>,
+    views: web::Data<ViewCounter>,
+    stats_config: web::Data<StatsConfig>,
+    bars_config: web::Data<BarsConfig>,
+    secrets: web::Data<SecretStore>,
+    req: HttpRequest
+) -> impl Responder {
+    if !views.is_enabled() || badges.get(&name).is_none() {
+        return not_found("badge statistics")
+    }
+    if let Err(e) = check_views_token(&req, &stats_config, &bars_config, &secrets) {
+        return e
+    }
+    HttpResponse::Ok()
+        .insert_header((http::header::CACHE_CONTROL, "no-cache"))
+        .json(views.get(views::Kind::Badge, &name))
+}
+
+/// The views of a stored bar, if `[views]` are enabled.
+#[get("/bars/{id:[\\w-]+}/stats", name = "bar_stats")]
+async fn serve_bar_stats(
+    id: web::Path<String>,
+    store: web::Data<BarStore>,
+    views: web::Data<ViewCounter>,
+    stats_config: web::Data<StatsConfig>,
+    bars_config: web::Data<BarsConfig>,
+    secrets: web::Data<SecretStore>,
+    req: HttpRequest
+) -> impl Responder {
+    if !views.is_enabled() || store.get(&id).is_none() {
+        return not_found("bar statistics")
+    }
+    if let Err(e) = check_views_token(&req, &stats_config, &bars_config, &secrets) {
+        return e
+    }
+    HttpResponse::Ok()
+        .insert_header((http::header::CACHE_CONTROL, "no-cache"))
+        .json(views.get(views::Kind::Bar, &id))
+}
+
+#[derive(Deserialize)]
+struct TrendQuery {
+    /// The number of latest values drawn, 30 by default.
+    points: Option<usize>,
+}
+
+/// A line chart of the latest values of a stored bar followed by its current value, or the
+/// values with their times as JSON.
+#[get("/bars/{id:[\\w-]+}/trend{ext:(@[1-9]x)?(\\.(svg|png|json))?}", name = "bar_trend")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_bar_trend(
+    path: web::Path<(String, String)>,
+    query: web::Query<TrendQuery>,
+    store: web::Data<BarStore>,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let (id, ext) = path.into_inner();
+    let log_header = log_header(&req);
+    let Some(bar) = store.get(&id) else { return not_found("bar") };
+    let history = store.history(&id, query.points.unwrap_or(30));
+    let composer = Composer { store: &store, client: &client, sources: &sources_config, health: &health, renderer: &renderer };
+    let mut args = match composer.resolve(bar.spec).await {
+        Ok(x) => x,
+        Err(e) => return compose_error(&req, &id, e),
+    };
+    apply_extension(&mut args, &ext);
+    let format = match output::negotiate(args.format, &req) {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{} - {}", log_header, e);
+            return HttpResponse::build(http::StatusCode::NOT_ACCEPTABLE)
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Not acceptable: {e}"))
+        }
+    };
+    if format == Format::Json {
+        info!("{} - OK", log_header);
+        return Rendered { body: Body::Json(serde_json::json!({ "points": history })), cache_control: Some("no-cache".to_string()) }
+            .respond_to(&req)
+    }
+    // composed bars have no history of their own, only their current value.
+    let mut values: Vec<f32> = history.iter().map(|x| x.value).collect();
+    if values.is_empty() {
+        values.extend(args.value.or(args.progress));
+    }
+    let density = args.density.unwrap_or(1);
+    let svg = {
+        let renderer = renderer.clone();
+        limits::render(&req, move || renderer.render_trend(&args, &values)).await.and_then(|x| x)
+    };
+    let svg = match svg {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{} - Failed to render the trend: {}", log_header, e);
+            return HttpResponse::build(render_status(&e))
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to render the trend: {e}"))
+        }
+    };
+    let body = match format {
+        Format::Png => match limits::render(&req, move || output::rasterize(&svg, density)).await
+            .map_err(anyhow::Error::from).and_then(|x| x) {
+            Ok(png) => Body::Png(png),
+            Err(e) => {
+                error!("{} - Failed to rasterize: {}", log_header, e);
+                return HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
+                    .content_type("text/plain; charset=utf-8")
+                    .body(format!("Failed to rasterize the trend: {e}"))
+            }
+        },
+        _ => Body::Svg(svg),
+    };
+    info!("{} - OK", log_header);
+    Rendered { body, cache_control: Some("no-cache".to_string()) }.respond_to(&req)
+}
+
+#[get("/bars/{id:[\\w-]+}/live", name = "live_bar")]
+async fn serve_live_bar(id: web::Path<String>, store: web::Data<BarStore>) -> impl Responder {
+    if store.get(&id).is_none() {
+        return not_found("bar")
+    }
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((http::header::CACHE_CONTROL, "no-cache"))
+        .body(live::page(&id))
+}
+
+/// Streams the SVG of a bar as Server-Sent Events, starting with its current state and
+/// followed by one `update` event per change. `delete` is sent when the bar is removed.
+#[get("/bars/{id:[\\w-]+}/events", name = "bar_events")]
+#[allow(clippy::too_many_arguments)]
+async fn serve_bar_events(
+    id: web::Path<String>,
+    store: web::Data<BarStore>,
+    renderer: web::Data<ProgressBarRenderer>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    req: HttpRequest
+) -> impl Responder {
+    let id = id.into_inner();
+    if store.get(&id).is_none() {
+        return not_found("bar")
+    }
+    let changes = store.subscribe();
+    let slots = req.app_data::<web::Data<RenderSlots>>().cloned();
+    let live = LiveBar { id, store, renderer, slots, client, sources_config, health, log_header: log_header(&req) };
+
+    let events = futures_util::stream::unfold((true, changes, live), |(first, mut changes, live)| async move {
+        if first {
+            let event = live.render().await;
+            return Some((Ok::<_, actix_web::Error>(web::Bytes::from(event)), (false, changes, live)));
+        }
+        let event = loop {
+            match tokio::time::timeout(live::KEEPALIVE, changes.recv()).await {
+                Err(_) => break ": keepalive\n\n".to_string(),
+                Ok(Ok(changed)) if !live.affected_by(&changed) => continue,
+                // missed changes are covered by rendering the current state.
+                Ok(Ok(_)) | Ok(Err(broadcast::error::RecvError::Lagged(_))) => break live.render().await,
+                Ok(Err(broadcast::error::RecvError::Closed)) => return None,
+            }
+        };
+        Some((Ok(web::Bytes::from(event)), (false, changes, live)))
+    });
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((http::header::CACHE_CONTROL, "no-cache"))
+        .streaming(events)
+}
+
+/// A stored bar streamed by [`serve_bar_events`].
+struct LiveBar {
+    id: String,
+    store: web::Data<BarStore>,
+    renderer: web::Data<ProgressBarRenderer>,
+    slots: Option<web::Data<RenderSlots>>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    log_header: String,
+}
+
+impl LiveBar {
+    fn composer(&self) -> Composer<'_> {
+        Composer {
+            store: &self.store,
+            client: &self.client,
+            sources: &self.sources_config,
+            health: &self.health,
+            renderer: &self.renderer,
+        }
+    }
+
+    /// Whether the change of bar `changed` may change this bar, which holds for any change
+    /// when the bar is composed.
+    fn affected_by(&self, changed: &str) -> bool {
+        changed == self.id || self.store.get(&self.id).is_some_and(|bar| bar.spec.components.is_some())
+    }
+
+    async fn render(&self) -> String {
+        let Some(bar) = self.store.get(&self.id) else { return live::event("delete", "") };
+        let rendered = match self.composer().resolve(bar.spec).await {
+            Ok(spec) => {
+                let renderer = self.renderer.clone();
+                let job = move || render_spec(&renderer, &spec);
+                match &self.slots {
+                    Some(slots) => slots.run(job).await.and_then(|x| x),
+                    None => job(),
+                }.map_err(|e| e.to_string())
+            },
+            Err(e) => Err(e.to_string()),
+        };
+        match rendered {
+            Ok((_, svg)) => live::update(&svg),
+            Err(e) => {
+                error!("{} - Failed to render bar {}: {}", self.log_header, self.id, e);
+                live::event("error", &e)
+            },
+        }
+    }
+}
+
+fn compose_error(req: &HttpRequest, id: &str, e: compose::ComposeError) -> HttpResponse {
+    error!("{} - Failed to compose bar {}: {}", log_header(req), id, e);
+    HttpResponse::build(e.status())
+        .content_type("text/plain; charset=utf-8")
+        .body(format!("Failed to compose the bar: {e}"))
+}
+
+/// Creates or replaces a bar with the JSON body, which holds the same fields as the query of `/render`.
+#[put("/bars/{id:[\\w-]+}", name = "put_bar")]
+async fn put_stored_bar(
+    id: web::Path<String>,
+    spec: web::Json<BarSpec>,
+    store: web::Data<BarStore>,
+    bars_config: web::Data<BarsConfig>,
+    secrets: web::Data<SecretStore>,
+    req: HttpRequest
+) -> impl Responder {
+    let log_header = log_header(&req);
+    if let Err(e) = check_bars_token(&req, &bars_config, &secrets) {
+        return e;
+    }
+    let spec = spec.into_inner();
+    if let Err(e) = compose::validate(&spec) {
+        return HttpResponse::build(http::StatusCode::BAD_REQUEST)
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("Bad bar: {e}"))
+    }
+    // the value of composed bars is only known when they are read.
+    let mut placeholder = spec.clone();
+    if placeholder.components.is_some() {
+        placeholder.value = Some(0.0);
+    }
+    if let Err(e) = progress_bar::build_context(placeholder) {
+        return HttpResponse::build(http::StatusCode::BAD_REQUEST)
+            .content_type("text/plain; charset=utf-8")
+            .body(format!("Bad bar: {e}"))
+    }
+    match store.put(&id, spec) {
+        Ok(created) => {
+            info!("{} - Stored bar {}", log_header, id);
+            let status = if created { http::StatusCode::CREATED } else { http::StatusCode::OK };
+            HttpResponse::build(status).json(json!({ "id": id.as_str() }))
+        },
+        Err(e) => {
+            error!("{} - Failed to store bar {}: {:#}", log_header, id, e);
+            HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to store the bar: {e:#}"))
+        }
+    }
+}
+
+#[delete("/bars/{id:[\\w-]+}", name = "delete_bar")]
+async fn delete_stored_bar(
+    id: web::Path<String>,
+    store: web::Data<BarStore>,
+    bars_config: web::Data<BarsConfig>,
+    secrets: web::Data<SecretStore>,
+    req: HttpRequest
+) -> impl Responder {
+    if let Err(e) = check_bars_token(&req, &bars_config, &secrets) {
+        return e;
+    }
+    match store.delete(&id) {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => not_found("bar"),
+        Err(e) => {
+            error!("{} - Failed to delete bar {}: {:#}", log_header(&req), id, e);
+            HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to delete the bar: {e:#}"))
+        }
+    }
+}
+
+/// Freezes the current state of a bar, e.g. to embed its progress as of a release.
+#[post("/bars/{id:[\\w-]+}/snapshots", name = "create_snapshot")]
+#[allow(clippy::too_many_arguments)]
+async fn create_snapshot(
+    id: web::Path<String>,
+    store: web::Data<BarStore>,
+    bars_config: web::Data<BarsConfig>,
+    secrets: web::Data<SecretStore>,
+    client: web::Data<reqwest::Client>,
+    sources_config: web::Data<SourcesConfig>,
+    health: web::Data<HealthRegistry>,
+    renderer: web::Data<ProgressBarRenderer>,
+    req: HttpRequest
+) -> impl Responder {
+    if let Err(e) = check_bars_token(&req, &bars_config, &secrets) {
+        return e;
+    }
+    let Some(bar) = store.get(&id) else { return not_found("bar") };
+    let composer = Composer { store: &store, client: &client, sources: &sources_config, health: &health, renderer: &renderer };
+    let spec = match composer.resolve(bar.spec).await {
+        Ok(x) => x,
+        Err(e) => return compose_error(&req, &id, e),
+    };
+    match store.create_snapshot(&id, spec) {
+        Ok(Some(sid)) => {
+            info!("{} - Created snapshot {} of bar {}", log_header(&req), sid, id);
+            HttpResponse::Created()
+                .insert_header((http::header::LOCATION, format!("/snapshots/{sid}.svg")))
+                .json(json!({ "id": sid, "bar": id.as_str(), "url": format!("/snapshots/{sid}.svg") }))
+        },
+        Ok(None) => not_found("bar"),
+        Err(e) => {
+            error!("{} - Failed to snapshot bar {}: {:#}", log_header(&req), id, e);
+            HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to create the snapshot: {e:#}"))
+        }
+    }
+}
+
+/// Sets the value of a stored bar from a webhook payload signed with the secret of its hook.
+#[post("/hooks/{id:[\\w-]+}", name = "hook")]
+async fn receive_hook(
+    id: web::Path<String>,
+    body: web::Bytes,
+    hooks: web::Data<Hooks>,
+    secrets: web::Data<SecretStore>,
+    store: web::Data<BarStore>,
+    req: HttpRequest
+) -> impl Responder {
+    let log_header = log_header(&req);
+    let Some(hook) = hooks.get(&id) else { return not_found("hook") };
+    let value = match hooks::value(hook, &secrets, &req, &body) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("{} - Rejected the webhook of bar {}: {}", log_header, id, e);
+            return HttpResponse::build(e.status())
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Bad webhook: {e}"))
+        }
+    };
+    if store.get(&id).is_some_and(|bar| bar.spec.components.is_some()) {
+        return HttpResponse::build(http::StatusCode::CONFLICT)
+            .content_type("text/plain; charset=utf-8")
+            .body("The bar is composed of other bars")
+    }
+    match store.update_value(&id, true, |_| value) {
+        Ok(_) => {
+            info!("{} - Set bar {} to {} by webhook", log_header, id, value);
+            HttpResponse::Ok().json(json!({ "id": id.as_str(), "value": value }))
+        },
+        Err(e) => {
+            error!("{} - Failed to set bar {} by webhook: {:#}", log_header, id, e);
+            HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to update the bar: {e:#}"))
+        }
+    }
+}
+
+#[get("/snapshots/{sid:[0-9a-f]+}{ext:(@[1-9]x)?(\\.(svg|png|json))?}", name = "snapshot")]
+async fn serve_snapshot(
+    path: web::Path<(String, String)>,
+    store: web::Data<BarStore>,
+    renderer: web::Data<ProgressBarRenderer>,
+    req: HttpRequest
+) -> impl Responder {
+    let (sid, ext) = path.into_inner();
+    let Some(snapshot) = store.snapshot(&sid) else { return not_found("snapshot") };
+    let mut args = snapshot.spec;
+    apply_extension(&mut args, &ext);
+    args.as_of = Some(snapshot.created);
+    // snapshots only change with the templates, which their ETag revalidates.
+    render_bar(&req, &renderer, args, Some("public, max-age=86400")).await
+}
+
+fn log_header(req: &HttpRequest) -> String {
+    format!(
+        "request {}from {} with query {}",
+        request_id::get(req).map_or(String::new(), |x| x + " "),
+        client_ip::get(req).map_or(Cow::from("<UNKNOWN>"), |x| x.to_string().into()),
+        req.uri())
+}
+
+/// Renders the bar described by `args` in the format negotiated with `req`.
+async fn render_bar(
+    req: &HttpRequest,
+    renderer: &web::Data<ProgressBarRenderer>,
+    mut args: BarSpec,
+    cache_control: Option<&str>,
+) -> HttpResponse {
+    let log_header = log_header(req);
+    let density = args.density.unwrap_or(1);
+    let format = match output::negotiate(args.format, req) {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{} - {}", log_header, e);
+            return HttpResponse::build(http::StatusCode::NOT_ACCEPTABLE)
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Not acceptable: {e}"))
+        }
+    };
+
+    // time based bars change by themselves, so they must not be cached for long.
+    let cache_control = if args.is_time_based() { Some("max-age=60") } else { cache_control };
+    let rules = req.app_data::<web::Data<ReferrerRules>>().filter(|x| !x.is_empty());
+    let rule = rules.and_then(|x| x.matching(req));
+    if let Some(rule) = rule {
+        referrers::apply(rule, &mut args);
+    }
+    let cache_control = rule.and_then(|x| x.cache_control.as_deref()).or(cache_control);
+
+    let span = telemetry::span(req, "build context");
+    let ctx = renderer.context(&args);
+    drop(span);
+    let ctx = match ctx {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{} - Bad query parameters: {}", log_header, e);
+            return HttpResponse::build(http::StatusCode::BAD_REQUEST)
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Bad query parameters: {e}"))
+        }
+    };
     debug!("{} - Parsed query arguments: {}", log_header, ctx);
+    if dry_run::requested(req) {
+        info!("{} - OK (dry run)", log_header);
+        return HttpResponse::Ok()
+            .insert_header((http::header::CACHE_CONTROL, "no-store"))
+            .json(dry_run::report(&args, format, &ctx, renderer, cache_control));
+    }
 
-    return if let Ok(x) = template.render(&ctx) {
+    let cache_control = cache_control.map(str::to_string);
+    let etag = output::etag(&ctx, &renderer.fingerprint(), format, density);
+    let respond = |body| {
+        let mut response = match body {
+            Some(body) => Rendered { body, cache_control }.respond_to(req),
+            None => {
+                let mut response = HttpResponse::NotModified();
+                response.insert_header((http::header::VARY, "Accept"));
+                if let Some(cache_control) = cache_control {
+                    response.insert_header((http::header::CACHE_CONTROL, cache_control));
+                }
+                response.finish()
+            },
+        };
+        response.extensions_mut().insert(ContextHash::of(&ctx));
+        if let Ok(value) = http::header::HeaderValue::from_str(&etag.to_string()) {
+            response.headers_mut().insert(http::header::ETAG, value);
+        }
+        // the body differs with the site the bar is embedded on.
+        if rules.is_some() {
+            response.headers_mut().append(http::header::VARY, http::header::HeaderValue::from_static("Referer"));
+        }
+        response
+    };
+    if output::is_fresh(req, &etag) {
+        info!("{} - Not modified", log_header);
+        return respond(None)
+    }
+    if format == Format::Json {
         info!("{} - OK", log_header);
-        HttpResponse::build(http::StatusCode::OK)
-            .content_type("image/svg+xml; charset=utf-8")
-            .body(x)
-    } else {
-        error!("{} - Failed. Probably bad query parameters", log_header);
-        HttpResponse::build(http::StatusCode::BAD_REQUEST)
+        return respond(Some(Body::Json(serde_json::to_value(&ctx).unwrap_or_default())))
+    }
+
+    let span = telemetry::span(req, "render");
+    let svg = {
+        let (renderer, ctx) = (renderer.clone(), ctx.clone());
+        limits::render(req, move || renderer.render_context(&ctx)).await.and_then(|x| x)
+    };
+    drop(span);
+    let svg = match svg {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{} - Failed to render the progress bar: {}", log_header, e);
+            return HttpResponse::build(render_status(&e))
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to render the progress bar: {e}"))
+        }
+    };
+    let body = match format {
+        Format::Png => {
+            let span = telemetry::span(req, "rasterize");
+            let png = limits::render(req, move || output::rasterize(&svg, density)).await;
+            drop(span);
+            match png.map_err(anyhow::Error::from).and_then(|x| x) {
+                Ok(png) => Body::Png(png),
+                Err(e) => {
+                    error!("{} - Failed to rasterize: {}", log_header, e);
+                    return HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
+                        .content_type("text/plain; charset=utf-8")
+                        .body(format!("Failed to rasterize the progress bar: {e}"))
+                }
+            }
+        },
+        _ => Body::Svg(svg),
+    };
+    info!("{} - OK", log_header);
+    respond(Some(body))
+}
+
+
+/// Summarizes the requests of the rolling window for operators.
+#[get("/stats", name = "stats")]
+async fn serve_stats(
+    usage: web::Data<UsageStats>,
+    config: web::Data<StatsConfig>,
+    secrets: web::Data<SecretStore>,
+    req: HttpRequest
+) -> impl Responder {
+    let Some(name) = &config.token else { return not_found("stats") };
+    match secrets.get(name) {
+        Some(token) if auth::has_bearer(&req, &token) => HttpResponse::Ok().json(usage.summary()),
+        _ => HttpResponse::build(http::StatusCode::UNAUTHORIZED)
+            .insert_header((http::header::WWW_AUTHENTICATE, "Bearer"))
             .content_type("text/plain; charset=utf-8")
-            .body(format!("Failed to construct progress bar with parameters: {ctx}"))
+            .body("A valid bearer token is required"),
     }
 }
 
+/// Rejects the request unless it carries the bearer token configured for `/admin/*`.
+fn check_admin_token(req: &HttpRequest, config: &AdminConfig, secrets: &SecretStore) -> Result<(), HttpResponse> {
+    let Some(name) = &config.token else { return Err(not_found("admin route")) };
+    match secrets.get(name) {
+        Some(token) if auth::has_bearer(req, &token) => Ok(()),
+        _ => Err(HttpResponse::build(http::StatusCode::UNAUTHORIZED)
+            .insert_header((http::header::WWW_AUTHENTICATE, "Bearer"))
+            .content_type("text/plain; charset=utf-8")
+            .body("A valid bearer token is required")),
+    }
+}
+
+fn template_error(e: TemplateError) -> HttpResponse {
+    let (status, problems) = match &e {
+        TemplateError::InvalidName(_) | TemplateError::InvalidPartialName(_) => (http::StatusCode::BAD_REQUEST, vec![]),
+        TemplateError::Problems { problems, .. } => (http::StatusCode::UNPROCESSABLE_ENTITY, problems.iter()
+            .map(|x| json!({ "template": x.template, "line": x.line, "case": x.case, "message": x.message }))
+            .collect()),
+    };
+    HttpResponse::build(status).json(json!({ "error": e.to_string(), "problems": problems }))
+}
+
+/// The response when the blocking thread checking templates failed to run.
+fn blocking_error(log_header: &str, e: actix_web::error::BlockingError) -> HttpResponse {
+    error!("{} - Failed to check the templates: {}", log_header, e);
+    HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
+        .content_type("text/plain; charset=utf-8")
+        .body(format!("Failed to check the templates: {e}"))
+}
 
-fn get_progress_color(progress: f32, scale: f32) -> &'static str {
-    let ratio = progress / scale;
+fn template_list(renderer: &ProgressBarRenderer) -> serde_json::Value {
+    json!({
+        "custom_default": renderer.has_custom_template(),
+        "templates": renderer.template_names(),
+        "partials": renderer.partial_names(),
+    })
+}
 
-    return if ratio < 0.3 {
-        "#d9534f"
-    } else if ratio < 0.7 {
-        "#f0ad4e"
-    } else {
-        "#5cb85c"
+/// Lists the templates selectable with `?template=<name>`.
+#[get("/admin/templates", name = "admin_templates")]
+async fn list_templates(
+    renderer: web::Data<ProgressBarRenderer>,
+    config: web::Data<AdminConfig>,
+    secrets: web::Data<SecretStore>,
+    req: HttpRequest
+) -> impl Responder {
+    if let Err(e) = check_admin_token(&req, &config, &secrets) {
+        return e;
     }
+    HttpResponse::Ok().json(template_list(&renderer))
 }
 
-fn extract_template_fields(query: QueryArgs) -> minijinja::value::Value {
-    let mut args = json!({});
-    let mut progress_width = 90;
-    let mut title_width = 0;
+/// Adds or replaces the template `name` of every worker, written to the `templates` directory
+/// if there is one. Templates failing `check-template` are rejected.
+#[post("/admin/templates/{name:[\\w-]+}", name = "admin_template")]
+async fn put_template(
+    name: web::Path<String>,
+    template: String,
+    renderer: web::Data<ProgressBarRenderer>,
+    sources: web::Data<TemplateSources>,
+    config: web::Data<AdminConfig>,
+    secrets: web::Data<SecretStore>,
+    req: HttpRequest
+) -> impl Responder {
+    let log_header = log_header(&req);
+    if let Err(e) = check_admin_token(&req, &config, &secrets) {
+        return e;
+    }
+    let created = !renderer.template_names().contains(&name);
+    // the check renders the template many times, on a blocking thread.
+    let set = {
+        let (renderer, name, template) = (renderer.clone(), name.to_string(), template.clone());
+        web::block(move || renderer.set_template(&name, template)).await
+    };
+    match set {
+        Ok(Ok(())) => {},
+        Ok(Err(e)) => {
+            info!("{} - Rejected template {}: {}", log_header, name, e);
+            return template_error(e);
+        },
+        Err(e) => return blocking_error(&log_header, e),
+    }
+    match sources.write(&name, &template) {
+        Ok(persisted) => {
+            info!("{} - Set template {}", log_header, name);
+            let status = if created { http::StatusCode::CREATED } else { http::StatusCode::OK };
+            HttpResponse::build(status).json(json!({ "name": name.as_str(), "persisted": persisted }))
+        },
+        Err(e) => {
+            error!("{} - Failed to write template {}: {:#}", log_header, name, e);
+            HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("The template is in use, but failed to be written: {e:#}"))
+        }
+    }
+}
 
-    if let Some(title) = query.title {
-        progress_width = 60;
-        title_width = 10 + 6 * title.len() as i32;
-        args["title"] = title.into();
+/// Re-reads the templates from disk, replacing all of them unless one is invalid. Templates
+/// uploaded without a `templates` directory are dropped.
+#[post("/admin/reload", name = "admin_reload")]
+async fn reload_templates(
+    renderer: web::Data<ProgressBarRenderer>,
+    sources: web::Data<TemplateSources>,
+    config: web::Data<AdminConfig>,
+    secrets: web::Data<SecretStore>,
+    req: HttpRequest
+) -> impl Responder {
+    let log_header = log_header(&req);
+    if let Err(e) = check_admin_token(&req, &config, &secrets) {
+        return e;
+    }
+    let files = match sources.read() {
+        Ok(x) => x,
+        Err(e) => {
+            error!("{} - Failed to read the templates: {:#}", log_header, e);
+            return HttpResponse::build(http::StatusCode::INTERNAL_SERVER_ERROR)
+                .content_type("text/plain; charset=utf-8")
+                .body(format!("Failed to read the templates: {e:#}"))
+        }
+    };
+    let replaced = {
+        let renderer = renderer.clone();
+        web::block(move || renderer.replace_templates(files.default, files.named, files.partials)).await
+    };
+    match replaced {
+        Ok(Ok(())) => {},
+        Ok(Err(e)) => {
+            info!("{} - Rejected the reloaded templates: {}", log_header, e);
+            return template_error(e);
+        },
+        Err(e) => return blocking_error(&log_header, e),
+    }
+    info!("{} - Reloaded {} template(s)", log_header, renderer.template_names().len());
+    HttpResponse::Ok().json(template_list(&renderer))
+}
+
+#[get("/integrations/health", name = "integrations_health")]
+async fn serve_integrations_health(health: web::Data<HealthRegistry>) -> impl Responder {
+    let integrations = health.snapshot();
+    let healthy = integrations.values().all(|x| x.status != health::Status::Error);
+    HttpResponse::Ok().json(json!({
+        "healthy": healthy,
+        "integrations": integrations,
+    }))
+}
+
+
+/// The OpenAPI document of the routes rendering bars.
+#[get("/openapi.json", name = "openapi")]
+async fn serve_openapi() -> impl Responder {
+    HttpResponse::Ok().json(openapi::document(env!("CARGO_PKG_VERSION")))
+}
+
+/// Swagger UI showing the OpenAPI document, served with `--docs`.
+#[get("/docs", name = "docs")]
+async fn serve_docs() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(openapi::DOCS_PAGE)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test;
+    use config::SecretSource;
+
+    fn secrets(names: &[&str]) -> web::Data<SecretStore> {
+        let sources = names.iter()
+            .map(|x| (x.to_string(), SecretSource::Value(format!("{x}-secret"))))
+            .collect();
+        web::Data::new(SecretStore::new(None, &sources).unwrap())
     }
 
-    if let Some(width) = query.title_width {
-        args["title_width"] = width.into();
+    /// An app with the stored `bars` and the data of the routes rendering them.
+    fn bars_app(bars: &[(&str, f32)]) -> App<impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >> {
+        let store = BarStore::open(None, 10).unwrap();
+        for (id, value) in bars {
+            store.put(id, BarSpec { progress: Some(*value), ..Default::default() }).unwrap();
+        }
+        App::new()
+            .app_data(web::Data::new(store))
+            .app_data(web::Data::new(ProgressBarRenderer::new(Default::default()).unwrap()))
+            .app_data(web::Data::new(RenderSlots::new(Some(std::time::Duration::from_secs(10)))))
+            .app_data(web::Data::new(reqwest::Client::new()))
+            .app_data(web::Data::new(SourcesConfig::default()))
+            .app_data(web::Data::new(HealthRegistry::new([])))
+            .app_data(web::Data::new(BarsConfig::default()))
+            .app_data(web::Data::new(ViewCounter::new(false)))
+            .app_data(secrets(&[]))
+    }
+
+    /// The next chunk of the streamed `body`, an event of the bar events.
+    async fn next_event(body: &mut actix_web::body::BoxBody) -> String {
+        use actix_web::body::MessageBody;
+        let chunk = std::future::poll_fn(|cx| std::pin::Pin::new(&mut *body).poll_next(cx)).await;
+        String::from_utf8(chunk.unwrap().unwrap().to_vec()).unwrap()
     }
 
-    let scale = query.scale.unwrap_or(100.0);
-    args["title_color"] = query.title_color.unwrap_or_else(|| "#428bca".into()).into();
-    args["title_width"] = query.title_width.unwrap_or(title_width).into();
-    args["scale"] = scale.into();
-    args["progress"] = query.progress.into();
-    args["progress_width"] = query.progress_width.unwrap_or(progress_width).into();
-    args["progress_color"] = query.progress_color.unwrap_or_else(||
-        get_progress_color(query.progress, scale).into()).into();
-    args["suffix"] = query.suffix.unwrap_or_else(|| "%".into()).into();
+    #[actix_web::test]
+    async fn bar_events_follow_updates_and_deletion() {
+        let app = test::init_service(bars_app(&[("build", 10.0)]).configure(routes)).await;
+        let response = test::call_service(&app, test::TestRequest::get().uri("/bars/build/events").to_request()).await;
+        assert_eq!(response.headers().get(http::header::CONTENT_TYPE).unwrap(), "text/event-stream");
+        let mut events = response.into_body();
+        let event = next_event(&mut events).await;
+        assert!(event.starts_with("event: update\ndata: <svg") && event.contains("aria-label=\"10%\""), "{event}");
+
+        let put = test::TestRequest::put().uri("/bars/build").set_json(json!({ "progress": 70 })).to_request();
+        assert_eq!(test::call_service(&app, put).await.status(), http::StatusCode::OK);
+        let event = next_event(&mut events).await;
+        assert!(event.starts_with("event: update\n") && event.contains("aria-label=\"70%\""), "{event}");
+
+        let delete = test::TestRequest::delete().uri("/bars/build").to_request();
+        assert_eq!(test::call_service(&app, delete).await.status(), http::StatusCode::NO_CONTENT);
+        assert_eq!(next_event(&mut events).await, "event: delete\ndata:\n\n");
+    }
 
-    minijinja::value::Value::from_serializable(&args)
+    #[actix_web::test]
+    async fn path_bars_leave_the_other_routes_their_paths() {
+        // an unreachable registry, so that the downloads fail without leaving the host.
+        let packages = Packages::new(config::PackagesConfig {
+            crates_api_url: "http://127.0.0.1:1".to_string(),
+            ..Default::default()
+        });
+        let app = test::init_service(bars_app(&[])
+            .app_data(web::Data::new(packages))
+            .app_data(web::Data::new(Badges::default()))
+            .configure(routes)).await;
+        let get = |uri: &'static str| test::call_service(&app, test::TestRequest::get().uri(uri).to_request());
+
+        let response = get("/73").await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert!(String::from_utf8_lossy(&test::read_body(response).await).contains("aria-label=\"73%\""));
+        let response = get("/coverage/73.png").await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(response.headers().get(http::header::CONTENT_TYPE).unwrap(), "image/png");
+        let response = get("/crates/73").await;
+        assert_eq!(response.status(), http::StatusCode::BAD_GATEWAY);
+        assert!(String::from_utf8_lossy(&test::read_body(response).await).starts_with("Failed to fetch the downloads"));
+        for (uri, body) in [("/bars/5", "bar not found"), ("/b/73", "badge not found")] {
+            let response = get(uri).await;
+            assert_eq!(response.status(), http::StatusCode::NOT_FOUND, "{uri}");
+            assert_eq!(test::read_body(response).await, body, "{uri}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn expired_countdowns_follow_the_overflow_policy() {
+        let app = test::init_service(bars_app(&[]).configure(routes)).await;
+        let get = |uri: &'static str| test::call_service(&app, test::TestRequest::get().uri(uri).to_request());
+
+        let response = get("/render?mode=countdown&until=2000-01-01").await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert!(String::from_utf8_lossy(&test::read_body(response).await).contains(">expired</text>"));
+        let response = get("/render?mode=countdown&until=2000-01-01&overflow=error").await;
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn unacceptable_formats_are_refused() {
+        let app = test::init_service(bars_app(&[]).configure(routes)).await;
+        let get = |uri: &'static str, accept: &'static str| test::call_service(&app, test::TestRequest::get()
+            .uri(uri)
+            .insert_header((http::header::ACCEPT, accept))
+            .to_request());
+
+        let response = get("/render?progress=42", "text/html").await;
+        assert_eq!(response.status(), http::StatusCode::NOT_ACCEPTABLE);
+        assert!(String::from_utf8_lossy(&test::read_body(response).await).starts_with("Not acceptable"));
+        let response = get("/render?progress=42&format=svg", "text/html").await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(response.headers().get(http::header::CONTENT_TYPE).unwrap(), "image/svg+xml; charset=utf-8");
+        let response = get("/render?progress=42", "text/html;q=0.9, application/json").await;
+        assert_eq!(response.headers().get(http::header::CONTENT_TYPE).unwrap(), "application/json");
+    }
+
+    #[actix_web::test]
+    async fn stats_require_their_token() {
+        let config = StatsConfig { token: Some("stats".to_string()), ..Default::default() };
+        let app = test::init_service(bars_app(&[])
+            .app_data(secrets(&["stats"]))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(UsageStats::new(std::time::Duration::from_secs(60))))
+            .wrap(from_fn(stats::record))
+            .configure(routes)).await;
+        let render = test::TestRequest::get().uri("/render?title=x&progress=1")
+            .insert_header((http::header::REFERER, "https://example.com/readme"))
+            .to_request();
+        assert_eq!(test::call_service(&app, render).await.status(), http::StatusCode::OK);
+
+        let response = test::call_service(&app, test::TestRequest::get().uri("/stats").to_request()).await;
+        assert_eq!(response.status(), http::StatusCode::UNAUTHORIZED);
+        let request = test::TestRequest::get().uri("/stats")
+            .insert_header((http::header::AUTHORIZATION, "Bearer wrong"))
+            .to_request();
+        assert_eq!(test::call_service(&app, request).await.status(), http::StatusCode::UNAUTHORIZED);
+        let request = test::TestRequest::get().uri("/stats")
+            .insert_header((http::header::AUTHORIZATION, "Bearer stats-secret"))
+            .to_request();
+        let summary: serde_json::Value = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(summary["requests"], 1, "{summary}");
+        assert_eq!(summary["parameters"][0]["key"], "render?progress&title");
+        assert_eq!(summary["formats"][0]["key"], "svg");
+        assert_eq!(summary["referrers"][0]["key"], "example.com");
+    }
+
+    #[actix_web::test]
+    async fn bars_vary_with_the_referring_site() {
+        let rules = vec![config::ReferrerRule {
+            hosts: vec!["*.github.com".to_string()],
+            title_color: None,
+            progress_color: Some("#1f883d".to_string()),
+            progress_width: None,
+            suffix: None,
+            cache_control: Some("max-age=600".to_string()),
+        }];
+        let app = test::init_service(bars_app(&[])
+            .app_data(web::Data::new(ReferrerRules::new(rules)))
+            .configure(routes)).await;
+        let get = |uri: &'static str, referrer: &'static str| test::call_service(&app, test::TestRequest::get()
+            .uri(uri)
+            .insert_header((http::header::REFERER, referrer))
+            .to_request());
+        let vary = |response: &actix_web::dev::ServiceResponse| response.headers().get_all(http::header::VARY)
+            .map(|x| x.to_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+
+        let response = get("/render?progress=42", "https://gist.github.com/x").await;
+        assert_eq!(vary(&response), ["Accept", "Referer"]);
+        assert_eq!(response.headers().get(http::header::CACHE_CONTROL).unwrap(), "max-age=600");
+        assert!(String::from_utf8_lossy(&test::read_body(response).await).contains("#1f883d"));
+        let response = get("/render?progress=42&progress_color=red", "https://gist.github.com/x").await;
+        let body = String::from_utf8_lossy(&test::read_body(response).await).to_string();
+        assert!(body.contains("red") && !body.contains("#1f883d"));
+        let response = get("/render?progress=42", "https://example.com/").await;
+        assert_eq!(vary(&response), ["Accept", "Referer"]);
+        assert_ne!(response.headers().get(http::header::CACHE_CONTROL).map(|x| x.to_str().unwrap()), Some("max-age=600"));
+        assert!(!String::from_utf8_lossy(&test::read_body(response).await).contains("#1f883d"));
+    }
+
+    #[actix_web::test]
+    async fn badges_are_served_by_name() {
+        let path = std::env::temp_dir().join(format!("pbar-badges-{}.yaml", std::process::id()));
+        std::fs::write(&path, "coverage:\n  title: coverage\n  progress: 73\n").unwrap();
+        let badges = Badges::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let app = test::init_service(bars_app(&[])
+            .app_data(web::Data::new(badges))
+            .configure(routes)).await;
+        let get = |uri: &'static str| test::call_service(&app, test::TestRequest::get().uri(uri).to_request());
+
+        let response = get("/b/coverage.svg").await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(response.headers().get(http::header::CONTENT_TYPE).unwrap(), "image/svg+xml; charset=utf-8");
+        assert!(String::from_utf8_lossy(&test::read_body(response).await).contains("aria-label=\"coverage: 73%\""));
+        let response = get("/b/coverage.json").await;
+        assert_eq!(response.headers().get(http::header::CONTENT_TYPE).unwrap(), "application/json");
+        let response = get("/b/missing.svg").await;
+        assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(test::read_body(response).await, "badge not found");
+    }
+
+    #[actix_web::test]
+    async fn snapshots_keep_the_state_they_were_taken_in() {
+        let app = test::init_service(bars_app(&[("release", 40.0)]).configure(routes)).await;
+        let response = test::call_service(&app, test::TestRequest::post().uri("/bars/release/snapshots").to_request()).await;
+        assert_eq!(response.status(), http::StatusCode::CREATED);
+        let location = response.headers().get(http::header::LOCATION).unwrap().to_str().unwrap().to_string();
+        let put = test::TestRequest::put().uri("/bars/release").set_json(json!({ "progress": 70 })).to_request();
+        assert_eq!(test::call_service(&app, put).await.status(), http::StatusCode::OK);
+
+        let response = test::call_service(&app, test::TestRequest::get().uri(&location).to_request()).await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        assert_eq!(response.headers().get(http::header::CACHE_CONTROL).unwrap(), "public, max-age=86400");
+        let etag = response.headers().get(http::header::ETAG).unwrap().clone();
+        assert!(String::from_utf8_lossy(&test::read_body(response).await).contains("aria-label=\"40%\""));
+        let response = test::call_service(&app, test::TestRequest::get().uri("/bars/release").to_request()).await;
+        assert!(String::from_utf8_lossy(&test::read_body(response).await).contains("aria-label=\"70%\""));
+
+        let revalidated = test::TestRequest::get().uri(&location).insert_header((http::header::IF_NONE_MATCH, etag)).to_request();
+        let response = test::call_service(&app, revalidated).await;
+        assert_eq!(response.status(), http::StatusCode::NOT_MODIFIED);
+        assert!(test::read_body(response).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn batches_stack_their_bars() {
+        let app = test::init_service(bars_app(&[("docs", 50.0)]).service(serve_batch)).await;
+        let post = |specs: serde_json::Value| test::TestRequest::post().uri("/batch").set_json(specs).to_request();
+
+        let batch = json!([{ "progress": 10, "title": "tests" }, { "components": [{ "bar": "docs", "weight": 1 }] }]);
+        let response = test::call_service(&app, post(batch)).await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let svg = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert_eq!(svg.matches("<?xml").count(), 1);
+        assert!(svg.contains("height=\"44\""), "{svg}");
+        let (first, second) = (svg.find("<svg y=\"0\">").unwrap(), svg.find("<svg y=\"24\">").unwrap());
+        assert!(first < svg.find("aria-label=\"tests: 10%\"").unwrap() && second < svg.find("aria-label=\"50%\"").unwrap());
+
+        let response = test::call_service(&app, post(json!([{ "progress": 10 }, { "progress": 10, "scale": 0 }]))).await;
+        assert_eq!(response.status(), http::StatusCode::BAD_REQUEST);
+        assert!(String::from_utf8_lossy(&test::read_body(response).await).starts_with("Bad bar 1:"));
+        let response = test::call_service(&app, post(json!(vec![json!({ "progress": 10 }); batch::MAX_BARS + 1]))).await;
+        assert_eq!(response.status(), http::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[actix_web::test]
+    async fn exports_render_in_the_slots() {
+        let app = test::init_service(bars_app(&[("a", 10.0), ("b", 20.0)]).service(serve_export)).await;
+        let response = test::call_service(&app, test::TestRequest::get().uri("/export.zip?ids=a,b,a").to_request()).await;
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let zip = test::read_body(response).await;
+        let names = zip.windows(5).filter(|x| *x == b"a.svg" || *x == b"b.svg").count();
+        // in the local headers and the central directory.
+        assert_eq!(names, 4);
+        assert_eq!(zip.windows(4).filter(|x| *x == b"<svg").count(), 2);
+    }
+
+    #[actix_web::test]
+    async fn views_require_the_stats_or_bars_token() {
+        let store = web::Data::new(BarStore::open(None, 0).unwrap());
+        store.put("coverage", BarSpec { progress: Some(40.0), ..Default::default() }).unwrap();
+        for (stats, bars, bearer, status) in [
+            (None, None, None, http::StatusCode::NOT_FOUND),
+            (Some("stats"), None, None, http::StatusCode::UNAUTHORIZED),
+            (Some("stats"), None, Some("bars-secret"), http::StatusCode::UNAUTHORIZED),
+            (Some("stats"), None, Some("stats-secret"), http::StatusCode::OK),
+            (Some("stats"), Some("bars"), Some("bars-secret"), http::StatusCode::OK),
+        ] {
+            let app = test::init_service(App::new()
+                .app_data(store.clone())
+                .app_data(web::Data::new(ViewCounter::new(true)))
+                .app_data(web::Data::new(StatsConfig { token: stats.map(str::to_string), ..Default::default() }))
+                .app_data(web::Data::new(BarsConfig { token: bars.map(str::to_string), ..Default::default() }))
+                .app_data(secrets(&["stats", "bars"]))
+                .service(serve_bar_stats)).await;
+            let mut request = test::TestRequest::get().uri("/bars/coverage/stats");
+            if let Some(bearer) = bearer {
+                request = request.insert_header((http::header::AUTHORIZATION, format!("Bearer {bearer}")));
+            }
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(response.status(), status, "{stats:?} {bars:?} {bearer:?}");
+        }
+    }
 }
@@ -0,0 +1,152 @@
+//! An MQTT client setting the values of stored bars from the messages of subscribed topics,
+//! so device fleets can be visualized without an intermediary service, e.g.
+//!
+//! ```toml
+//! [mqtt]
+//! host = "broker.local"
+//! [[mqtt.subscriptions]]
+//! topic = "devices/+/battery"
+//! bar = "battery-{1}"
+//! value_path = "$.level"
+//! ```
+//!
+//! `{1}` is replaced by the level of the topic matched by the first `+`. Payloads are
+//! numbers, or JSON documents read with `value_path`.
+use std::time::Duration;
+use actix_web::{rt, web};
+use anyhow::bail;
+use log::{debug, info, warn};
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+use crate::bars::BarStore;
+use crate::config::{MqttConfig, MqttSubscription};
+use crate::health::HealthRegistry;
+use crate::secrets::SecretStore;
+use crate::sources;
+
+
+/// Pause before reconnecting to the broker.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The levels of `topic` matched by the `+` wildcards of `filter`, if it matches.
+fn match_topic<'a>(filter: &str, topic: &'a str) -> Option<Vec<&'a str>> {
+    let mut wildcards = Vec::new();
+    let mut levels = topic.split('/');
+    for pattern in filter.split('/') {
+        match (pattern, levels.next()) {
+            ("#", _) => return Some(wildcards),
+            ("+", Some(level)) => wildcards.push(level),
+            (pattern, Some(level)) if pattern == level => {},
+            _ => return None,
+        }
+    }
+    levels.next().is_none().then_some(wildcards)
+}
+
+/// `bar` with `{n}` replaced by the `n`th wildcard level, `None` if the result is no valid id.
+fn bar_id(bar: &str, wildcards: &[&str]) -> Option<String> {
+    let mut id = bar.to_string();
+    for (i, level) in wildcards.iter().enumerate() {
+        id = id.replace(&format!("{{{}}}", i + 1), level);
+    }
+    let valid = !id.is_empty() && id.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+    valid.then_some(id)
+}
+
+fn parse_payload(payload: &[u8], subscription: &MqttSubscription) -> anyhow::Result<f32> {
+    let text = std::str::from_utf8(payload)?.trim();
+    let value = match &subscription.value_path {
+        Some(path) => sources::extract_value(&serde_json::from_str(text)?, path)?,
+        None => text.parse()?,
+    };
+    if !value.is_finite() {
+        bail!("{value} is not a finite number");
+    }
+    Ok(value)
+}
+
+fn apply(store: &BarStore, subscriptions: &[MqttSubscription], topic: &str, payload: &[u8]) {
+    for subscription in subscriptions {
+        let Some(wildcards) = match_topic(&subscription.topic, topic) else { continue };
+        let Some(id) = bar_id(&subscription.bar, &wildcards) else {
+            debug!("Ignoring MQTT topic {}, which names no valid bar with `{}`.", topic, subscription.bar);
+            continue;
+        };
+        let result = parse_payload(payload, subscription)
+            .and_then(|value| store.update_value(&id, true, |_| value));
+        if let Err(e) = result {
+            warn!("Failed to apply the MQTT message of {} to bar {}: {:#}", topic, id, e);
+        }
+    }
+}
+
+/// Connects to the broker and subscribes to the topics if `config.host` is given.
+pub fn spawn(
+    config: MqttConfig,
+    secrets: &SecretStore,
+    store: web::Data<BarStore>,
+    health: web::Data<HealthRegistry>,
+) -> anyhow::Result<()> {
+    let Some(host) = config.host.clone() else { return Ok(()) };
+    for subscription in &config.subscriptions {
+        if !rumqttc::valid_filter(&subscription.topic) {
+            bail!("invalid MQTT topic filter `{}`", subscription.topic);
+        }
+    }
+    let mut options = MqttOptions::new(&config.client_id, &host, config.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    if let Some(username) = &config.username {
+        let password = match &config.password {
+            Some(name) => match secrets.get(name) {
+                Some(x) => x.to_string(),
+                None => bail!("unknown secret `{name}` for the MQTT password"),
+            },
+            None => String::new(),
+        };
+        options.set_credentials(username, password);
+    }
+
+    let (client, mut events) = AsyncClient::new(options, config.subscriptions.len().max(10));
+    let name = format!("mqtt:{host}");
+    let url = format!("mqtt://{host}:{}", config.port);
+    rt::spawn(async move {
+        loop {
+            match events.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    info!("Connected to the MQTT broker {}.", url);
+                    health.record(&name, &url, Ok::<_, String>(()));
+                    // subscriptions are renewed on every connection, as sessions are clean.
+                    for subscription in &config.subscriptions {
+                        if let Err(e) = client.try_subscribe(&subscription.topic, QoS::AtLeastOnce) {
+                            warn!("Failed to subscribe to {}: {}", subscription.topic, e);
+                        }
+                    }
+                },
+                Ok(Event::Incoming(Packet::Publish(message))) =>
+                    apply(&store, &config.subscriptions, &message.topic, &message.payload),
+                Ok(_) => {},
+                Err(e) => {
+                    warn!("MQTT connection to {} failed, reconnecting: {}", url, e);
+                    health.record(&name, &url, Err(e));
+                    rt::time::sleep(RECONNECT_DELAY).await;
+                },
+            }
+        }
+    });
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_name_the_bar() {
+        let wildcards = match_topic("devices/+/battery", "devices/d42/battery").unwrap();
+        assert_eq!(bar_id("battery-{1}", &wildcards).as_deref(), Some("battery-d42"));
+        assert!(match_topic("devices/+/battery", "devices/d42/sync").is_none());
+        assert!(match_topic("devices/+/battery", "devices/d42/battery/x").is_none());
+        assert_eq!(match_topic("devices/#", "devices/d42/battery"), Some(vec![]));
+        assert_eq!(bar_id("b-{1}", &["a/b"]), None);
+    }
+}
@@ -0,0 +1,68 @@
+//! The formatting of the value selected by `preset`, with the decimal separator of `locale`,
+//! e.g. `146/200 items` for `?value=146&max=200&preset=count&suffix= items`, or `0,73` for
+//! `?value=0.73&preset=ratio&locale=de-DE`.
+use crate::spec::{Preset, SpecError};
+
+
+/// Languages writing a decimal comma. The others write a point.
+const DECIMAL_COMMA: &[&str] = &[
+    "bg", "cs", "da", "de", "el", "es", "et", "fi", "fr", "hr", "hu", "id", "it", "lt", "lv", "nb", "nl", "nn", "no",
+    "pl", "pt", "ro", "ru", "sk", "sl", "sr", "sv", "tr", "uk", "vi",
+];
+
+/// The decimal separator of `locale`, a language tag like `de-DE` or `fr`.
+pub fn decimal_separator(locale: &str) -> Result<char, SpecError> {
+    let mut parts = locale.split(['-', '_']);
+    let language = parts.next().unwrap_or_default().to_ascii_lowercase();
+    let valid = (2..=3).contains(&language.len()) && language.bytes().all(|b| b.is_ascii_alphabetic())
+        && parts.all(|x| (1..=8).contains(&x.len()) && x.bytes().all(|b| b.is_ascii_alphanumeric()));
+    if !valid {
+        return Err(SpecError::InvalidLocale(locale.to_string()));
+    }
+    Ok(if DECIMAL_COMMA.contains(&language.as_str()) { ',' } else { '.' })
+}
+
+/// `x` with `decimals` digits after the `separator`, dropping trailing zeros if `trim`.
+pub(crate) fn number(x: f32, decimals: usize, separator: char, trim: bool) -> String {
+    let mut text = format!("{x:.decimals$}");
+    if trim && text.contains('.') {
+        text = text.trim_end_matches('0').trim_end_matches('.').to_string();
+    }
+    text.replace('.', separator.encode_utf8(&mut [0; 4]))
+}
+
+/// The formatted value of a bar at `value` of a range ending at `max`, the share of the range
+/// being `ratio`. Without a preset, the value is shown like the default template does.
+pub(crate) fn format_value(
+    preset: Option<Preset>,
+    value: f32,
+    ratio: f32,
+    max: f32,
+    suffix: &str,
+    separator: char,
+) -> String {
+    match preset {
+        None => format!("{}{suffix}", number(value, 1, separator, false)),
+        Some(Preset::Percent) => format!("{}{suffix}", number(ratio * 100.0, 1, separator, true)),
+        Some(Preset::Ratio) => format!("{}{suffix}", number(ratio, 2, separator, false)),
+        Some(Preset::Count) =>
+            format!("{}/{}{suffix}", number(value, 1, separator, true), number(max, 1, separator, true)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_format_with_the_separator_of_the_locale() {
+        assert_eq!(decimal_separator("de-DE").unwrap(), ',');
+        assert_eq!(decimal_separator("en_US").unwrap(), '.');
+        assert!(matches!(decimal_separator("german"), Err(SpecError::InvalidLocale(_))));
+        assert_eq!(format_value(Some(Preset::Ratio), 146.0, 0.73, 200.0, "", ','), "0,73");
+        assert_eq!(format_value(Some(Preset::Count), 146.0, 0.73, 200.0, " items", '.'), "146/200 items");
+        assert_eq!(format_value(Some(Preset::Percent), 146.0, 0.735, 200.0, "%", ','), "73,5%");
+        assert_eq!(format_value(None, 73.0, 0.73, 100.0, "%", ','), "73,0%");
+    }
+}
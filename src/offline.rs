@@ -0,0 +1,226 @@
+//! Subcommands working without the server: `render` writes one bar to a file or stdout,
+//! e.g. for CI jobs committing static badges, and `check-template` validates a template.
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Context};
+use clap::Args;
+use serde::de::DeserializeOwned;
+use progress_bar::{BarSpec, ColorMode, ColorSpace, Direction, Format, LabelPosition, Mode, OverflowPolicy, Palette, Preset, ProgressBarRenderer, RenderLimits, Units};
+use crate::output;
+use crate::templates::TemplateSources;
+
+
+/// Parses the lowercase names the query parameters use.
+fn parse_name<T: DeserializeOwned>(name: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).map_err(|e| e.to_string())
+}
+
+#[derive(Args)]
+pub struct RenderArgs {
+    /// Where the bar is written, stdout by default
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// svg, png or json, guessed from the extension of the output by default
+    #[arg(long, value_parser = parse_name::<Format>)]
+    format: Option<Format>,
+
+    #[arg(long)]
+    title: Option<String>,
+    #[arg(long)]
+    title_width: Option<i32>,
+    #[arg(long)]
+    title_color: Option<String>,
+    /// Name of a bundled logo drawn left of the title
+    #[arg(long)]
+    logo: Option<String>,
+    /// Base64 data URI of an SVG or PNG logo
+    #[arg(long)]
+    logo_data: Option<String>,
+    #[arg(long, allow_negative_numbers = true)]
+    progress: Option<f32>,
+    #[arg(long, allow_negative_numbers = true)]
+    value: Option<f32>,
+    #[arg(long, allow_negative_numbers = true)]
+    min: Option<f32>,
+    #[arg(long, allow_negative_numbers = true)]
+    max: Option<f32>,
+    /// Bytes done of `--total`, shown instead of the percentage
+    #[arg(long)]
+    done: Option<f64>,
+    #[arg(long)]
+    total: Option<f64>,
+    /// bytes or si, the units of `--done` and `--total`
+    #[arg(long, value_parser = parse_name::<Units>)]
+    units: Option<Units>,
+    /// Value the difference to is shown next to the bar
+    #[arg(long, allow_negative_numbers = true)]
+    baseline: Option<f32>,
+    #[arg(long)]
+    scale: Option<f32>,
+    #[arg(long)]
+    start: Option<String>,
+    #[arg(long)]
+    end: Option<String>,
+    #[arg(long)]
+    tz: Option<String>,
+    /// progress or countdown
+    #[arg(long, value_parser = parse_name::<Mode>)]
+    mode: Option<Mode>,
+    #[arg(long)]
+    until: Option<String>,
+    /// Name of a pipeline in the `[transforms]` config section
+    #[arg(long)]
+    transform: Option<String>,
+    /// clamp, error or allow
+    #[arg(long, value_parser = parse_name::<OverflowPolicy>)]
+    overflow: Option<OverflowPolicy>,
+    #[arg(long)]
+    progress_width: Option<i32>,
+    #[arg(long)]
+    progress_color: Option<String>,
+    /// default or colorblind
+    #[arg(long, value_parser = parse_name::<Palette>)]
+    palette: Option<Palette>,
+    /// bands or interpolate
+    #[arg(long, value_parser = parse_name::<ColorMode>)]
+    color_mode: Option<ColorMode>,
+    /// Colors at percentages of the range, e.g. 0:#d9534f,100:#5cb85c
+    #[arg(long)]
+    color_stops: Option<String>,
+    /// lab or hsl
+    #[arg(long, value_parser = parse_name::<ColorSpace>)]
+    color_space: Option<ColorSpace>,
+    /// Hatches the filled part of low and medium values
+    #[arg(long)]
+    patterns: bool,
+    /// Switches to darker colors for viewers preferring a dark color scheme
+    #[arg(long)]
+    adaptive: bool,
+    #[arg(long)]
+    suffix: Option<String>,
+    /// percent, ratio or count
+    #[arg(long, value_parser = parse_name::<Preset>)]
+    preset: Option<Preset>,
+    /// Language tag the value is formatted for, e.g. de-DE
+    #[arg(long)]
+    locale: Option<String>,
+    /// Text replacing the formatted value
+    #[arg(long)]
+    label: Option<String>,
+    /// center, inside, outside or none
+    #[arg(long, value_parser = parse_name::<LabelPosition>)]
+    label_position: Option<LabelPosition>,
+    /// Link of the whole bar, or of the title if `--link2` is given
+    #[arg(long)]
+    link: Option<String>,
+    /// Link of the progress part
+    #[arg(long)]
+    link2: Option<String>,
+    /// Text screen readers announce for the bar
+    #[arg(long)]
+    aria_label: Option<String>,
+    /// ltr or rtl
+    #[arg(long, value_parser = parse_name::<Direction>)]
+    dir: Option<Direction>,
+    /// Pixels per pixel of the declared size of PNGs, e.g. 2 for high-DPI displays
+    #[arg(long)]
+    density: Option<u32>,
+}
+
+impl RenderArgs {
+    fn spec(self) -> BarSpec {
+        BarSpec {
+            title: self.title,
+            title_width: self.title_width,
+            title_color: self.title_color.map(Into::into),
+            logo: self.logo,
+            logo_data: self.logo_data,
+            scale: self.scale,
+            progress: self.progress,
+            value: self.value,
+            min: self.min,
+            max: self.max,
+            baseline: self.baseline,
+            done: self.done,
+            total: self.total,
+            units: self.units,
+            start: self.start,
+            end: self.end,
+            tz: self.tz,
+            mode: self.mode,
+            until: self.until,
+            transform: self.transform,
+            overflow: self.overflow,
+            progress_width: self.progress_width,
+            progress_color: self.progress_color.map(Into::into),
+            palette: self.palette,
+            color_mode: self.color_mode,
+            color_stops: self.color_stops,
+            color_space: self.color_space,
+            patterns: self.patterns.then_some(true),
+            adaptive: self.adaptive.then_some(true),
+            suffix: self.suffix.map(Into::into),
+            preset: self.preset,
+            locale: self.locale,
+            label: self.label,
+            label_position: self.label_position,
+            link: self.link,
+            link2: self.link2,
+            aria_label: self.aria_label,
+            dir: self.dir,
+            density: self.density,
+            ..Default::default()
+        }
+    }
+}
+
+fn format_of(path: &Path) -> Format {
+    match path.extension().and_then(|x| x.to_str()) {
+        Some("png") => Format::Png,
+        Some("json") => Format::Json,
+        _ => Format::Svg,
+    }
+}
+
+pub fn run(renderer: &ProgressBarRenderer, args: RenderArgs) -> anyhow::Result<()> {
+    let output = args.output.clone();
+    let format = args.format
+        .or_else(|| output.as_deref().map(format_of))
+        .unwrap_or(Format::Svg);
+    let spec = args.spec();
+    let ctx = renderer.context(&spec)?;
+    let body = match format {
+        Format::Json => serde_json::to_vec_pretty(&ctx)?,
+        Format::Svg => renderer.render_context(&ctx)?.into_bytes(),
+        Format::Png => output::rasterize(&renderer.render_context(&ctx)?, spec.density.unwrap_or(1))?,
+    };
+    match output {
+        Some(path) => std::fs::write(&path, body)
+            .with_context(|| format!("failed to write {}", path.display()))?,
+        None => std::io::stdout().write_all(&body)?,
+    }
+    Ok(())
+}
+
+/// Reports the problems of the template given with `-f`, which may extend, include or import
+/// those of the `templates` directory, failing if there are any.
+pub fn check_template(
+    sources: &TemplateSources,
+    globals: &BTreeMap<String, serde_json::Value>,
+    limits: RenderLimits,
+) -> anyhow::Result<()> {
+    let Some(path) = &sources.file else { bail!("the template to check must be given with `-f`") };
+    let files = sources.read()?;
+    let template = files.default.unwrap_or_default();
+    let problems = progress_bar::check_template_with(&template, &files.named, &files.partials, globals, limits);
+    for problem in &problems {
+        eprintln!("{}: {}", path.display(), problem);
+    }
+    if !problems.is_empty() {
+        bail!("{} has {} problem(s)", path.display(), problems.len());
+    }
+    println!("{} is fine", path.display());
+    Ok(())
+}
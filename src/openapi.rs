@@ -0,0 +1,372 @@
+//! The OpenAPI 3 document of the routes rendering bars, served at `/openapi.json`, with every
+//! query parameter, the formats of the responses and the JSON body of errors. `--docs` serves
+//! Swagger UI showing it at `/docs`.
+use serde_json::{json, Value};
+use progress_bar::logos;
+
+
+/// Swagger UI loading the document relative to `/docs`, so it works behind path prefixes too.
+pub const DOCS_PAGE: &str = include_str!("../resources/docs.html");
+
+/// The values a parameter takes.
+#[derive(Clone, Copy)]
+enum Schema {
+    String,
+    Number,
+    Integer { min: u32, max: u32 },
+    Boolean,
+    Enum(&'static [&'static str]),
+    /// One of the bundled logos.
+    Logo,
+}
+
+impl Schema {
+    fn json(self) -> Value {
+        match self {
+            Schema::String => json!({ "type": "string" }),
+            Schema::Number => json!({ "type": "number" }),
+            Schema::Integer { min, max } => json!({ "type": "integer", "minimum": min, "maximum": max }),
+            Schema::Boolean => json!({ "type": "boolean" }),
+            Schema::Enum(values) => json!({ "type": "string", "enum": values }),
+            Schema::Logo => json!({ "type": "string", "enum": logos::NAMES }),
+        }
+    }
+}
+
+/// The parameters of [`progress_bar::BarSpec`], as [`crate::query`] accepts them.
+const SPEC_PARAMETERS: &[(&str, Schema, &str)] = &[
+    ("title", Schema::String, "Text left of the progress."),
+    ("title_width", Schema::Integer { min: 0, max: 2000 }, "Width of the title in pixels."),
+    ("title_color", Schema::String, "Color of the title, e.g. `#428bca`, `green` or `rgb(68, 204, 17)`."),
+    ("logo", Schema::Logo, "Bundled logo drawn left of the title."),
+    ("logo_data", Schema::String, "Base64 data URI of an SVG or PNG logo, replacing `logo`."),
+    ("scale", Schema::Number, "Sugar for `min=0&max=scale`."),
+    ("progress", Schema::Number, "Alias of `value`."),
+    ("value", Schema::Number, "The value placed within the range, from 0 to 100 by default."),
+    ("min", Schema::Number, "Start of the range, 0 by default."),
+    ("max", Schema::Number, "End of the range, 100 by default."),
+    ("baseline", Schema::Number, "Value the difference to is shown next to the bar, e.g. `+3.0%`."),
+    ("done", Schema::Number, "Bytes done of `total`, shown like `700 MiB / 2 GiB` instead of the percentage."),
+    ("total", Schema::Number, "Bytes in total."),
+    ("units", Schema::Enum(&["bytes", "si"]), "Powers of 1024 or of 1000 for `done` and `total`."),
+    ("start", Schema::String, "ISO date or unix timestamp the progress is computed from at render time, with `end`."),
+    ("end", Schema::String, "ISO date or unix timestamp the progress reaches 100% at."),
+    ("tz", Schema::String, "Time zone of dates without an offset, UTC by default."),
+    ("mode", Schema::Enum(&["progress", "countdown"]), "`countdown` shows the time left until `until`."),
+    ("until", Schema::String, "Deadline of countdowns."),
+    ("source", Schema::String, "URL of a JSON document the value is read from."),
+    ("value_path", Schema::String, "JSONPath of the value in `source`, `$` by default."),
+    ("transform", Schema::String, "Name of a configured pipeline the value is passed through."),
+    ("overflow", Schema::Enum(&["clamp", "error", "allow"]), "Treatment of values outside of the range."),
+    ("progress_width", Schema::Integer { min: 1, max: 2000 }, "Width of the progress in pixels."),
+    ("progress_color", Schema::String, "Color of the progress, chosen by the value by default."),
+    ("palette", Schema::Enum(&["default", "colorblind"]), "Colors of the progress unless `progress_color` is given."),
+    ("color_mode", Schema::Enum(&["bands", "interpolate"]),
+     "`interpolate` blends the colors of the progress between the stops instead of switching at each."),
+    ("color_stops", Schema::String,
+     "Colors at percentages of the range like `0:#d9534f,100:#5cb85c`, those of the palette by default."),
+    ("color_space", Schema::Enum(&["lab", "hsl"]), "Space the colors are blended in, `lab` by default."),
+    ("patterns", Schema::Boolean, "Hatches the progress of low and medium values."),
+    ("suffix", Schema::String, "Appended to the value, `%` by default, nothing for the `ratio` and `count` presets."),
+    ("preset", Schema::Enum(&["percent", "ratio", "count"]),
+     "Shows the share of the range like `73%` or `0.73`, the latter of a range from 0 to 1 by default, or the value of the maximum like `146/200`."),
+    ("locale", Schema::String, "Language tag the value is formatted for, e.g. `de-DE` writing `73,5`."),
+    ("label", Schema::String, "Text replacing the formatted value."),
+    ("label_position", Schema::Enum(&["center", "inside", "outside", "none"]), "Where the value is drawn."),
+    ("link", Schema::String, "http(s) link of the whole bar, or of the title with `link2`."),
+    ("link2", Schema::String, "http(s) link of the progress."),
+    ("aria_label", Schema::String, "Text screen readers announce, `<title>: <value>` by default."),
+    ("dir", Schema::Enum(&["ltr", "rtl"]), "`rtl` mirrors the bar."),
+    ("adaptive", Schema::Boolean, "Switches to darker colors for viewers preferring a dark color scheme."),
+    ("template", Schema::String, "Name of a configured template drawing the bar."),
+    ("format", Schema::Enum(&["svg", "png", "json"]), "Output format, negotiated with the `Accept` header by default."),
+    ("density", Schema::Integer { min: 1, max: progress_bar::MAX_DENSITY }, "Pixels per pixel of PNGs, e.g. 2 for high-DPI displays."),
+    ("blackhole", Schema::String, "Ignored, so that image extensions appended to the URL by some tools do no harm."),
+];
+
+/// The parameters read by middleware, valid for every route.
+const SERVER_PARAMETERS: &[(&str, Schema, &str)] = &[
+    ("dry_run", Schema::Boolean, "Reports what would be rendered as JSON instead."),
+    ("error", Schema::Enum(&["svg", "json", "text"]),
+     "Format of errors, an SVG badge on routes rendering bars and plain text elsewhere by default."),
+    ("simulate", Schema::Enum(&["slow", "500", "garbage"]), "Simulates a failure, if enabled with `[chaos]`."),
+];
+
+fn parameter(name: &str, schema: Schema, description: &str) -> Value {
+    json!({ "name": name, "in": "query", "description": description, "schema": schema.json() })
+}
+
+fn path_parameter(name: &str, description: &str) -> Value {
+    json!({ "name": name, "in": "path", "required": true, "description": description, "schema": { "type": "string" } })
+}
+
+fn reference(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/parameters/{name}") })
+}
+
+/// A route responding with a bar in the negotiated format, taking `parameters` and those of
+/// the middleware, and the spec in the query if `with_spec`.
+fn bar_route(summary: &str, description: &str, parameters: Vec<Value>, with_spec: bool) -> Value {
+    let spec = if with_spec { SPEC_PARAMETERS } else { &[] };
+    let common = spec.iter().chain(SERVER_PARAMETERS).map(|(name, _, _)| reference(name));
+    json!({
+        "summary": summary,
+        "description": description,
+        "parameters": parameters.into_iter().chain(common).collect::<Vec<_>>(),
+        "responses": {
+            "200": { "$ref": "#/components/responses/Bar" },
+            "4XX": { "$ref": "#/components/responses/Error" },
+            "5XX": { "$ref": "#/components/responses/Error" },
+        },
+    })
+}
+
+const EXTENSIONS: &str = "`.svg`, `.png` or `.json` may be appended to select the format, \
+    preceded by e.g. `@2x` to set the density.";
+
+/// The OpenAPI document, describing the server `version`.
+pub fn document(version: &str) -> Value {
+    let bar_schema = {
+        let mut properties: serde_json::Map<String, Value> = SPEC_PARAMETERS.iter()
+            .filter(|(name, _, _)| !matches!(*name, "format" | "blackhole"))
+            .map(|(name, schema, description)| {
+                let mut schema = schema.json();
+                schema["description"] = (*description).into();
+                (name.to_string(), schema)
+            })
+            .collect();
+        properties.insert("states".into(), json!({
+            "type": "array",
+            "description": "Named stages replacing the value once it reaches them.",
+            "items": { "type": "object", "properties": { "name": { "type": "string" }, "from": { "type": "number" } } },
+        }));
+        properties.insert("components".into(), json!({
+            "type": "array",
+            "description": "Bars and sources the value is the weighted average of.",
+            "items": { "type": "object" },
+        }));
+        json!({ "type": "object", "properties": properties })
+    };
+    let errors = json!({
+        "4XX": { "$ref": "#/components/responses/Error" },
+        "5XX": { "$ref": "#/components/responses/Error" },
+    });
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "progress-bar",
+            "version": version,
+            "description": "Progress bars and badges rendered as SVG, PNG or their JSON template context.",
+        },
+        "paths": {
+            "/render": { "get": bar_route("Render a bar", "Renders the bar described by the query.", vec![], true) },
+            "/{progress}": {
+                "get": bar_route("Render a bar given in the path",
+                    &format!("Like `/render?progress=`, for places mangling query strings. {EXTENSIONS}"),
+                    vec![path_parameter("progress", "The value, a decimal number.")], true),
+            },
+            "/{title}/{progress}": {
+                "get": bar_route("Render a titled bar given in the path",
+                    &format!("Like `/render?title=&progress=`, unless the title names another route. {EXTENSIONS}"),
+                    vec![path_parameter("title", "The title."), path_parameter("progress", "The value, a decimal number.")], true),
+            },
+            "/bars/{id}": {
+                "get": bar_route("Render a stored bar",
+                    &format!("Renders the stored bar `id`, resolving the values of composed ones. {EXTENSIONS}"),
+                    vec![path_parameter("id", "Letters, digits, `-` and `_`.")], false),
+                "put": {
+                    "summary": "Store a bar",
+                    "security": [{ "bearer": [] }],
+                    "parameters": [path_parameter("id", "Letters, digits, `-` and `_`.")],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/BarSpec" } } } },
+                    "responses": {
+                        "200": { "description": "The bar was replaced." },
+                        "201": { "description": "The bar was created." },
+                        "4XX": errors["4XX"],
+                    },
+                },
+                "delete": {
+                    "summary": "Delete a stored bar",
+                    "security": [{ "bearer": [] }],
+                    "parameters": [path_parameter("id", "Letters, digits, `-` and `_`.")],
+                    "responses": { "204": { "description": "The bar was deleted." }, "4XX": errors["4XX"] },
+                },
+            },
+            "/bars/{id}/trend.svg": {
+                "get": {
+                    "summary": "Chart the latest values of a stored bar",
+                    "description": "A line chart of the values the bar was set to, followed by its current value. \
+                        `.png` and `.json` instead of `.svg` select the other formats.",
+                    "parameters": [
+                        path_parameter("id", "Letters, digits, `-` and `_`."),
+                        { "name": "points", "in": "query", "description": "The number of latest values, 30 by default.",
+                          "schema": { "type": "integer", "minimum": 1 } },
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "The chart, or the values with their times.",
+                            "content": {
+                                "image/svg+xml": { "schema": { "type": "string" } },
+                                "image/png": { "schema": { "type": "string", "format": "binary" } },
+                                "application/json": { "schema": { "type": "object", "properties": {
+                                    "points": { "type": "array", "items": { "$ref": "#/components/schemas/HistoryPoint" } },
+                                } } },
+                            },
+                        },
+                        "4XX": errors["4XX"],
+                        "5XX": errors["5XX"],
+                    },
+                },
+            },
+            "/bars/{id}/snapshots": {
+                "post": {
+                    "summary": "Freeze the current state of a stored bar",
+                    "security": [{ "bearer": [] }],
+                    "parameters": [path_parameter("id", "Letters, digits, `-` and `_`.")],
+                    "responses": { "201": { "description": "The snapshot was created at the `Location`." }, "4XX": errors["4XX"] },
+                },
+            },
+            "/hooks/{id}": {
+                "post": {
+                    "summary": "Set a stored bar from a webhook",
+                    "description": "Sets the value of bar `id` to the field of the JSON payload selected by the \
+                        `value_path` of its hook, configured as `[hooks.<id>]`.",
+                    "security": [{ "signature": [] }],
+                    "parameters": [path_parameter("id", "Letters, digits, `-` and `_`.")],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "type": "object" } } } },
+                    "responses": {
+                        "200": {
+                            "description": "The bar was set.",
+                            "content": { "application/json": { "schema": { "type": "object", "properties": {
+                                "id": { "type": "string" }, "value": { "type": "number" },
+                            } } } },
+                        },
+                        "4XX": errors["4XX"],
+                    },
+                },
+            },
+            "/snapshots/{sid}": {
+                "get": bar_route("Render a snapshot", &format!("Renders a frozen copy of a stored bar. {EXTENSIONS}"),
+                    vec![path_parameter("sid", "Hexadecimal id of the snapshot.")], false),
+            },
+            "/b/{name}": {
+                "get": bar_route("Render a badge of the badges file", EXTENSIONS,
+                    vec![path_parameter("name", "Letters, digits, `-` and `_`.")], false),
+            },
+            "/crates/{name}": {
+                "get": bar_route("Render the downloads of a crate", "Shows the downloads as progress towards a goal.", vec![
+                    path_parameter("name", "Name of the crate."),
+                    parameter("goal", Schema::Number, "Downloads to reach, the next power of ten by default."),
+                    parameter("period", Schema::Enum(&["all", "recent"]), "Downloads counted, `all` by default."),
+                ], true),
+            },
+            "/npm/{name}": {
+                "get": bar_route("Render the downloads of an npm package", "Shows the downloads as progress towards a goal.", vec![
+                    path_parameter("name", "Name of the package, scoped ones like `@scope/name` included."),
+                    parameter("goal", Schema::Number, "Downloads to reach, the next power of ten by default."),
+                    parameter("period", Schema::Enum(&["last-week", "last-month", "last-year"]), "Downloads counted."),
+                ], true),
+            },
+            "/github/{owner}/{repo}/milestone/{number}": {
+                "get": bar_route("Render the progress of a GitHub milestone", "The share of its closed issues.", vec![
+                    path_parameter("owner", "Owner of the repository."),
+                    path_parameter("repo", "Name of the repository."),
+                    path_parameter("number", "Number of the milestone."),
+                ], true),
+            },
+            "/shields": {
+                "get": bar_route("Render a shields.io endpoint", "Draws the message of the endpoint JSON at `url`.", vec![
+                    json!({ "name": "url", "in": "query", "required": true, "description": "URL of the endpoint JSON.",
+                            "schema": { "type": "string" } }),
+                ], true),
+            },
+        },
+        "components": {
+            "parameters": SPEC_PARAMETERS.iter().chain(SERVER_PARAMETERS)
+                .map(|(name, schema, description)| (name.to_string(), parameter(name, *schema, description)))
+                .collect::<serde_json::Map<_, _>>(),
+            "responses": {
+                "Bar": {
+                    "description": "The bar, or its template context with `format=json`.",
+                    "content": {
+                        "image/svg+xml": { "schema": { "type": "string" } },
+                        "image/png": { "schema": { "type": "string", "format": "binary" } },
+                        "application/json": { "schema": { "type": "object", "description": "The template context." } },
+                    },
+                },
+                "Error": {
+                    "description": "The error, as a JSON body with `error=json` and as a badge with `error=svg`.",
+                    "content": {
+                        "application/json": { "schema": { "$ref": "#/components/schemas/Error" } },
+                        "image/svg+xml": { "schema": { "type": "string" } },
+                        "text/plain": { "schema": { "type": "string" } },
+                    },
+                },
+            },
+            "schemas": {
+                "BarSpec": bar_schema,
+                "HistoryPoint": {
+                    "type": "object",
+                    "properties": { "at": { "type": "string", "format": "date-time" }, "value": { "type": "number" } },
+                },
+                "Error": {
+                    "type": "object",
+                    "required": ["status", "error"],
+                    "properties": {
+                        "status": { "type": "integer", "description": "The HTTP status." },
+                        "error": { "type": "string", "description": "What went wrong." },
+                        "fields": {
+                            "type": "array",
+                            "description": "The invalid query parameters, if any.",
+                            "items": { "$ref": "#/components/schemas/FieldError" },
+                        },
+                        "request_id": { "type": "string", "description": "The `X-Request-Id` of the request." },
+                    },
+                },
+                "FieldError": {
+                    "type": "object",
+                    "required": ["field", "message"],
+                    "properties": { "field": { "type": "string" }, "message": { "type": "string" } },
+                },
+            },
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer", "description": "The configured `token` of the bars." },
+                "signature": {
+                    "type": "apiKey", "in": "header", "name": "X-Hub-Signature-256",
+                    "description": "`sha256=` followed by the hex HMAC-SHA256 of the body with the secret of the hook, \
+                        in the configured `signature_header` if any.",
+                },
+            },
+        },
+    })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::{SERVER_KEYS, SPEC_KEYS};
+
+    #[test]
+    fn every_parameter_is_documented() {
+        let documented: Vec<&str> = SPEC_PARAMETERS.iter().map(|(name, _, _)| *name).collect();
+        assert_eq!(documented, SPEC_KEYS);
+        let documented: Vec<&str> = SERVER_PARAMETERS.iter().map(|(name, _, _)| *name).collect();
+        assert_eq!(documented, SERVER_KEYS);
+
+        // every field of the spec but the lists, which only JSON bodies can hold.
+        let spec = serde_json::to_value(progress_bar::BarSpec::default()).unwrap();
+        let mut fields: Vec<&str> = spec.as_object().unwrap().keys()
+            .map(String::as_str)
+            .filter(|x| !matches!(*x, "components" | "states"))
+            .collect();
+        let mut documented: Vec<&str> = SPEC_PARAMETERS.iter().map(|(name, _, _)| *name).collect();
+        fields.sort_unstable();
+        documented.sort_unstable();
+        assert_eq!(documented, fields);
+
+        let document = document("1.0.0");
+        assert_eq!(document["components"]["parameters"]["units"]["schema"]["enum"], json!(["bytes", "si"]));
+        assert!(document["paths"]["/render"]["get"]["parameters"].as_array().unwrap().len() > 40);
+    }
+}
@@ -0,0 +1,230 @@
+//! Output formats of the rendered bars and their negotiation.
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use actix_web::{http, HttpRequest, HttpResponse, Responder};
+use actix_web::body::BoxBody;
+use actix_web::http::header::{self, Header};
+use resvg::{tiny_skia, usvg};
+use sha2::{Digest, Sha256};
+pub use progress_bar::Format;
+
+
+/// The formats of `mime`, by preference for wildcards.
+fn formats_of_mime(mime: &mime::Mime) -> &'static [Format] {
+    match (mime.type_(), mime.subtype()) {
+        (mime::STAR, mime::STAR) | (mime::IMAGE, mime::STAR) => &[Format::Svg, Format::Png],
+        (mime::IMAGE, mime::SVG) => &[Format::Svg],
+        (mime::IMAGE, mime::PNG) => &[Format::Png],
+        (mime::APPLICATION, mime::JSON) => &[Format::Json],
+        _ => &[],
+    }
+}
+
+/// Resolves the format from the `format` query parameter, falling back to the most
+/// preferred supported type of the `Accept` header which it does not refuse with `q=0`. SVG is
+/// the default.
+pub fn negotiate(param: Option<Format>, req: &HttpRequest) -> Result<Format, NotAcceptable> {
+    if let Some(x) = param {
+        return Ok(x);
+    }
+    if !req.headers().contains_key(header::ACCEPT) {
+        return Ok(Format::Svg);
+    }
+    let accept = header::Accept::parse(req).map_err(|_| NotAcceptable)?;
+    if accept.is_empty() {
+        return Ok(Format::Svg);
+    }
+    // types of quality 0 are refused, even when a wildcard accepts them.
+    let (refused, accepted): (Vec<_>, Vec<_>) = accept.0.into_iter()
+        .partition(|x| x.quality == header::Quality::ZERO);
+    let refused: Vec<&Format> = refused.iter()
+        .filter(|x| x.item.subtype() != mime::STAR)
+        .flat_map(|x| formats_of_mime(&x.item))
+        .collect();
+    header::Accept(accepted).ranked().iter()
+        .flat_map(formats_of_mime)
+        .find(|x| !refused.contains(x))
+        .copied()
+        .ok_or(NotAcceptable)
+}
+
+#[derive(Debug)]
+pub struct NotAcceptable;
+
+impl fmt::Display for NotAcceptable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "none of image/svg+xml, image/png and application/json is acceptable")
+    }
+}
+
+impl std::error::Error for NotAcceptable {}
+
+fn fonts() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+    FONTS.get_or_init(|| {
+        let mut fonts = usvg::fontdb::Database::new();
+        fonts.load_system_fonts();
+        Arc::new(fonts)
+    }).clone()
+}
+
+/// Pixels per meter of a CSS pixel, which is 1/96 inch.
+const CSS_PIXELS_PER_METER: u32 = 3780;
+
+/// Rasterizes `svg` with `density` pixels per pixel of its declared size. The PNG records
+/// the density, so viewers honoring it show the image at the declared size.
+pub fn rasterize(svg: &str, density: u32) -> anyhow::Result<Vec<u8>> {
+    let options = usvg::Options {
+        fontdb: fonts(),
+        ..Default::default()
+    };
+    let tree = usvg::Tree::from_str(svg, &options)?;
+    let (width, height) = progress_bar::pixel_size(tree.size().width(), tree.size().height(), density);
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| anyhow::anyhow!("cannot allocate an image of {width}x{height}"))?;
+    let scale = density as f32;
+    resvg::render(&tree, tiny_skia::Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+
+    let data: Vec<u8> = pixmap.pixels().iter()
+        .flat_map(|x| {
+            let x = x.demultiply();
+            [x.red(), x.green(), x.blue(), x.alpha()]
+        })
+        .collect();
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let pixels_per_meter = CSS_PIXELS_PER_METER * density;
+    encoder.set_pixel_dims(Some(png::PixelDimensions {
+        xppu: pixels_per_meter,
+        yppu: pixels_per_meter,
+        unit: png::Unit::Meter,
+    }));
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(png)
+}
+
+/// The entity tag of a bar rendered from `ctx` by templates with `fingerprint`, in `format` at
+/// `density`.
+pub fn etag(ctx: &minijinja::value::Value, fingerprint: &str, format: Format, density: u32) -> header::EntityTag {
+    let hash = Sha256::new()
+        .chain_update(serde_json::to_vec(&(ctx, format)).unwrap_or_default())
+        .chain_update(fingerprint)
+        .chain_update(density.to_be_bytes())
+        .finalize();
+    header::EntityTag::new_strong(hash.iter().take(16).map(|x| format!("{x:02x}")).collect())
+}
+
+/// Codings of `--compression`, suffixed to the entity tags of the bodies encoded with them,
+/// e.g. `"<hash>-br"`, so that every encoding has its own tag.
+pub const CODINGS: [&str; 2] = ["gzip", "br"];
+
+/// Whether the client of `req` already has the representation tagged `etag`, in any of the
+/// [`CODINGS`].
+pub fn is_fresh(req: &HttpRequest, etag: &header::EntityTag) -> bool {
+    let decoded = |x: &header::EntityTag| CODINGS.iter()
+        .find_map(|coding| x.tag().strip_suffix(coding)?.strip_suffix('-'))
+        .unwrap_or(x.tag())
+        .to_string();
+    match header::IfNoneMatch::parse(req) {
+        Ok(header::IfNoneMatch::Any) => true,
+        Ok(header::IfNoneMatch::Items(tags)) => tags.iter().any(|x| decoded(x) == etag.tag()),
+        Err(_) => false,
+    }
+}
+
+pub enum Body {
+    Svg(String),
+    Png(Vec<u8>),
+    Json(serde_json::Value),
+}
+
+/// A successfully rendered bar in its negotiated format.
+pub struct Rendered {
+    pub body: Body,
+    pub cache_control: Option<String>,
+}
+
+impl Responder for Rendered {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse {
+        let mut response = HttpResponse::build(http::StatusCode::OK);
+        if let Some(cache_control) = self.cache_control {
+            response.insert_header((header::CACHE_CONTROL, cache_control));
+        }
+        // the body differs with the negotiated format.
+        response.insert_header((header::VARY, "Accept"));
+        match self.body {
+            Body::Svg(x) => response.content_type("image/svg+xml; charset=utf-8").body(x),
+            Body::Png(x) => response.content_type("image/png").body(x),
+            Body::Json(x) => response.json(x),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn formats_are_negotiated_by_preference() {
+        let negotiated = |param: Option<Format>, accept: Option<&str>| {
+            let mut req = TestRequest::default();
+            if let Some(accept) = accept {
+                req = req.insert_header((header::ACCEPT, accept));
+            }
+            negotiate(param, &req.to_http_request()).ok()
+        };
+        for (accept, expected) in [
+            (None, Some(Format::Svg)),
+            (Some(""), Some(Format::Svg)),
+            (Some("image/png"), Some(Format::Png)),
+            (Some("image/svg+xml;q=0.5, image/png;q=0.8"), Some(Format::Png)),
+            (Some("image/png;q=0.1, application/json"), Some(Format::Json)),
+            (Some("text/html, image/png;q=0"), None),
+            (Some("text/html, */*;q=0.1"), Some(Format::Svg)),
+            (Some("image/svg+xml;q=0, */*"), Some(Format::Png)),
+            (Some("image/svg+xml;q=0, image/png;q=0, image/*"), None),
+            (Some("*/*;q=0"), None),
+            (Some("image/*"), Some(Format::Svg)),
+            (Some("image/webp, image/png;q=0.9, image/*;q=0.8"), Some(Format::Png)),
+            (Some("text/html"), None),
+            (Some("application/xml, text/*"), None),
+        ] {
+            assert_eq!(negotiated(None, accept), expected, "{accept:?}");
+        }
+        assert_eq!(negotiated(Some(Format::Json), Some("image/png")), Some(Format::Json));
+        assert_eq!(negotiated(Some(Format::Png), Some("text/html")), Some(Format::Png));
+    }
+
+    #[test]
+    fn if_none_match_lists_and_wildcards_are_fresh() {
+        let etag = header::EntityTag::new_strong("abc".to_string());
+        let fresh = |if_none_match: Option<&str>| {
+            let mut req = TestRequest::default();
+            if let Some(x) = if_none_match {
+                req = req.insert_header((header::IF_NONE_MATCH, x));
+            }
+            is_fresh(&req.to_http_request(), &etag)
+        };
+        for (if_none_match, expected) in [
+            (None, false),
+            (Some("*"), true),
+            (Some(r#""abc""#), true),
+            (Some(r#"W/"abc""#), true),
+            (Some(r#""old", "abc""#), true),
+            (Some(r#""old", "older""#), false),
+            (Some(r#""abc-gzip""#), true),
+            (Some(r#""old", "abc-br""#), true),
+            (Some(r#""abc-deflate""#), false),
+            (Some(r#""ab""#), false),
+            (Some("abc"), false),
+        ] {
+            assert_eq!(fresh(if_none_match), expected, "{if_none_match:?}");
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Download counts of crates.io and npm packages, served at `/crates/{name}` and
+//! `/npm/{name}` as progress towards a download goal.
+use std::fmt;
+use std::time::Duration;
+use actix_web::http::StatusCode;
+use reqwest::Client;
+use serde::Deserialize;
+use crate::cache::TtlCache;
+use crate::config::PackagesConfig;
+use crate::health::HealthRegistry;
+
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Registry {
+    Crates,
+    Npm,
+}
+
+impl Registry {
+    /// Name of the registry in the [`HealthRegistry`].
+    pub fn health_name(self) -> &'static str {
+        match self {
+            Registry::Crates => "crates",
+            Registry::Npm => "npm",
+        }
+    }
+}
+
+/// The downloads which are counted. crates.io knows `all` and `recent` (the last 90 days),
+/// npm knows `last-week`, `last-month` and `last-year`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Period {
+    All,
+    Recent,
+    LastWeek,
+    LastMonth,
+    LastYear,
+}
+
+#[derive(Debug)]
+pub enum PackageError {
+    UnsupportedPeriod(Registry, Period),
+    Fetch(reqwest::Error),
+}
+
+impl fmt::Display for PackageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageError::UnsupportedPeriod(registry, period) =>
+                write!(f, "{} does not count downloads of period {period:?}", registry.health_name()),
+            PackageError::Fetch(e) => write!(f, "failed to fetch the downloads: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for PackageError {}
+
+impl PackageError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            PackageError::UnsupportedPeriod(..) => StatusCode::BAD_REQUEST,
+            PackageError::Fetch(e) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) => StatusCode::NOT_FOUND,
+            PackageError::Fetch(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: CrateInfo,
+}
+
+#[derive(Deserialize)]
+struct CrateInfo {
+    downloads: u64,
+    recent_downloads: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct NpmResponse {
+    downloads: u64,
+}
+
+pub struct Packages {
+    config: PackagesConfig,
+    downloads: TtlCache<(Registry, String, Period), u64>,
+}
+
+impl Packages {
+    pub fn new(config: PackagesConfig) -> Self {
+        let downloads = TtlCache::new(Duration::from_secs(config.cache_ttl));
+        Packages { config, downloads }
+    }
+
+    pub fn cache_ttl(&self) -> u64 {
+        self.downloads.ttl().as_secs()
+    }
+
+    pub fn default_period(registry: Registry) -> Period {
+        match registry {
+            Registry::Crates => Period::All,
+            Registry::Npm => Period::LastMonth,
+        }
+    }
+
+    pub async fn downloads(
+        &self,
+        client: &Client,
+        health: &HealthRegistry,
+        registry: Registry,
+        name: &str,
+        period: Period,
+    ) -> Result<u64, PackageError> {
+        let key = (registry, name.to_string(), period);
+        if let Some(x) = self.downloads.get(&key) {
+            return Ok(x);
+        }
+
+        let url = match (registry, period) {
+            (Registry::Crates, Period::All | Period::Recent) =>
+                format!("{}/api/v1/crates/{name}", self.config.crates_api_url.trim_end_matches('/')),
+            (Registry::Npm, Period::LastWeek | Period::LastMonth | Period::LastYear) => {
+                let period = match period {
+                    Period::LastWeek => "last-week",
+                    Period::LastMonth => "last-month",
+                    _ => "last-year",
+                };
+                format!("{}/downloads/point/{period}/{name}", self.config.npm_api_url.trim_end_matches('/'))
+            },
+            _ => return Err(PackageError::UnsupportedPeriod(registry, period)),
+        };
+        let result = async {
+            let response = client.get(&url).send().await?.error_for_status()?;
+            Ok(match registry {
+                Registry::Crates => {
+                    let info = response.json::<CrateResponse>().await?.krate;
+                    match period {
+                        Period::Recent => info.recent_downloads.unwrap_or_default(),
+                        _ => info.downloads,
+                    }
+                },
+                Registry::Npm => response.json::<NpmResponse>().await?.downloads,
+            })
+        }.await;
+        health.record(registry.health_name(), &url, result.as_ref().map(|_| ()));
+
+        let downloads = result.map_err(PackageError::Fetch)?;
+        self.downloads.insert(key, downloads);
+        Ok(downloads)
+    }
+}
+
+/// The next power of ten above `downloads`, the goal when none is given.
+pub fn next_milestone(downloads: u64) -> u64 {
+    let mut goal = 10;
+    while goal <= downloads && goal < u64::MAX / 10 {
+        goal *= 10;
+    }
+    goal
+}
+
+/// Formats counts compactly, e.g. `950`, `12.3k` or `4M`.
+pub fn humanize_count(count: u64) -> String {
+    const UNITS: [&str; 3] = ["k", "M", "G"];
+    if count < 1_000 {
+        return count.to_string();
+    }
+    let round = |x: f64| if x < 100.0 { (x * 10.0).round() / 10.0 } else { x.round() };
+    let (mut value, mut unit) = (count as f64 / 1e3, 0);
+    // the unit is chosen after rounding, so that 999 999 is `1M` rather than `1000k`.
+    while round(value) >= 1e3 && unit + 1 < UNITS.len() {
+        value /= 1e3;
+        unit += 1;
+    }
+    let formatted = if value < 100.0 { format!("{value:.1}") } else { format!("{value:.0}") };
+    format!("{}{}", formatted.trim_end_matches(".0"), UNITS[unit])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_are_rounded_into_the_next_unit() {
+        for (count, text) in [
+            (950, "950"),
+            (1_000, "1k"),
+            (12_345, "12.3k"),
+            (99_960, "100k"),
+            (999_499, "999k"),
+            (999_999, "1M"),
+            (4_000_000, "4M"),
+            (999_950_000, "1G"),
+            (2_500_000_000_000, "2500G"),
+        ] {
+            assert_eq!(humanize_count(count), text, "{count}");
+        }
+    }
+}
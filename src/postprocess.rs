@@ -0,0 +1,224 @@
+//! Steps applied in order to the rendered SVG, configured per deployment, e.g.
+//!
+//! ```toml
+//! postprocess = ["strip_metadata", "minify", { comment = "CC BY 4.0, example.com" }]
+//! ```
+//!
+//! `optimize` combines the steps shrinking the SVG, by about a quarter for the default bars.
+use serde::Deserialize;
+
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostProcessor {
+    /// Drops the whitespace between tags and the indentation of lines.
+    Minify,
+    /// Removes comments and `<metadata>` elements.
+    StripMetadata,
+    /// Removes gradients, patterns and other definitions whose `id` is never referenced.
+    StripUnusedDefs,
+    /// Removes the XML declaration, attributes set to their default, the unused xlink
+    /// namespace and empty groups.
+    StripRedundant,
+    /// Strips metadata, unused definitions and redundant markup, then minifies.
+    Optimize,
+    /// Adds an XML comment, e.g. a license, after the XML declaration.
+    Comment(String),
+    /// Adds a faint text to the bottom right corner of the bar.
+    Watermark(String),
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn minify(svg: &str) -> String {
+    let mut out = String::with_capacity(svg.len());
+    for line in svg.lines().map(str::trim).filter(|x| !x.is_empty()) {
+        // text continued on the next line keeps a separating space.
+        if !(out.is_empty() || (out.ends_with('>') && line.starts_with('<'))) {
+            out.push(' ');
+        }
+        out.push_str(line);
+    }
+    out.replace(" />", "/>")
+}
+
+/// Elements which only draw where they are referenced.
+const DEF_ELEMENTS: &[&str] = &[
+    "linearGradient", "radialGradient", "pattern", "clipPath", "mask", "filter", "marker", "symbol",
+];
+
+/// The `id` attribute of the opening tag `tag`.
+fn id_of(tag: &str) -> Option<&str> {
+    let start = tag.find(" id=\"")? + 5;
+    tag[start..].find('"').map(|end| &tag[start..start + end])
+}
+
+fn strip_unused_defs(svg: &str) -> String {
+    let mut svg = svg.to_string();
+    // removing a definition may leave others it referenced unused.
+    loop {
+        let before = svg.len();
+        for name in DEF_ELEMENTS {
+            svg = remove_unused(&svg, name);
+        }
+        svg = svg.replace("<defs/>", "");
+        svg = remove_empty(&svg, "<defs>", "</defs>");
+        if svg.len() == before {
+            return svg;
+        }
+    }
+}
+
+/// Removes the `name` elements with an `id` referenced nowhere in `svg`.
+fn remove_unused(svg: &str, name: &str) -> String {
+    let open = format!("<{name}");
+    let close = format!("</{name}>");
+    let mut out = String::with_capacity(svg.len());
+    let mut rest = svg;
+    while let Some(i) = rest.find(&open) {
+        out.push_str(&rest[..i]);
+        let element = &rest[i..];
+        let Some(tag_end) = element.find('>') else { break };
+        let end = match element[..tag_end].ends_with('/') {
+            true => tag_end + 1,
+            false => element.find(&close).map_or(element.len(), |j| j + close.len()),
+        };
+        let used = id_of(&element[..tag_end]).is_none_or(|id| {
+            svg.contains(&format!("url(#{id})")) || svg.contains(&format!("\"#{id}\""))
+        });
+        if used {
+            out.push_str(&element[..end]);
+        }
+        rest = &element[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Attributes which are the same when left out.
+const DEFAULT_ATTRIBUTES: &[&str] = &[
+    " version=\"1.1\"", " preserveAspectRatio=\"xMidYMid\"", " x=\"0\"", " y=\"0\"",
+];
+
+fn strip_redundant(svg: &str) -> String {
+    let mut svg = match svg.trim_start().starts_with("<?xml") {
+        true => remove_between(svg, "<?xml", "?>").trim_start().to_string(),
+        false => svg.to_string(),
+    };
+    for attribute in DEFAULT_ATTRIBUTES {
+        svg = svg.replace(attribute, "");
+    }
+    if !svg.contains("xlink:") {
+        svg = svg.replace(" xmlns:xlink=\"http://www.w3.org/1999/xlink\"", "");
+    }
+    remove_empty(&svg, "<g>", "</g>")
+}
+
+/// Removes each `start` followed by nothing but whitespace and `end`.
+fn remove_empty(svg: &str, start: &str, end: &str) -> String {
+    let mut out = String::with_capacity(svg.len());
+    let mut rest = svg;
+    while let Some(i) = rest.find(start) {
+        let after = &rest[i + start.len()..];
+        let inner = after.len() - after.trim_start().len();
+        out.push_str(&rest[..i]);
+        match after[inner..].starts_with(end) {
+            true => rest = &after[inner + end.len()..],
+            false => {
+                out.push_str(start);
+                rest = after;
+            },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Removes everything from each `start` to the following `end`, inclusive.
+fn remove_between(svg: &str, start: &str, end: &str) -> String {
+    let mut out = String::with_capacity(svg.len());
+    let mut rest = svg;
+    while let Some(i) = rest.find(start) {
+        out.push_str(&rest[..i]);
+        match rest[i..].find(end) {
+            Some(j) => rest = &rest[i + j + end.len()..],
+            None => {
+                rest = "";
+                break;
+            },
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+impl PostProcessor {
+    pub fn apply(&self, svg: String) -> String {
+        match self {
+            PostProcessor::Minify => minify(&svg),
+            PostProcessor::StripMetadata => {
+                let svg = remove_between(&svg, "<!--", "-->");
+                remove_between(&svg, "<metadata", "</metadata>")
+            },
+            PostProcessor::StripUnusedDefs => strip_unused_defs(&svg),
+            PostProcessor::StripRedundant => strip_redundant(&svg),
+            PostProcessor::Optimize => [
+                PostProcessor::StripMetadata,
+                PostProcessor::StripUnusedDefs,
+                PostProcessor::StripRedundant,
+                PostProcessor::Minify,
+            ].iter().fold(svg, |svg, step| step.apply(svg)),
+            PostProcessor::Comment(text) => {
+                // `--` must not occur within comments.
+                let comment = format!("<!-- {} -->", text.replace("--", "- -"));
+                match svg.find("?>") {
+                    Some(i) if svg.trim_start().starts_with("<?xml") =>
+                        format!("{}\n{comment}{}", &svg[..i + 2], &svg[i + 2..]),
+                    _ => format!("{comment}\n{svg}"),
+                }
+            },
+            PostProcessor::Watermark(text) => {
+                let mark = format!(
+                    "<text x=\"100%\" y=\"100%\" dx=\"-2\" dy=\"-2\" text-anchor=\"end\" \
+                     font-family=\"DejaVu Sans,Verdana,Geneva,sans-serif\" font-size=\"5\" \
+                     fill=\"#fff\" fill-opacity=\".5\">{}</text>\n",
+                    escape(text));
+                match svg.rfind("</svg>") {
+                    Some(i) => format!("{}{mark}{}", &svg[..i], &svg[i..]),
+                    None => svg,
+                }
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minify_joins_tags_and_keeps_text_apart() {
+        let svg = "<svg>\n    <text>\n        a\n        b\n    </text>\n\n</svg>\n";
+        assert_eq!(PostProcessor::Minify.apply(svg.to_string()), "<svg><text> a b </text></svg>");
+    }
+
+    #[test]
+    fn comment_follows_the_xml_declaration() {
+        let svg = "<?xml version=\"1.0\"?>\n<svg></svg>";
+        assert_eq!(PostProcessor::Comment("a -- b".into()).apply(svg.to_string()),
+                   "<?xml version=\"1.0\"?>\n<!-- a - - b -->\n<svg></svg>");
+        assert_eq!(PostProcessor::StripMetadata.apply("<svg><!-- x --><metadata>y</metadata></svg>".into()),
+                   "<svg></svg>");
+    }
+
+    #[test]
+    fn unused_defs_are_stripped() {
+        let svg = "<?xml version=\"1.0\"?>\n<svg version=\"1.1\">\n<g></g>\n<defs>\n<pattern id=\"a\"><rect/></pattern>\n<linearGradient id=\"b\"/>\n</defs>\n\
+                   <linearGradient id=\"c\"><stop/></linearGradient>\n<rect fill=\"url(#c)\" />\n</svg>";
+        assert_eq!(PostProcessor::Optimize.apply(svg.to_string()),
+                   "<svg><linearGradient id=\"c\"><stop/></linearGradient><rect fill=\"url(#c)\"/></svg>");
+    }
+}
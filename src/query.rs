@@ -0,0 +1,156 @@
+//! The [`BarSpec`] of a query, validated field by field. Instead of the first error of the
+//! deserializer, clients get a JSON list naming every bad parameter, e.g.
+//! `{"error": "invalid query", "fields": [{"field": "scale", "message": "must be greater than 0"}]}`.
+//! With `--strict`, parameters the route does not know are rejected as well.
+use std::collections::HashMap;
+use std::fmt;
+use std::future::{ready, Ready};
+use actix_web::{dev::Payload, http::StatusCode, web, FromRequest, HttpRequest, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use progress_bar::BarSpec;
+
+
+/// Parameters of [`BarSpec`] which can be given in a query.
+pub(crate) const SPEC_KEYS: &[&str] = &[
+    "title", "title_width", "title_color", "logo", "logo_data", "scale", "progress", "value", "min", "max", "baseline", "done",
+    "total", "units", "start", "end", "tz", "mode", "until", "source", "value_path", "transform", "overflow", "progress_width", "progress_color", "palette", "color_mode",
+    "color_stops", "color_space", "patterns", "suffix", "preset", "locale", "label", "label_position", "link", "link2", "aria_label", "dir", "adaptive", "template", "format", "density", "blackhole",
+];
+/// Parameters read by middleware, valid for every route.
+pub(crate) const SERVER_KEYS: &[&str] = &["dry_run", "error", "simulate"];
+
+/// Parameters of the route `name` besides the spec.
+fn route_keys(name: Option<&str>) -> &'static [&'static str] {
+    match name {
+        Some("crate_downloads" | "npm_downloads") => &["goal", "period"],
+        Some("shields") => &["url"],
+        _ => &[],
+    }
+}
+
+/// Rejection of unknown parameters, as set with `--strict`.
+#[derive(Clone, Copy, Default)]
+pub struct QueryConfig {
+    pub strict: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: impl Into<String>) -> Self {
+        FieldError { field: field.to_string(), message: message.into() }
+    }
+}
+
+#[derive(Debug)]
+pub struct QueryError(pub Vec<FieldError>);
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid query")?;
+        for (i, e) in self.0.iter().enumerate() {
+            write!(f, "{} `{}` {}", if i == 0 { ":" } else { ";" }, e.field, e.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl ResponseError for QueryError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": "invalid query",
+            "fields": self.0,
+        }))
+    }
+}
+
+/// The problem with `value` of the spec parameter `key`, if any. Besides failing to parse,
+/// the value may fail [`BarSpec::invalid_fields`], which JSON specs are checked with as well.
+fn check(key: &str, value: &str) -> Option<String> {
+    let spec = web::Query::<BarSpec>::from_query(&serde_urlencoded::to_string([(key, value)]).ok()?);
+    match spec {
+        Ok(spec) => spec.invalid_fields().into_iter().next().map(|(_, e)| e.to_string()),
+        Err(actix_web::error::QueryPayloadError::Deserialize(e)) => Some(e.to_string()),
+        Err(e) => Some(e.to_string()),
+    }
+}
+
+/// Parses and validates the spec in `query` for the route `route`.
+pub fn parse(query: &str, route: Option<&str>, config: QueryConfig) -> Result<BarSpec, QueryError> {
+    let pairs: Vec<(String, String)> = serde_urlencoded::from_str(query)
+        .map_err(|e| QueryError(vec![FieldError::new("query", e.to_string())]))?;
+    let mut errors = Vec::new();
+    let mut seen = HashMap::new();
+    for (key, value) in &pairs {
+        let count = seen.entry(key.as_str()).or_insert(0);
+        *count += 1;
+        if *count == 2 {
+            errors.push(FieldError::new(key, "is given more than once"));
+        }
+        if *count > 1 {
+            continue;
+        }
+        if SPEC_KEYS.contains(&key.as_str()) {
+            errors.extend(check(key, value).map(|message| FieldError::new(key, message)));
+        } else if matches!(key.as_str(), "states" | "components") {
+            errors.push(FieldError::new(key, "can only be given in JSON, e.g. for stored bars"));
+        } else if config.strict && !SERVER_KEYS.contains(&key.as_str()) && !route_keys(route).contains(&key.as_str()) {
+            errors.push(FieldError::new(key, "is not a known parameter"));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(QueryError(errors));
+    }
+    web::Query::<BarSpec>::from_query(query)
+        .map(web::Query::into_inner)
+        .map_err(|e| QueryError(vec![FieldError::new("query", e.to_string())]))
+}
+
+/// Extractor of the validated [`BarSpec`] in the query, replacing `web::Query<BarSpec>`.
+pub struct SpecQuery(pub BarSpec);
+
+impl SpecQuery {
+    pub fn into_inner(self) -> BarSpec {
+        self.0
+    }
+}
+
+impl FromRequest for SpecQuery {
+    type Error = QueryError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let config = req.app_data::<web::Data<QueryConfig>>().map(|x| *x.get_ref()).unwrap_or_default();
+        ready(parse(req.query_string(), req.match_name(), config).map(SpecQuery))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_names_every_bad_field() {
+        let strict = QueryConfig { strict: true };
+        let fields = |query, config| match parse(query, Some("render"), config) {
+            Ok(_) => vec![],
+            Err(QueryError(errors)) => errors.into_iter().map(|x| x.field).collect::<Vec<_>>(),
+        };
+        assert!(parse("progress=5&scale=20&title_color=%23abc&mode=countdown&until=2030-01-01", None, strict).is_ok());
+        assert_eq!(fields("progress=x&scale=0&progress_width=-1&title_color=red\"&mode=up", strict),
+                   ["progress", "scale", "progress_width", "title_color", "mode"]);
+        assert_eq!(fields("progress=5&progress=6&colour=red", strict), ["progress", "colour"]);
+        assert!(fields("progress=5&colour=red&error=json", QueryConfig::default()).is_empty());
+        assert_eq!(fields("progress=5&goal=10", strict), ["goal"]);
+        assert_eq!(fields("progress=inf&density=9&link=javascript:x", strict), ["progress", "density", "link"]);
+    }
+}
@@ -0,0 +1,107 @@
+//! Default styling and cache policies selected by the site a bar is embedded on, so one URL
+//! renders appropriately on e.g. github.com and an internal wiki.
+use actix_web::{http::header, HttpRequest};
+use progress_bar::BarSpec;
+use crate::config::ReferrerRule;
+use crate::sources;
+
+
+pub struct ReferrerRules(Vec<ReferrerRule>);
+
+impl ReferrerRules {
+    pub fn new(rules: Vec<ReferrerRule>) -> Self {
+        ReferrerRules(rules)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The first rule matching the host of the `Referer` of `req`.
+    pub fn matching(&self, req: &HttpRequest) -> Option<&ReferrerRule> {
+        let host = host(req)?;
+        self.0.iter().find(|rule| sources::is_allowed(&host, &rule.hosts))
+    }
+}
+
+/// The host of the `Referer` of `req`.
+pub fn host(req: &HttpRequest) -> Option<String> {
+    let referrer = req.headers().get(header::REFERER)?.to_str().ok()?;
+    let url = reqwest::Url::parse(referrer).ok()?;
+    url.host_str().map(str::to_string)
+}
+
+/// Fills what `args` leaves unset with the defaults of `rule`.
+pub fn apply(rule: &ReferrerRule, args: &mut BarSpec) {
+    if args.title_color.is_none() {
+        args.title_color = rule.title_color.clone().map(Into::into);
+    }
+    if args.progress_color.is_none() {
+        args.progress_color = rule.progress_color.clone().map(Into::into);
+    }
+    if args.suffix.is_none() {
+        args.suffix = rule.suffix.clone().map(Into::into);
+    }
+    args.progress_width = args.progress_width.or(rule.progress_width);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn rule(hosts: &[&str], progress_color: &str) -> ReferrerRule {
+        ReferrerRule {
+            hosts: hosts.iter().map(|x| x.to_string()).collect(),
+            title_color: Some("#24292f".to_string()),
+            progress_color: Some(progress_color.to_string()),
+            progress_width: Some(120),
+            suffix: None,
+            cache_control: None,
+        }
+    }
+
+    #[test]
+    fn the_first_matching_rule_wins() {
+        let rules = ReferrerRules::new(vec![
+            rule(&["*.github.com"], "#1f883d"),
+            rule(&["wiki.example.com", "gist.github.com"], "#0969da"),
+            rule(&["*.example.com"], "#cf222e"),
+        ]);
+        let matching = |referrer: Option<&str>| {
+            let mut req = TestRequest::default();
+            if let Some(referrer) = referrer {
+                req = req.insert_header((header::REFERER, referrer));
+            }
+            rules.matching(&req.to_http_request()).and_then(|x| x.progress_color.clone())
+        };
+        for (referrer, expected) in [
+            (Some("https://gist.github.com/someone/1"), Some("#1f883d")),
+            (Some("https://a.b.GitHub.com/"), Some("#1f883d")),
+            (Some("https://github.com/someone/repo"), None),
+            (Some("https://notgithub.com/"), None),
+            (Some("https://wiki.example.com/page"), Some("#0969da")),
+            (Some("https://docs.example.com/"), Some("#cf222e")),
+            (Some("example.com/page"), None),
+            (None, None),
+        ] {
+            assert_eq!(matching(referrer).as_deref(), expected, "{referrer:?}");
+        }
+    }
+
+    #[test]
+    fn explicit_parameters_beat_the_defaults_of_rules() {
+        let rule = ReferrerRule { suffix: Some(" pts".to_string()), ..rule(&["github.com"], "#1f883d") };
+        let mut args = BarSpec {
+            progress_color: Some("red".into()),
+            progress_width: Some(60),
+            ..Default::default()
+        };
+        apply(&rule, &mut args);
+        assert_eq!(args.progress_color.as_deref(), Some("red"));
+        assert_eq!(args.progress_width, Some(60));
+        assert_eq!(args.title_color.as_deref(), Some("#24292f"));
+        assert_eq!(args.suffix.as_deref(), Some(" pts"));
+    }
+}
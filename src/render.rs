@@ -0,0 +1,572 @@
+//! The template environment turning bar specs into SVG.
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::time::Duration;
+use minijinja::{Environment, Source};
+use minijinja::value::Value;
+use sha2::{Digest, Sha256};
+use crate::check::{check_template_with, Problem};
+use crate::color_script::ColorScript;
+use crate::context::build_context_with;
+use crate::defaults::BarDefaults;
+use crate::filters;
+use crate::postprocess::PostProcessor;
+use crate::spec::{BarSpec, SpecError};
+use crate::transforms::{TransformStep, Transforms};
+use crate::trend::{trend_context, TREND_NAME, TREND_TEMPLATE};
+
+
+pub(crate) const TEMPLATE_NAME: &str = "pbar_template";
+/// Macros shipped for templates, imported with `{% import "macros.svg.j2" as m %}`.
+pub const MACROS_NAME: &str = "macros.svg.j2";
+pub const MACROS: &str = include_str!("../resources/macros.svg.j2");
+/// The template used unless [`RendererOptions::template`] is given.
+pub const DEFAULT_TEMPLATE: &str = include_str!("../resources/default.svg");
+/// Name of [`DEFAULT_TEMPLATE`], which other templates can inherit with
+/// `{% extends "default.svg" %}`, overriding some of its blocks, even if it is replaced.
+pub const DEFAULT_NAME: &str = "default.svg";
+
+#[derive(Debug, Default, Clone)]
+pub struct RendererOptions {
+    /// Source of the template replacing the default one.
+    pub template: Option<String>,
+    /// Templates selected with [`BarSpec::template`], keyed by name.
+    pub named_templates: BTreeMap<String, String>,
+    /// Templates only extended, included or imported by others, keyed by names like
+    /// `label.svg.j2`.
+    pub partials: BTreeMap<String, String>,
+    /// Value pipelines selected by [`BarSpec::transform`].
+    pub transforms: HashMap<String, Vec<TransformStep>>,
+    /// Applied in order to every rendered SVG.
+    pub post_processors: Vec<PostProcessor>,
+    /// Constants available to the template, overridden by the context of the bar.
+    pub globals: BTreeMap<String, serde_json::Value>,
+    /// Source of a [`ColorScript`] replacing the default progress colors.
+    pub color_fn: Option<String>,
+    /// Fill the fields the specs leave out.
+    pub defaults: BarDefaults,
+    /// Rendered SVGs larger than this, after the post-processors, are rejected.
+    pub max_body_bytes: Option<usize>,
+    /// Bounds of the work of templates, protecting against pathological ones.
+    pub limits: RenderLimits,
+}
+
+/// Bounds of the work of a template, unbounded by default. Besides, minijinja stops macro
+/// calls nested more than about a hundred levels deep.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RenderLimits {
+    /// Instructions a template may execute for a bar.
+    pub fuel: Option<u64>,
+    /// Time a bar may take to render, which requires `fuel`. The templates run on a pool of
+    /// a thread per CPU then, and a render abandoned when its time is up keeps its thread
+    /// until the fuel runs out.
+    pub timeout: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub enum RenderError {
+    Spec(SpecError),
+    Template(minijinja::Error),
+    TooLarge { size: usize, max: usize },
+    OutOfFuel(u64),
+    Timeout(Duration),
+    /// Every thread of the pool rendering with a timeout is taken.
+    Busy,
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Spec(e) => e.fmt(f),
+            RenderError::Template(e) => write!(f, "failed to render the template: {e}"),
+            RenderError::TooLarge { size, max } =>
+                write!(f, "the rendered bar has {size} bytes, at most {max} are allowed"),
+            RenderError::OutOfFuel(fuel) =>
+                write!(f, "the template ran out of fuel after {fuel} instructions, it may loop too much"),
+            RenderError::Timeout(timeout) =>
+                write!(f, "the template did not finish within {} s", timeout.as_secs_f64()),
+            RenderError::Busy => write!(f, "too many bars are being rendered at once"),
+        }
+    }
+}
+
+impl std::error::Error for RenderError {}
+
+
+impl From<SpecError> for RenderError {
+    fn from(e: SpecError) -> Self {
+        RenderError::Spec(e)
+    }
+}
+
+impl From<minijinja::Error> for RenderError {
+    fn from(e: minijinja::Error) -> Self {
+        RenderError::Template(e)
+    }
+}
+
+/// A template which cannot be added to a renderer.
+#[derive(Debug)]
+pub enum TemplateError {
+    InvalidName(String),
+    InvalidPartialName(String),
+    /// The template `name`, `None` for the default one, fails [`check_template`].
+    Problems { name: Option<String>, problems: Vec<Problem> },
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::InvalidName(name) => write!(
+                f, "`{name}` is no valid template name, which has up to 64 letters, digits, `-` and `_`"),
+            TemplateError::InvalidPartialName(name) => write!(
+                f, "`{name}` is no valid partial name, which has up to 64 letters, digits, `-`, `_` and `.` and ends in `.j2`"),
+            TemplateError::Problems { name, problems } => {
+                match name {
+                    Some(name) => write!(f, "the template `{name}` has {} problem(s)", problems.len())?,
+                    None => write!(f, "the default template has {} problem(s)", problems.len())?,
+                }
+                for problem in problems {
+                    write!(f, "; {problem}")?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+/// Whether `name` can name a template selected with [`BarSpec::template`].
+pub fn is_template_name(name: &str) -> bool {
+    !name.is_empty() && name.len() <= 64 && name != TEMPLATE_NAME && name != TREND_NAME
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Whether `name` can name a partial, which other templates extend, include or import.
+pub fn is_partial_name(name: &str) -> bool {
+    name.len() <= 64 && name.len() > ".j2".len() && name.ends_with(".j2") && name != MACROS_NAME
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+}
+
+/// The environment holding the macros, the trend chart, the bundled default template under
+/// [`DEFAULT_NAME`], `template` under [`TEMPLATE_NAME`], and the `named` templates and
+/// `partials` under their names.
+pub(crate) fn environment(
+    template: &str,
+    named: &BTreeMap<String, String>,
+    partials: &BTreeMap<String, String>,
+    globals: &BTreeMap<String, serde_json::Value>,
+) -> Result<Environment<'static>, minijinja::Error> {
+    let mut source = Source::new();
+    source.add_template(MACROS_NAME, MACROS)?;
+    source.add_template(TREND_NAME, TREND_TEMPLATE)?;
+    source.add_template(DEFAULT_NAME, DEFAULT_TEMPLATE)?;
+    source.add_template(TEMPLATE_NAME, template)?;
+    for (name, template) in named.iter().chain(partials) {
+        source.add_template(name, template)?;
+    }
+    let mut env = Environment::new();
+    env.set_source(source);
+    filters::register(&mut env);
+    for (name, value) in globals {
+        env.add_global(name.clone(), Value::from_serializable(value));
+    }
+    Ok(env)
+}
+
+/// Renders bars with a template, e.g.
+///
+/// ```
+/// use progress_bar::{BarSpec, ProgressBarRenderer};
+///
+/// let renderer = ProgressBarRenderer::new(Default::default()).unwrap();
+/// let svg = renderer.render(&BarSpec { progress: Some(42.0), ..Default::default() }).unwrap();
+/// assert!(svg.contains("<svg"));
+/// ```
+pub struct ProgressBarRenderer {
+    templates: RwLock<Arc<Templates>>,
+    globals: BTreeMap<String, serde_json::Value>,
+    transforms: Transforms,
+    post_processors: Vec<PostProcessor>,
+    color_script: Option<ColorScript>,
+    defaults: BarDefaults,
+    max_body_bytes: Option<usize>,
+    limits: RenderLimits,
+    pool: Option<RenderPool>,
+}
+
+type RenderJob = Box<dyn FnOnce() + Send>;
+
+/// The threads rendering the templates when there is a timeout, as many as the CPUs. They stop
+/// with the renderer.
+struct RenderPool {
+    jobs: mpsc::SyncSender<RenderJob>,
+}
+
+impl RenderPool {
+    fn new() -> std::io::Result<Self> {
+        let threads = std::thread::available_parallelism().map_or(1, usize::from);
+        let (jobs, receiver) = mpsc::sync_channel::<RenderJob>(threads);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..threads {
+            let receiver = receiver.clone();
+            std::thread::Builder::new()
+                .name("render".to_string())
+                .spawn(move || loop {
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })?;
+        }
+        Ok(RenderPool { jobs })
+    }
+}
+
+/// The templates of a renderer, replaced as a whole when they change.
+struct Templates {
+    env: Environment<'static>,
+    default: Option<String>,
+    named: BTreeMap<String, String>,
+    partials: BTreeMap<String, String>,
+    /// Digest of the sources, which the rendered bars depend on besides their context.
+    digest: [u8; 32],
+}
+
+impl Templates {
+    fn new(
+        default: Option<String>,
+        named: BTreeMap<String, String>,
+        partials: BTreeMap<String, String>,
+        globals: &BTreeMap<String, serde_json::Value>,
+        limits: RenderLimits,
+    ) -> Result<Arc<Self>, minijinja::Error> {
+        let mut env = environment(default.as_deref().unwrap_or(DEFAULT_TEMPLATE), &named, &partials, globals)?;
+        env.set_fuel(limits.fuel);
+        let sources = (default.as_deref().unwrap_or(DEFAULT_TEMPLATE), MACROS, &named, &partials, globals);
+        let digest = Sha256::digest(serde_json::to_vec(&sources).unwrap_or_default()).into();
+        Ok(Arc::new(Templates { env, default, named, partials, digest }))
+    }
+
+    fn render(&self, name: &str, ctx: &Value) -> Result<String, minijinja::Error> {
+        self.env.get_template(name)?.render(ctx)
+    }
+}
+
+impl ProgressBarRenderer {
+    pub fn new(options: RendererOptions) -> anyhow::Result<Self> {
+        if let Some(name) = options.named_templates.keys().find(|x| !is_template_name(x)) {
+            return Err(TemplateError::InvalidName(name.clone()).into());
+        }
+        if let Some(name) = options.partials.keys().find(|x| !is_partial_name(x)) {
+            return Err(TemplateError::InvalidPartialName(name.clone()).into());
+        }
+        if options.limits.timeout.is_some() && options.limits.fuel.is_none() {
+            anyhow::bail!("a render timeout requires fuel, which stops the renders abandoned at the timeout");
+        }
+        let pool = options.limits.timeout.map(|_| RenderPool::new()).transpose()?;
+        let templates = Templates::new(
+            options.template, options.named_templates, options.partials, &options.globals, options.limits)?;
+        let transforms = Transforms::new(options.transforms)?;
+        Ok(ProgressBarRenderer {
+            templates: RwLock::new(templates),
+            globals: options.globals,
+            transforms,
+            post_processors: options.post_processors,
+            color_script: options.color_fn.as_deref().map(ColorScript::compile).transpose()?,
+            defaults: options.defaults,
+            max_body_bytes: options.max_body_bytes,
+            limits: options.limits,
+            pool,
+        })
+    }
+
+    /// Whether [`RendererOptions::template`] replaced the default template.
+    pub fn has_custom_template(&self) -> bool {
+        self.templates.read().unwrap().default.is_some()
+    }
+
+    /// Changes whenever the same context could render differently, i.e. with the templates
+    /// or the post-processors.
+    pub fn fingerprint(&self) -> String {
+        let digest = self.templates.read().unwrap().digest;
+        let hash = Sha256::new()
+            .chain_update(digest)
+            .chain_update(format!("{:?}", self.post_processors))
+            .finalize();
+        hash.iter().take(8).map(|x| format!("{x:02x}")).collect()
+    }
+
+    /// Names of the templates selectable with [`BarSpec::template`].
+    pub fn template_names(&self) -> Vec<String> {
+        self.templates.read().unwrap().named.keys().cloned().collect()
+    }
+
+    /// Names of the partials the templates can extend, include or import.
+    pub fn partial_names(&self) -> Vec<String> {
+        self.templates.read().unwrap().partials.keys().cloned().collect()
+    }
+
+    /// Adds or replaces the template `name`, unless it fails [`check_template`]. Bars being
+    /// rendered keep the templates they started with, and bars keep rendering during the check.
+    pub fn set_template(&self, name: &str, template: String) -> Result<(), TemplateError> {
+        if !is_template_name(name) {
+            return Err(TemplateError::InvalidName(name.to_string()));
+        }
+        loop {
+            let current = self.templates.read().unwrap().clone();
+            let mut named = current.named.clone();
+            named.remove(name);
+            self.check(Some(name), &template, &named, &current.partials)?;
+            named.insert(name.to_string(), template.clone());
+            let templates = Templates::new(current.default.clone(), named, current.partials.clone(), &self.globals, self.limits)
+                .map_err(|e| TemplateError::Problems { name: Some(name.to_string()), problems: vec![Problem::from(e)] })?;
+            let mut slot = self.templates.write().unwrap();
+            // templates changed during the check are kept, and the check is repeated with them.
+            if Arc::ptr_eq(&slot, &current) {
+                *slot = templates;
+                return Ok(());
+            }
+        }
+    }
+
+    /// Replaces every template and partial at once, `default` being `None` for the bundled
+    /// template. Nothing is replaced if any of the templates fails [`check_template`].
+    pub fn replace_templates(
+        &self,
+        default: Option<String>,
+        named: BTreeMap<String, String>,
+        partials: BTreeMap<String, String>,
+    ) -> Result<(), TemplateError> {
+        if let Some(name) = partials.keys().find(|x| !is_partial_name(x)) {
+            return Err(TemplateError::InvalidPartialName(name.clone()));
+        }
+        if let Some(template) = &default {
+            self.check(None, template, &named, &partials)?;
+        }
+        for (name, template) in &named {
+            if !is_template_name(name) {
+                return Err(TemplateError::InvalidName(name.clone()));
+            }
+            let mut others = named.clone();
+            others.remove(name);
+            self.check(Some(name), template, &others, &partials)?;
+        }
+        let templates = Templates::new(default, named, partials, &self.globals, self.limits)
+            .map_err(|e| TemplateError::Problems { name: None, problems: vec![Problem::from(e)] })?;
+        *self.templates.write().unwrap() = templates;
+        Ok(())
+    }
+
+    /// Checks `template`, which may extend, include or import the `named` templates and the
+    /// `partials`.
+    fn check(
+        &self,
+        name: Option<&str>,
+        template: &str,
+        named: &BTreeMap<String, String>,
+        partials: &BTreeMap<String, String>,
+    ) -> Result<(), TemplateError> {
+        let problems = check_template_with(template, named, partials, &self.globals, self.limits);
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(TemplateError::Problems { name: name.map(str::to_string), problems }),
+        }
+    }
+
+    pub fn transforms(&self) -> &Transforms {
+        &self.transforms
+    }
+
+    /// Passes the value and the baseline of `spec` through the pipeline selected by its
+    /// `transform`, if any.
+    pub fn apply_transform(&self, spec: &mut BarSpec) -> Result<(), SpecError> {
+        let Some(name) = &spec.transform else { return Ok(()) };
+        if let Some(value) = spec.value.or(spec.progress) {
+            spec.value = Some(self.transforms.apply(name, value)?);
+        }
+        if let Some(baseline) = spec.baseline {
+            spec.baseline = Some(self.transforms.apply(name, baseline)?);
+        }
+        Ok(())
+    }
+
+    /// The template context of `spec`, with the defaults and its transform applied.
+    pub fn context(&self, spec: &BarSpec) -> Result<Value, SpecError> {
+        if let Some(name) = spec.template.as_ref().filter(|x| !self.templates.read().unwrap().named.contains_key(*x)) {
+            return Err(SpecError::UnknownTemplate(name.clone()));
+        }
+        let mut spec = spec.clone();
+        self.defaults.apply(&mut spec);
+        self.apply_transform(&mut spec)?;
+        build_context_with(spec, self.color_script.as_ref())
+    }
+
+    /// Renders a context returned by [`ProgressBarRenderer::context`], followed by the
+    /// post-processors.
+    pub fn render_context(&self, ctx: &Value) -> Result<String, RenderError> {
+        let name = ctx.get_attr("template").ok().filter(|x| !x.is_undefined()).map(|x| x.to_string());
+        let svg = self.render_template(name.unwrap_or_else(|| TEMPLATE_NAME.to_string()), ctx)?;
+        self.finish(svg)
+    }
+
+    /// Renders `spec` as a line chart of `values`, oldest first, followed by its current value.
+    pub fn render_trend(&self, spec: &BarSpec, values: &[f32]) -> Result<String, RenderError> {
+        let ctx = trend_context(&self.context(spec)?, values);
+        let svg = self.render_template(TREND_NAME.to_string(), &ctx)?;
+        self.finish(svg)
+    }
+
+    /// Applies the post-processors to `svg` and checks its size.
+    fn finish(&self, svg: String) -> Result<String, RenderError> {
+        let svg = self.post_processors.iter().fold(svg, |svg, step| step.apply(svg));
+        match self.max_body_bytes {
+            Some(max) if svg.len() > max => Err(RenderError::TooLarge { size: svg.len(), max }),
+            _ => Ok(svg),
+        }
+    }
+
+    /// Renders the template `name`, within the [`RenderLimits`].
+    fn render_template(&self, name: String, ctx: &Value) -> Result<String, RenderError> {
+        let templates = self.templates.read().unwrap().clone();
+        let out_of_fuel = |e: minijinja::Error| match (e.kind(), self.limits.fuel) {
+            (minijinja::ErrorKind::OutOfFuel, Some(fuel)) => RenderError::OutOfFuel(fuel),
+            _ => RenderError::Template(e),
+        };
+        let (Some(timeout), Some(pool)) = (self.limits.timeout, &self.pool) else {
+            return templates.render(&name, ctx).map_err(out_of_fuel);
+        };
+        let (sender, receiver) = mpsc::channel();
+        let ctx = ctx.clone();
+        pool.jobs.try_send(Box::new(move || {
+            let _ = sender.send(templates.render(&name, &ctx));
+        })).map_err(|_| RenderError::Busy)?;
+        match receiver.recv_timeout(timeout) {
+            Ok(rendered) => rendered.map_err(out_of_fuel),
+            Err(_) => Err(RenderError::Timeout(timeout)),
+        }
+    }
+
+    /// Renders `spec` into an SVG.
+    pub fn render(&self, spec: &BarSpec) -> Result<String, RenderError> {
+        self.render_context(&self.context(spec)?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::LabelPosition;
+
+    #[test]
+    fn named_templates_are_checked_and_selected() {
+        let renderer = ProgressBarRenderer::new(Default::default()).unwrap();
+        let spec = BarSpec { progress: Some(40.0), template: Some("flat".into()), ..Default::default() };
+        assert!(matches!(renderer.render(&spec), Err(RenderError::Spec(SpecError::UnknownTemplate(_)))));
+        assert!(matches!(renderer.set_template("flat", "<svg>{{ progres }}</svg>".into()),
+                         Err(TemplateError::Problems { .. })));
+        assert!(matches!(renderer.set_template("a.b", "<svg/>".into()), Err(TemplateError::InvalidName(_))));
+
+        renderer.set_template("flat", "<svg>{{ progress }}</svg>".into()).unwrap();
+        assert_eq!(renderer.render(&spec).unwrap(), "<svg>40.0</svg>");
+        assert_eq!(renderer.template_names(), ["flat"]);
+        renderer.replace_templates(None, BTreeMap::new(), BTreeMap::new()).unwrap();
+        assert!(renderer.template_names().is_empty());
+    }
+
+    #[test]
+    fn macros_escape_their_text() {
+        let renderer = ProgressBarRenderer::new(Default::default()).unwrap();
+        let spec = BarSpec { progress: Some(40.0), title: Some("<script>alert(1)</script>".into()), ..Default::default() };
+        let svg = renderer.render(&spec).unwrap();
+        assert!(!svg.contains("<script>") && svg.contains("&lt;script&gt;alert(1)&lt;&#x2f;script&gt;"), "{svg}");
+    }
+
+    #[test]
+    fn fingerprints_change_with_the_templates() {
+        let renderer = ProgressBarRenderer::new(Default::default()).unwrap();
+        let bundled = renderer.fingerprint();
+        assert_eq!(ProgressBarRenderer::new(Default::default()).unwrap().fingerprint(), bundled);
+        renderer.set_template("flat", "<svg>{{ progress }}</svg>".into()).unwrap();
+        let named = renderer.fingerprint();
+        assert_ne!(named, bundled);
+        renderer.set_template("flat", "<svg>{{ progress }}%</svg>".into()).unwrap();
+        assert_ne!(renderer.fingerprint(), named);
+    }
+
+    #[test]
+    fn labels_are_escaped() {
+        let renderer = ProgressBarRenderer::new(Default::default()).unwrap();
+        for position in [None, Some(LabelPosition::Outside)] {
+            let spec = BarSpec { progress: Some(40.0), label: Some("<a>&".into()), label_position: position, ..Default::default() };
+            let svg = renderer.render(&spec).unwrap();
+            assert!(svg.contains("&lt;a&gt;&amp;") && !svg.contains("<a>"), "{svg}");
+        }
+    }
+
+    #[test]
+    fn trend_logos_are_escaped() {
+        let renderer = ProgressBarRenderer::new(Default::default()).unwrap();
+        let spec = BarSpec { progress: Some(40.0), ..Default::default() };
+        let ctx = trend_context(&renderer.context(&spec).unwrap(), &[40.0]);
+        let mut ctx = serde_json::to_value(ctx).unwrap();
+        ctx["logo"] = "data:image/png;base64,\" onload=\"x".into();
+        let svg = renderer.render_template(TREND_NAME.to_string(), &Value::from_serializable(&ctx)).unwrap();
+        assert!(svg.contains("&quot; onload=&quot;x") && !svg.contains("\" onload"), "{svg}");
+    }
+
+    #[test]
+    fn concurrent_template_changes_are_all_kept() {
+        let renderer = Arc::new(ProgressBarRenderer::new(Default::default()).unwrap());
+        let threads: Vec<_> = (0..4)
+            .map(|i| {
+                let renderer = renderer.clone();
+                std::thread::spawn(move || renderer.set_template(&format!("t{i}"), "<svg>{{ progress }}</svg>".into()))
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+        assert_eq!(renderer.template_names(), ["t0", "t1", "t2", "t3"]);
+    }
+
+    #[test]
+    fn templates_extend_the_default_and_include_partials() {
+        let renderer = ProgressBarRenderer::new(RendererOptions {
+            partials: BTreeMap::from([("label.svg.j2".to_string(), "<text>{{ text }}!</text>".to_string())]),
+            ..Default::default()
+        }).unwrap();
+        let spec = BarSpec { progress: Some(40.0), template: Some("loud".into()), ..Default::default() };
+        let loud = r#"{% extends "default.svg" %}{% block label %}{% include "label.svg.j2" %}{% endblock %}"#;
+        renderer.set_template("loud", loud.into()).unwrap();
+        let svg = renderer.render(&spec).unwrap();
+        assert!(svg.starts_with("<?xml") && svg.contains("<text>40.0%!</text>"));
+        assert!(svg.contains(r##"fill="#f0ad4e""##));
+
+        let missing = r#"{% extends "default.svg" %}{% block label %}{% include "none.svg.j2" %}{% endblock %}"#;
+        assert!(matches!(renderer.set_template("quiet", missing.into()), Err(TemplateError::Problems { .. })));
+        assert!(matches!(renderer.replace_templates(None, BTreeMap::new(), BTreeMap::from([("a".into(), "".into())])),
+                         Err(TemplateError::InvalidPartialName(_))));
+    }
+
+    #[test]
+    fn limits_stop_pathological_templates() {
+        let looping = "{% for a in range(1000) %}{% for b in range(1000) %}.{% endfor %}{% endfor %}";
+        let renderer = |limits| ProgressBarRenderer::new(RendererOptions {
+            template: Some(looping.into()),
+            limits,
+            ..Default::default()
+        }).unwrap();
+        let spec = BarSpec { progress: Some(40.0), ..Default::default() };
+        let fuel = RenderLimits { fuel: Some(10_000), timeout: None };
+        assert!(matches!(renderer(fuel).render(&spec), Err(RenderError::OutOfFuel(10_000))));
+        let timeout = RenderLimits { fuel: Some(100_000_000), timeout: Some(Duration::from_millis(1)) };
+        assert!(matches!(renderer(timeout).render(&spec), Err(RenderError::Timeout(_))));
+        let unfueled = RenderLimits { fuel: None, timeout: Some(Duration::from_secs(1)) };
+        assert!(ProgressBarRenderer::new(RendererOptions { limits: unfueled, ..Default::default() }).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+//! The `X-Request-Id` of every request, taken from the client or a proxy in front of the
+//! server if given, and generated otherwise. It is echoed in the response, written in the
+//! log lines and appended to error messages, so that failures can be correlated with the
+//! logs of proxies.
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use actix_web::{body::{self, BoxBody, MessageBody}, dev::{ServiceRequest, ServiceResponse}, HttpMessage, HttpRequest};
+use actix_web::http::header::{self, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+
+
+pub const HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+#[derive(Clone)]
+pub struct RequestId(pub String);
+
+/// Random bits for identifiers, not suitable for secrets.
+pub fn random_u128() -> u128 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    // every `RandomState` is seeded differently.
+    let hi = RandomState::new().hash_one(count);
+    let lo = RandomState::new().hash_one(count);
+    (u128::from(hi) << 64) | u128::from(lo)
+}
+
+/// The ID given by the client, if it is reasonable to put into logs.
+fn given(req: &ServiceRequest) -> Option<String> {
+    let id = req.headers().get(HEADER)?.to_str().ok()?;
+    let valid = (1..=128).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_graphic());
+    valid.then(|| id.to_string())
+}
+
+/// The ID of `req`, if assigned by [`assign`].
+pub fn get(req: &HttpRequest) -> Option<String> {
+    req.extensions().get::<RequestId>().map(|x| x.0.clone())
+}
+
+/// Middleware assigning the [`RequestId`] to the request extensions and the response.
+pub async fn assign(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let id = given(&req).unwrap_or_else(|| format!("{:032x}", random_u128()));
+    req.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next.call(req).await?.map_into_boxed_body();
+    let value = HeaderValue::from_str(&id).expect("request IDs are visible ASCII");
+    response.headers_mut().insert(HEADER, value);
+    let is_text = response.headers().get(header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.starts_with("text/plain"));
+    if !response.status().is_client_error() && !response.status().is_server_error() || !is_text {
+        return Ok(response);
+    }
+
+    let (req, response) = response.into_parts();
+    let (response, body) = response.into_parts();
+    let mut message = body::to_bytes(body).await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .to_vec();
+    message.extend_from_slice(format!("\n(request ID {id})").as_bytes());
+    Ok(ServiceResponse::new(req, response.set_body(BoxBody::new(message))))
+}
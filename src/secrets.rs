@@ -0,0 +1,77 @@
+//! Credentials for upstream services, resolved from the `[secrets]` config section.
+//!
+//! Sending `SIGHUP` re-reads the config file and re-resolves every secret, so rotated
+//! tokens are picked up without a restart.
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use actix_web::{rt, web};
+use anyhow::Context;
+use log::{error, info};
+use tokio::signal::unix::{signal, SignalKind};
+use crate::config::{Config, SecretSource};
+
+
+pub struct SecretStore {
+    config_path: Option<PathBuf>,
+    secrets: RwLock<HashMap<String, Arc<str>>>,
+}
+
+/// Reads the secret `name` from its `source`.
+pub fn resolve_secret(name: &str, source: &SecretSource) -> anyhow::Result<String> {
+    Ok(match source {
+        SecretSource::Value(x) => x.clone(),
+        SecretSource::Env(var) => std::env::var(var)
+            .with_context(|| format!("failed to read secret `{name}` from ${var}"))?,
+        SecretSource::File(path) => read_to_string(path)
+            .with_context(|| format!("failed to read secret `{name}` from {}", path.display()))?
+            .trim_end()
+            .to_string(),
+    })
+}
+
+fn resolve(sources: &HashMap<String, SecretSource>) -> anyhow::Result<HashMap<String, Arc<str>>> {
+    sources.iter()
+        .map(|(name, source)| Ok((name.clone(), resolve_secret(name, source)?.into())))
+        .collect()
+}
+
+impl SecretStore {
+    pub fn new(config_path: Option<PathBuf>, sources: &HashMap<String, SecretSource>) -> anyhow::Result<Self> {
+        Ok(SecretStore {
+            config_path,
+            secrets: RwLock::new(resolve(sources)?),
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<str>> {
+        self.secrets.read().unwrap().get(name).cloned()
+    }
+
+    /// Re-reads the config file and replaces all secrets. Nothing changes on failure.
+    pub fn reload(&self) -> anyhow::Result<usize> {
+        let sources = match &self.config_path {
+            Some(path) => Config::load(path)?.secrets,
+            None => HashMap::new(),
+        };
+        let secrets = resolve(&sources)?;
+        let count = secrets.len();
+        *self.secrets.write().unwrap() = secrets;
+        Ok(count)
+    }
+}
+
+/// Reloads `store` whenever the process receives `SIGHUP`.
+pub fn reload_on_sighup(store: web::Data<SecretStore>) -> anyhow::Result<()> {
+    let mut hangup = signal(SignalKind::hangup())?;
+    rt::spawn(async move {
+        while hangup.recv().await.is_some() {
+            match store.reload() {
+                Ok(count) => info!("Reloaded {} secret(s).", count),
+                Err(e) => error!("Failed to reload secrets, keeping the old ones: {:#}", e),
+            }
+        }
+    });
+    Ok(())
+}
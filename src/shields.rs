@@ -0,0 +1,85 @@
+//! Compatibility with the [shields.io endpoint schema](https://shields.io/badges/endpoint-badge),
+//! so pipelines already emitting it can switch to progress bars.
+use std::borrow::Cow;
+use serde::Deserialize;
+use progress_bar::BarSpec;
+use crate::sources::SourceError;
+
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Endpoint {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub label: Option<String>,
+    pub message: String,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub label_color: Option<String>,
+    #[serde(default)]
+    pub is_error: bool,
+}
+
+/// Translates the named colors of shields.io, also accepting hex colors without `#`.
+fn color(name: &str) -> Cow<'static, str> {
+    let hex = match name {
+        "brightgreen" | "success" => "#4c1",
+        "green" => "#97ca00",
+        "yellow" => "#dfb317",
+        "yellowgreen" => "#a4a61d",
+        "orange" | "important" => "#fe7d37",
+        "red" | "critical" => "#e05d44",
+        "blue" | "informational" => "#007ec6",
+        "grey" | "gray" => "#555",
+        "lightgrey" | "lightgray" | "inactive" => "#9f9f9f",
+        _ if matches!(name.len(), 3 | 6) && name.chars().all(|x| x.is_ascii_hexdigit()) =>
+            return format!("#{name}").into(),
+        _ => return name.to_string().into(),
+    };
+    hex.into()
+}
+
+/// Reads the progress from messages like `73%`, `73.5` or `146/200`.
+fn parse_message(message: &str) -> Option<f32> {
+    let message = message.trim();
+    if let Some((done, total)) = message.split_once('/') {
+        let done: f32 = done.trim().parse().ok()?;
+        let total: f32 = total.trim().parse().ok()?;
+        return (total > 0.0).then(|| 100.0 * done / total);
+    }
+    message.trim_end_matches('%').trim_end().parse().ok()
+}
+
+impl Endpoint {
+    pub fn from_document(document: serde_json::Value) -> Result<Self, SourceError> {
+        serde_json::from_value(document).map_err(|e| SourceError::InvalidDocument(e.to_string()))
+    }
+
+    /// Fills the bar described by `args` with the endpoint, keeping what `args` sets explicitly.
+    pub fn apply(self, args: &mut BarSpec) -> Result<(), SourceError> {
+        if self.schema_version != 1 {
+            return Err(SourceError::InvalidDocument(
+                format!("unsupported schemaVersion {}", self.schema_version)));
+        }
+        let value = parse_message(&self.message)
+            .ok_or_else(|| SourceError::NotANumber(self.message.clone()))?;
+        args.value = Some(value);
+        (args.min, args.max, args.scale) = (None, None, None);
+        if args.title.is_none() {
+            args.title = self.label.filter(|x| !x.is_empty());
+        }
+        if args.title_color.is_none() {
+            args.title_color = self.label_color.as_deref().map(color);
+        }
+        if args.progress_color.is_none() {
+            args.progress_color = match (&self.color, self.is_error) {
+                (Some(x), _) => Some(color(x)),
+                (None, true) => Some(color("critical")),
+                (None, false) => None,
+            };
+        }
+        args.label = Some(self.message);
+        Ok(())
+    }
+}
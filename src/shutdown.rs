@@ -0,0 +1,32 @@
+//! Graceful shutdown on `SIGTERM` and `SIGINT`: the listeners are closed at once, and the
+//! requests in flight get up to `--shutdown-timeout` seconds to finish before the process
+//! exits. A second signal stops the server without waiting.
+//!
+//! Changes of stored bars are written before they are acknowledged, except for the values
+//! set by [`BarStore::update_value`](crate::bars::BarStore::update_value), which wait for the
+//! next flush. `main` flushes them once more after the server stopped.
+use actix_web::{dev::ServerHandle, rt};
+use futures_util::future::{select, Either};
+use log::{info, warn};
+use tokio::signal::unix::{signal, SignalKind};
+use crate::systemd;
+
+
+pub fn on_signal(server: ServerHandle, timeout: u64) -> anyhow::Result<()> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    rt::spawn(async move {
+        let name = match select(Box::pin(terminate.recv()), Box::pin(interrupt.recv())).await {
+            Either::Left(_) => "SIGTERM",
+            Either::Right(_) => "SIGINT",
+        };
+        info!("Received {}, finishing the requests in flight for up to {} s.", name, timeout);
+        systemd::notify_stopping();
+        let graceful = server.clone();
+        rt::spawn(async move { graceful.stop(true).await });
+        select(Box::pin(terminate.recv()), Box::pin(interrupt.recv())).await;
+        warn!("Received a second signal, stopping at once.");
+        server.stop(false).await;
+    });
+    Ok(())
+}
@@ -0,0 +1,280 @@
+//! Progress values read from remote JSON documents, e.g.
+//! `?source=https://example.com/status.json&value_path=$.coverage.percent`.
+use std::fmt;
+use std::time::Duration;
+use actix_web::http::StatusCode;
+use reqwest::{redirect, Client, Url};
+use serde_json_path::JsonPath;
+use crate::config::SourcesConfig;
+use crate::health::HealthRegistry;
+
+
+#[derive(Debug)]
+pub enum SourceError {
+    InvalidUrl(String),
+    HostNotAllowed(String),
+    InvalidPath(String),
+    Fetch(reqwest::Error),
+    NoValue(String),
+    NotANumber(String),
+    InvalidDocument(String),
+    TooLarge(usize),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::InvalidUrl(url) => write!(f, "`{url}` is not a valid http(s) URL"),
+            SourceError::HostNotAllowed(host) => write!(f, "fetching from `{host}` is not allowed"),
+            SourceError::InvalidPath(e) => write!(f, "invalid JSONPath: {e}"),
+            SourceError::Fetch(e) => write!(f, "failed to fetch the source: {e}"),
+            SourceError::NoValue(path) => write!(f, "`{path}` does not select exactly one value"),
+            SourceError::NotANumber(value) => write!(f, "`{value}` is not a number"),
+            SourceError::InvalidDocument(e) => write!(f, "unexpected document: {e}"),
+            SourceError::TooLarge(max) => write!(f, "the document is larger than {max} bytes"),
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+impl SourceError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SourceError::HostNotAllowed(_) => StatusCode::FORBIDDEN,
+            SourceError::InvalidUrl(_) | SourceError::InvalidPath(_) => StatusCode::BAD_REQUEST,
+            SourceError::Fetch(_)
+            | SourceError::NoValue(_)
+            | SourceError::NotANumber(_)
+            | SourceError::InvalidDocument(_)
+            | SourceError::TooLarge(_) => StatusCode::BAD_GATEWAY,
+        }
+    }
+}
+
+/// Whether `host` is listed in `allowed`, where `*.example.com` matches every subdomain of `example.com`.
+pub fn is_allowed(host: &str, allowed: &[String]) -> bool {
+    allowed.iter().any(|pattern| match pattern.strip_prefix("*.") {
+        Some(domain) => host.strip_suffix(domain).is_some_and(|sub| sub.ends_with('.')),
+        None => host.eq_ignore_ascii_case(pattern),
+    })
+}
+
+/// Redirects of the requests to `allowed` hosts, the sources, may only lead to other allowed
+/// hosts, so that a source cannot point the server at internal services. Other requests
+/// follow up to 10 redirects, like by default.
+pub fn redirect_policy(allowed: Vec<String>) -> redirect::Policy {
+    redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > 10 {
+            return attempt.error("too many redirects");
+        }
+        let from_source = attempt.previous().first()
+            .and_then(Url::host_str)
+            .is_some_and(|host| is_allowed(host, &allowed));
+        let url = attempt.url();
+        let host = url.host_str().unwrap_or_default().to_string();
+        if from_source && !(matches!(url.scheme(), "http" | "https") && is_allowed(&host, &allowed)) {
+            return attempt.error(SourceError::HostNotAllowed(host));
+        }
+        attempt.follow()
+    })
+}
+
+/// Extracts the number selected by `path` from `document`. Numeric strings are accepted.
+pub fn extract_value(document: &serde_json::Value, path: &str) -> Result<f32, SourceError> {
+    let json_path = JsonPath::parse(path).map_err(|e| SourceError::InvalidPath(e.to_string()))?;
+    let value = json_path.query(document)
+        .exactly_one()
+        .map_err(|_| SourceError::NoValue(path.to_string()))?;
+    match value {
+        serde_json::Value::Number(x) => x.as_f64().map(|x| x as f32),
+        serde_json::Value::String(x) => x.trim().parse().ok(),
+        _ => None,
+    }.ok_or_else(|| SourceError::NotANumber(value.to_string()))
+}
+
+/// Name of the source fetched from `host` in the [`HealthRegistry`].
+pub fn health_name(host: &str) -> String {
+    format!("source:{host}")
+}
+
+pub async fn fetch_value(
+    client: &Client,
+    config: &SourcesConfig,
+    health: &HealthRegistry,
+    url: &str,
+    path: &str,
+) -> Result<f32, SourceError> {
+    fetch_with(client, config, health, url, |document| extract_value(&document, path)).await
+}
+
+/// Fetches the JSON document at `url`, of at most `max_body_bytes`, and reads it with `read`.
+///
+/// Failures of both are recorded in the [`HealthRegistry`].
+pub async fn fetch_with<T>(
+    client: &Client,
+    config: &SourcesConfig,
+    health: &HealthRegistry,
+    url: &str,
+    read: impl FnOnce(serde_json::Value) -> Result<T, SourceError>,
+) -> Result<T, SourceError> {
+    let parsed = Url::parse(url)
+        .ok()
+        .filter(|x| matches!(x.scheme(), "http" | "https"))
+        .ok_or_else(|| SourceError::InvalidUrl(url.to_string()))?;
+    let host = parsed.host_str().unwrap_or_default();
+    if !is_allowed(host, &config.allowed_hosts) {
+        return Err(SourceError::HostNotAllowed(host.to_string()));
+    }
+
+    let name = health_name(host);
+
+    let fetched = async {
+        let mut response = client.get(parsed.clone())
+            .timeout(Duration::from_secs_f64(config.timeout))
+            .send().await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(SourceError::Fetch)?;
+        let too_large = || SourceError::TooLarge(config.max_body_bytes);
+        if response.content_length().is_some_and(|x| x > config.max_body_bytes as u64) {
+            return Err(too_large());
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(SourceError::Fetch)? {
+            if body.len() + chunk.len() > config.max_body_bytes {
+                return Err(too_large());
+            }
+            body.extend_from_slice(&chunk);
+        }
+        serde_json::from_slice::<serde_json::Value>(&body).map_err(|e| SourceError::InvalidDocument(e.to_string()))
+    }.await;
+    let result = fetched.and_then(read);
+    health.record(&name, url, result.as_ref().map(|_| ()));
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use crate::health::Status;
+    use super::*;
+
+    /// Serves the `responses` to the paths on an ephemeral port of 127.0.0.1, returning its
+    /// address. Every response is a status line with its headers, the body `{"value": 42}`
+    /// being appended to those with status 200.
+    fn serve(responses: &'static [(&'static str, &'static str)]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(&stream);
+                reader.read_line(&mut request).unwrap();
+                // the headers end with an empty line.
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let head = responses.iter().find(|x| x.0 == path).map_or("404 Not Found", |x| x.1);
+                let body = if head.starts_with("200") { r#"{"value": 42}"# } else { "" };
+                let response = format!("HTTP/1.1 {head}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len());
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        address
+    }
+
+    fn config(allowed: &[&str]) -> SourcesConfig {
+        SourcesConfig { allowed_hosts: allowed.iter().map(|x| x.to_string()).collect(), ..Default::default() }
+    }
+
+    fn client(config: &SourcesConfig) -> Client {
+        Client::builder().redirect(redirect_policy(config.allowed_hosts.clone())).build().unwrap()
+    }
+
+    #[test]
+    fn hosts_are_listed_or_subdomains_of_wildcards() {
+        let allowed = ["api.example.com".to_string(), "*.example.org".to_string()];
+        assert!(is_allowed("api.example.com", &allowed));
+        assert!(is_allowed("API.Example.com", &allowed));
+        assert!(!is_allowed("www.example.com", &allowed));
+        assert!(is_allowed("ci.example.org", &allowed));
+        assert!(is_allowed("a.b.example.org", &allowed));
+        // neither the domain of the wildcard itself nor other domains ending alike.
+        assert!(!is_allowed("example.org", &allowed));
+        assert!(!is_allowed("badexample.org", &allowed));
+        assert!(!is_allowed("example.org.evil.com", &allowed));
+        assert!(!is_allowed("anything", &[]));
+    }
+
+    #[actix_web::test]
+    async fn only_allowed_http_urls_are_fetched() {
+        let config = config(&["127.0.0.1", "localhost"]);
+        let health = HealthRegistry::new([]);
+        for url in ["file:///etc/passwd", "ftp://127.0.0.1/value.json", "gopher://localhost/", "not a url"] {
+            let fetched = fetch_value(&client(&config), &config, &health, url, "$.value").await;
+            assert!(matches!(fetched, Err(SourceError::InvalidUrl(_))), "{url}");
+        }
+        let fetched = fetch_value(&client(&config), &config, &health, "http://10.0.0.1/value.json", "$.value").await;
+        assert!(matches!(fetched, Err(SourceError::HostNotAllowed(host)) if host == "10.0.0.1"));
+        // urls rejected before fetching are no fetches of a source.
+        assert!(health.snapshot().is_empty());
+    }
+
+    #[actix_web::test]
+    async fn fetches_are_recorded_in_the_health() {
+        let address = serve(&[("/value.json", "200 OK"), ("/broken.json", "500 Internal Server Error")]);
+        let config = config(&["127.0.0.1"]);
+        let health = HealthRegistry::new([]);
+        let fetch = |path: &str| {
+            let url = format!("http://{address}{path}");
+            let (client, config, health) = (client(&config), config.clone(), &health);
+            async move { fetch_value(&client, &config, health, &url, "$.value").await }
+        };
+
+        assert_eq!(fetch("/value.json").await.unwrap(), 42.0);
+        let source = health.snapshot()["source:127.0.0.1"].clone();
+        assert_eq!(source.status, Status::Ok);
+        assert!(source.url.unwrap().ends_with("/value.json"));
+
+        assert!(matches!(fetch("/broken.json").await, Err(SourceError::Fetch(_))));
+        let source = health.snapshot()["source:127.0.0.1"].clone();
+        assert_eq!(source.status, Status::Error);
+        assert!(source.last_success.is_some() && source.error.is_some());
+
+        // values missing from the document count as failures too.
+        let url = format!("http://{address}/value.json");
+        let fetched = fetch_value(&client(&config), &config, &health, &url, "$.missing").await;
+        assert!(matches!(fetched, Err(SourceError::NoValue(_))));
+        assert_eq!(health.snapshot()["source:127.0.0.1"].status, Status::Error);
+    }
+
+    #[actix_web::test]
+    async fn redirects_stay_on_allowed_hosts() {
+        let address = serve(&[
+            ("/moved.json", "301 Moved Permanently\r\nLocation: /value.json"),
+            ("/internal.json", "302 Found\r\nLocation: http://localhost:9/admin"),
+            ("/file.json", "302 Found\r\nLocation: file:///etc/passwd"),
+            ("/value.json", "200 OK"),
+        ]);
+        let config = config(&["127.0.0.1"]);
+        let health = HealthRegistry::new([]);
+        let fetch = |path: &str| {
+            let url = format!("http://{address}{path}");
+            let (client, config, health) = (client(&config), config.clone(), &health);
+            async move { fetch_value(&client, &config, health, &url, "$.value").await }
+        };
+
+        assert_eq!(fetch("/moved.json").await.unwrap(), 42.0);
+        // the client leaves the redirects to other schemes alone, answering the redirect itself.
+        assert!(matches!(fetch("/file.json").await, Err(SourceError::InvalidDocument(_))));
+        let Err(SourceError::Fetch(e)) = fetch("/internal.json").await else { panic!("the redirect was followed") };
+        assert!(e.is_redirect(), "{e}");
+        let cause = std::error::Error::source(&e).and_then(|x| x.downcast_ref::<SourceError>());
+        assert!(matches!(cause, Some(SourceError::HostNotAllowed(host)) if host == "localhost"), "{e:?}");
+    }
+}
@@ -0,0 +1,526 @@
+//! The description of a bar, given as query of `/render` or stored as JSON.
+use std::borrow::Cow;
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use crate::color_script::ColorScriptError;
+use crate::colors;
+use crate::logos::{self, LogoError};
+use crate::numbers;
+use crate::timespan::TimeError;
+use crate::transforms::TransformError;
+
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct BarSpec {
+    pub title: Option<String>,
+    pub title_width: Option<i32>,
+    pub title_color: Option<Cow<'static, str>>,
+    /// an icon left of the title, one of the bundled [`crate::logos::NAMES`], or a base64
+    /// data URI of an SVG or PNG as `logo_data`, which takes precedence.
+    pub logo: Option<String>,
+    pub logo_data: Option<String>,
+    pub scale: Option<f32>,
+    pub progress: Option<f32>,
+    /// `value`, `min` and `max` place a value within an arbitrary range, e.g.
+    /// `?value=73&min=50&max=90`. `progress` is an alias of `value` and `scale`
+    /// is sugar for `min=0&max=scale`.
+    pub value: Option<f32>,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+    /// `baseline` adds a chip with the difference of the value to it, e.g. `+3.0%` for
+    /// `?progress=82&baseline=79`, colored by whether the value improved or regressed.
+    pub baseline: Option<f32>,
+    /// `done` and `total` count bytes, e.g. of a download or a quota, showing them in `units`
+    /// like `700 MiB / 2 GiB` instead of the percentage they place the bar at.
+    pub done: Option<f64>,
+    pub total: Option<f64>,
+    pub units: Option<Units>,
+    /// `start` and `end` (ISO dates or unix timestamps) compute the progress from the
+    /// time elapsed at render time. Dates without an offset are taken in `tz`, UTC by default.
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub tz: Option<String>,
+    /// `mode=countdown` shows the time left until `until` with a shrinking bar.
+    pub mode: Option<Mode>,
+    pub until: Option<String>,
+    /// `source` is the URL of a JSON document the value is read from with the JSONPath
+    /// `value_path`, `$` by default. It is resolved by the server, not by the renderer.
+    pub source: Option<String>,
+    pub value_path: Option<String>,
+    /// stored bars may instead be the weighted average of other bars and sources,
+    /// resolved by the server as well.
+    pub components: Option<Vec<Component>>,
+    /// named stages like `design`, `build` and `shipped`, replacing the formatted value
+    /// with the stage the value reached. Given as JSON for stored bars and batches.
+    pub states: Option<Vec<State>>,
+    /// name of a pipeline in the `[transforms]` config section the value is passed through.
+    pub transform: Option<String>,
+    pub overflow: Option<OverflowPolicy>,
+    pub progress_width: Option<i32>,
+    pub progress_color: Option<Cow<'static, str>>,
+    /// the colors of the progress unless `progress_color` is given.
+    pub palette: Option<Palette>,
+    /// `color_stops` like `0:#d9534f,100:#5cb85c` place colors at percentages of the range,
+    /// replacing those of the palette, and `color_mode=interpolate` blends them in `color_space`.
+    pub color_mode: Option<ColorMode>,
+    pub color_stops: Option<String>,
+    pub color_space: Option<ColorSpace>,
+    /// hatches the filled part, densely in the lowest third and sparsely in the middle one,
+    /// telling them apart without relying on hue.
+    pub patterns: Option<bool>,
+    pub suffix: Option<Cow<'static, str>>,
+    /// how the value is shown, `percent`, `ratio` like `0.73` or `count` like `146/200`,
+    /// each with its default suffix.
+    pub preset: Option<Preset>,
+    /// the language the value is formatted for, e.g. `de-DE` writing a decimal comma.
+    pub locale: Option<String>,
+    /// makes the bar clickable where SVGs are shown inline, the whole bar if only `link` is
+    /// given, and the title and the progress separately with `link2`.
+    pub link: Option<String>,
+    pub link2: Option<String>,
+    /// replaces the text screen readers announce, `<title>: <value>` by default.
+    pub aria_label: Option<String>,
+    /// `dir=rtl` mirrors the bar, the title on the right and the progress filling leftwards.
+    pub dir: Option<Direction>,
+    /// `adaptive=true` switches to darker colors where the viewer prefers a dark color scheme,
+    /// keeping a `title_color` given explicitly.
+    pub adaptive: Option<bool>,
+    /// the named template drawing the bar instead of the default one, e.g. `?template=flat`.
+    pub template: Option<String>,
+    /// the output format, negotiated with the `Accept` header if not given.
+    pub format: Option<Format>,
+    /// `density=2` rasterizes PNGs with twice the pixels of the declared size, for high-DPI
+    /// displays. It has no effect on SVGs.
+    pub density: Option<u32>,
+    /// replaces the formatted value in the bar, e.g. `?label=3 of 10 done`. Routes rendering
+    /// fetched data set it unless it is given.
+    pub label: Option<String>,
+    /// where the formatted value is drawn, `none` hiding it.
+    pub label_position: Option<LabelPosition>,
+    /// the instant time based progress is evaluated at instead of now, set for snapshots.
+    #[serde(skip)]
+    pub as_of: Option<chrono::DateTime<chrono::Utc>>,
+    // a workaround to handle that quarto adds an image extension to the URL automatically.
+    // In this case, use the url like: https://ip:port/render?progress=39&title=xxx&blackhole=1
+    // By this way, even if the url is modified to something like
+    // https://ip:port/render?progress=39&title=xxx&blackhole=1.png
+    // it will not affect the other TRUE query parameters.
+    pub blackhole: Option<String>,
+}
+
+impl BarSpec {
+    /// The fields holding values no bar can be drawn with, along with the problem of each,
+    /// whether the spec comes from a query or from JSON.
+    pub fn invalid_fields(&self) -> Vec<(&'static str, SpecError)> {
+        let mut invalid = Vec::new();
+        let numbers = [("progress", self.progress), ("value", self.value), ("min", self.min), ("max", self.max), ("baseline", self.baseline)];
+        for (field, number) in numbers {
+            if let Some(x) = number.filter(|x| !x.is_finite()) {
+                invalid.push((field, SpecError::NotFinite(f64::from(x))));
+            }
+        }
+        match self.scale {
+            Some(x) if !x.is_finite() => invalid.push(("scale", SpecError::NotFinite(f64::from(x)))),
+            Some(x) if x <= 0.0 => invalid.push(("scale", SpecError::InvalidScale(x))),
+            _ => {},
+        }
+        for (field, bytes) in [("done", self.done), ("total", self.total)] {
+            if let Some(x) = bytes.filter(|x| !x.is_finite() || *x < 0.0) {
+                invalid.push((field, SpecError::InvalidByteCount(x)));
+            }
+        }
+        for (field, width, min) in [("title_width", self.title_width, 0), ("progress_width", self.progress_width, 1)] {
+            if let Some(width) = width.filter(|x| !(min..=MAX_WIDTH).contains(x)) {
+                invalid.push((field, SpecError::InvalidWidth { width, min }));
+            }
+        }
+        for (field, color) in [("title_color", &self.title_color), ("progress_color", &self.progress_color)] {
+            if let Some(color) = color.as_deref().filter(|x| !is_color(x)) {
+                invalid.push((field, SpecError::InvalidColor(color.to_string())));
+            }
+        }
+        if let Some(density) = self.density.filter(|x| !(1..=MAX_DENSITY).contains(x)) {
+            invalid.push(("density", SpecError::InvalidDensity(density)));
+        }
+        let checks = [
+            ("color_stops", self.color_stops.as_deref().and_then(|x| colors::check_color_stops(x).err())),
+            ("locale", self.locale.as_deref().and_then(|x| numbers::decimal_separator(x).err())),
+            ("link", self.link.as_deref().and_then(|x| check_link(x).err())),
+            ("link2", self.link2.as_deref().and_then(|x| check_link(x).err())),
+            ("logo", self.logo.as_deref().and_then(|x| logos::named(x).err().map(SpecError::Logo))),
+            ("logo_data", self.logo_data.as_deref().and_then(|x| logos::check_data(x).err().map(SpecError::Logo))),
+        ];
+        invalid.extend(checks.into_iter().filter_map(|(field, e)| Some((field, e?))));
+        invalid
+    }
+
+    /// Fails with the problem of the first of [`BarSpec::invalid_fields`], if any.
+    pub fn validate(&self) -> Result<(), SpecError> {
+        match self.invalid_fields().into_iter().next() {
+            Some((_, e)) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the bar changes by itself as time passes.
+    pub fn is_time_based(&self) -> bool {
+        self.as_of.is_none()
+            && (self.start.is_some() || self.end.is_some() || self.mode == Some(Mode::Countdown))
+    }
+}
+
+/// Widths beyond this are surely mistakes, and would only waste memory when rasterized.
+pub const MAX_WIDTH: i32 = 2000;
+
+/// The color keywords of CSS, which SVG renderers understand.
+const NAMED_COLORS: &[&str] = &[
+    "aliceblue", "antiquewhite", "aqua", "aquamarine", "azure", "beige", "bisque", "black",
+    "blanchedalmond", "blue", "blueviolet", "brown", "burlywood", "cadetblue", "chartreuse",
+    "chocolate", "coral", "cornflowerblue", "cornsilk", "crimson", "cyan", "darkblue", "darkcyan",
+    "darkgoldenrod", "darkgray", "darkgreen", "darkgrey", "darkkhaki", "darkmagenta",
+    "darkolivegreen", "darkorange", "darkorchid", "darkred", "darksalmon", "darkseagreen",
+    "darkslateblue", "darkslategray", "darkslategrey", "darkturquoise", "darkviolet", "deeppink",
+    "deepskyblue", "dimgray", "dimgrey", "dodgerblue", "firebrick", "floralwhite", "forestgreen",
+    "fuchsia", "gainsboro", "ghostwhite", "gold", "goldenrod", "gray", "green", "greenyellow",
+    "grey", "honeydew", "hotpink", "indianred", "indigo", "ivory", "khaki", "lavender",
+    "lavenderblush", "lawngreen", "lemonchiffon", "lightblue", "lightcoral", "lightcyan",
+    "lightgoldenrodyellow", "lightgray", "lightgreen", "lightgrey", "lightpink", "lightsalmon",
+    "lightseagreen", "lightskyblue", "lightslategray", "lightslategrey", "lightsteelblue",
+    "lightyellow", "lime", "limegreen", "linen", "magenta", "maroon", "mediumaquamarine",
+    "mediumblue", "mediumorchid", "mediumpurple", "mediumseagreen", "mediumslateblue",
+    "mediumspringgreen", "mediumturquoise", "mediumvioletred", "midnightblue", "mintcream",
+    "mistyrose", "moccasin", "navajowhite", "navy", "oldlace", "olive", "olivedrab", "orange",
+    "orangered", "orchid", "palegoldenrod", "palegreen", "paleturquoise", "palevioletred",
+    "papayawhip", "peachpuff", "peru", "pink", "plum", "powderblue", "purple", "rebeccapurple",
+    "red", "rosybrown", "royalblue", "saddlebrown", "salmon", "sandybrown", "seagreen", "seashell",
+    "sienna", "silver", "skyblue", "slateblue", "slategray", "slategrey", "snow", "springgreen",
+    "steelblue", "tan", "teal", "thistle", "tomato", "turquoise", "violet", "wheat", "white",
+    "whitesmoke", "yellow", "yellowgreen", "currentcolor", "transparent",
+];
+
+/// Whether `color` is a hex color, a color keyword or an `rgb()`/`hsl()` function, which is
+/// all SVG needs and keeps markup out of the attributes.
+pub fn is_color(color: &str) -> bool {
+    if let Some(hex) = color.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8) && hex.bytes().all(|b| b.is_ascii_hexdigit());
+    }
+    if let Some(args) = ["rgb(", "rgba(", "hsl(", "hsla("].iter().find_map(|f| color.strip_prefix(f)) {
+        return args.strip_suffix(')').is_some_and(|args| args.bytes()
+            .all(|b| b.is_ascii_digit() || b" .,%/".contains(&b) || b == b'-'));
+    }
+    NAMED_COLORS.iter().any(|x| x.eq_ignore_ascii_case(color))
+}
+
+/// Longer links are surely mistakes, and browsers cut them off anyway.
+pub const MAX_LINK_LEN: usize = 2048;
+
+/// Checks that `url` is an absolute http(s) URL without characters which would have to be
+/// encoded, so that it can be placed in the markup without surprises.
+pub fn check_link(url: &str) -> Result<(), SpecError> {
+    let rest = url.strip_prefix("https://").or_else(|| url.strip_prefix("http://"));
+    let valid = rest.is_some_and(|x| !x.is_empty() && !x.starts_with('/'))
+        && url.len() <= MAX_LINK_LEN
+        && url.bytes().all(|b| b.is_ascii_graphic() && !b"\"<>\\`{}|^".contains(&b));
+    if !valid {
+        return Err(SpecError::InvalidLink(url.to_string()));
+    }
+    Ok(())
+}
+
+/// One part of a composed bar, either the stored bar `bar` or the value read from `source`,
+/// which is placed within `min` and `max`, 0 and 100 by default.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Component {
+    pub weight: f32,
+    pub bar: Option<String>,
+    pub source: Option<String>,
+    pub value_path: Option<String>,
+    pub min: Option<f32>,
+    pub max: Option<f32>,
+}
+
+/// A stage of a bar, reached once the value is at least `from`, in the units of the value.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct State {
+    pub name: String,
+    pub from: f32,
+}
+
+impl State {
+    /// The last of `states` reached by `value`.
+    pub fn reached(states: &[State], value: f32) -> Option<&State> {
+        states.iter()
+            .filter(|x| x.from <= value)
+            .max_by(|a, b| a.from.total_cmp(&b.from))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Show how far the value got.
+    #[default]
+    Progress,
+    /// Show the time left until a deadline.
+    Countdown,
+}
+
+/// Formats of the value, setting the range, the suffix and the decimals together.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Preset {
+    /// The share of the range, like `73%` or `73.5%`.
+    Percent,
+    /// The share of the range with two decimals, like `0.73`, the range being 0 to 1 unless
+    /// given.
+    Ratio,
+    /// The value of the maximum, like `146/200`, without a suffix unless given.
+    Count,
+}
+
+impl Preset {
+    /// The end of the range unless `max` or `scale` are given.
+    pub fn default_max(self) -> f32 {
+        if self == Preset::Ratio { 1.0 } else { 100.0 }
+    }
+
+    /// The suffix unless one is given.
+    pub fn default_suffix(self) -> &'static str {
+        if self == Preset::Percent { "%" } else { "" }
+    }
+}
+
+/// The units byte counts are shown in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// Powers of 1024, like `MiB`.
+    #[default]
+    Bytes,
+    /// Powers of 1000, like `MB`.
+    Si,
+}
+
+impl Units {
+    /// Formats `bytes` with the largest unit it reaches and a single decimal, dropped if zero,
+    /// e.g. `1.5 GiB` or `700 MiB`.
+    pub fn format(self, bytes: f64) -> String {
+        let (base, units) = match self {
+            Units::Bytes => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB", "PiB"]),
+            Units::Si => (1000.0, ["B", "kB", "MB", "GB", "TB", "PB"]),
+        };
+        let mut amount = bytes;
+        let mut unit = 0;
+        // the unit is chosen after rounding, so that a byte short of 1 MiB is not `1024 KiB`.
+        while (amount.abs() * 10.0).round() / 10.0 >= base && unit + 1 < units.len() {
+            amount /= base;
+            unit += 1;
+        }
+        let amount = format!("{amount:.1}");
+        format!("{} {}", amount.strip_suffix(".0").unwrap_or(&amount), units[unit])
+    }
+}
+
+/// What to do with values outside of `[min, max]`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    /// Clamp the value to the range.
+    #[default]
+    Clamp,
+    /// Reject the request.
+    Error,
+    /// Keep the value and render an over-100% marker.
+    Allow,
+}
+
+/// Raster images are at most this many times their declared size.
+pub const MAX_DENSITY: u32 = 4;
+
+/// The default colors of the progress, from low to high.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    /// Red, yellow and green.
+    #[default]
+    Default,
+    /// Vermillion, orange and blue, which stay distinct with red-green color blindness.
+    Colorblind,
+}
+
+impl Palette {
+    /// The colors and the percentages of the range they start at.
+    pub fn stops(self) -> &'static [(f32, &'static str)] {
+        match self {
+            Palette::Default => &[(0.0, "#d9534f"), (30.0, "#f0ad4e"), (70.0, "#5cb85c")],
+            Palette::Colorblind => &[(0.0, "#d55e00"), (30.0, "#e69f00"), (70.0, "#0072b2")],
+        }
+    }
+
+    /// The color of the progress at `ratio`.
+    pub fn color(self, ratio: f32) -> &'static str {
+        let stops = self.stops();
+        stops.iter().rev().find(|(at, _)| ratio * 100.0 >= *at).unwrap_or(&stops[0]).1
+    }
+
+    /// The color of a change of the value by `delta`, higher values being better.
+    pub fn delta_color(self, delta: f32) -> &'static str {
+        match (self, delta) {
+            (_, 0.0) => "#9f9f9f",
+            (Palette::Default, x) if x > 0.0 => "#4c1",
+            (Palette::Default, _) => "#e05d44",
+            (Palette::Colorblind, x) if x > 0.0 => "#0072b2",
+            (Palette::Colorblind, _) => "#d55e00",
+        }
+    }
+}
+
+/// How the colors of the stops are spread over the range.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Each color up to the next stop.
+    #[default]
+    Bands,
+    /// Blended between the stops.
+    Interpolate,
+}
+
+/// The space colors are blended in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    /// Perceptually even steps of lightness.
+    #[default]
+    Lab,
+    /// Round the hue circle, keeping the colors saturated.
+    Hsl,
+}
+
+/// The placement of the formatted value.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelPosition {
+    /// Centered over the progress.
+    #[default]
+    Center,
+    /// Within the filled part, in a color contrasting to it, or right after the fill if
+    /// it is too narrow.
+    Inside,
+    /// After the bar, which grows by the width of the text.
+    Outside,
+    /// Hidden.
+    None,
+}
+
+/// The direction of the layout and the text of the title.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Title on the left, filling rightwards.
+    #[default]
+    Ltr,
+    /// Title on the right, filling leftwards, e.g. for Arabic or Hebrew titles.
+    Rtl,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Svg,
+    Png,
+    /// The resolved template context instead of an image.
+    Json,
+}
+
+
+#[derive(Debug)]
+pub enum SpecError {
+    MissingValue,
+    EmptyRange { min: f32, max: f32 },
+    OutOfRange { value: f32, min: f32, max: f32 },
+    IncompleteTimespan,
+    IncompleteByteCount,
+    MissingDeadline,
+    Time(TimeError),
+    Transform(TransformError),
+    Color(ColorScriptError),
+    Logo(LogoError),
+    InvalidLink(String),
+    InvalidDensity(u32),
+    NotFinite(f64),
+    InvalidScale(f32),
+    InvalidByteCount(f64),
+    InvalidWidth { width: i32, min: i32 },
+    InvalidColor(String),
+    InvalidLocale(String),
+    InvalidColorStops(String),
+    UnknownTemplate(String),
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpecError::MissingValue =>
+                write!(f, "either `progress` or `value` is required"),
+            SpecError::EmptyRange { min, max } =>
+                write!(f, "`max` ({max}) must be greater than `min` ({min})"),
+            SpecError::OutOfRange { value, min, max } =>
+                write!(f, "value {value} is outside of the range [{min}, {max}]"),
+            SpecError::IncompleteTimespan =>
+                write!(f, "`start` and `end` must be given together"),
+            SpecError::IncompleteByteCount =>
+                write!(f, "`done` and `total` must be given together, with `total` greater than 0"),
+            SpecError::MissingDeadline =>
+                write!(f, "`mode=countdown` requires `until`"),
+            SpecError::Time(e) => e.fmt(f),
+            SpecError::Transform(e) => e.fmt(f),
+            SpecError::Color(e) => e.fmt(f),
+            SpecError::Logo(e) => e.fmt(f),
+            SpecError::InvalidLink(url) =>
+                write!(f, "`{url}` is not a valid http(s) link"),
+            SpecError::InvalidDensity(density) =>
+                write!(f, "density {density} is not between 1 and {MAX_DENSITY}"),
+            SpecError::NotFinite(x) => write!(f, "{x} is not a finite number"),
+            SpecError::InvalidScale(scale) => write!(f, "scale {scale} is not greater than 0"),
+            SpecError::InvalidByteCount(x) => write!(f, "{x} is not a number of bytes"),
+            SpecError::InvalidWidth { width, min } =>
+                write!(f, "width {width} is not a whole number from {min} to {MAX_WIDTH}"),
+            SpecError::InvalidColor(color) =>
+                write!(f, "`{color}` is not a color like #4c1, #44cc11, green or rgb(68, 204, 17)"),
+            SpecError::InvalidLocale(locale) =>
+                write!(f, "`{locale}` is not a language tag like `de-DE`"),
+            SpecError::InvalidColorStops(stops) =>
+                write!(f, "`{stops}` are not color stops like `0:#d9534f,100:#5cb85c`"),
+            SpecError::UnknownTemplate(name) => write!(f, "there is no template `{name}`"),
+        }
+    }
+}
+
+impl std::error::Error for SpecError {}
+
+impl From<TimeError> for SpecError {
+    fn from(e: TimeError) -> Self {
+        SpecError::Time(e)
+    }
+}
+
+impl From<TransformError> for SpecError {
+    fn from(e: TransformError) -> Self {
+        SpecError::Transform(e)
+    }
+}
+
+impl From<ColorScriptError> for SpecError {
+    fn from(e: ColorScriptError) -> Self {
+        SpecError::Color(e)
+    }
+}
+
+impl From<LogoError> for SpecError {
+    fn from(e: LogoError) -> Self {
+        SpecError::Logo(e)
+    }
+}
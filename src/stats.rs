@@ -0,0 +1,201 @@
+//! Anonymous usage statistics over a rolling window, served at `/stats` to help operators
+//! decide which styles to precache or deprecate. Neither addresses nor parameter values
+//! are kept, only the names of the parameters, the formats and the referring hosts.
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, http::header, web};
+use actix_web::middleware::Next;
+use serde::Serialize;
+
+
+/// Requests are counted in buckets of this length, the window moving by one bucket at a time.
+const BUCKET: Duration = Duration::from_secs(60);
+/// Entries listed per category.
+const TOP: usize = 20;
+
+#[derive(Default)]
+struct Counts {
+    requests: u64,
+    parameters: HashMap<String, u64>,
+    formats: HashMap<String, u64>,
+    referrers: HashMap<String, u64>,
+    denied: HashMap<String, u64>,
+}
+
+#[derive(Serialize)]
+pub struct Entry {
+    pub key: String,
+    pub count: u64,
+}
+
+#[derive(Serialize)]
+pub struct Summary {
+    pub window_secs: u64,
+    pub requests: u64,
+    /// Route names with the sorted names of their query parameters, e.g. `render?progress&title`.
+    pub parameters: Vec<Entry>,
+    pub formats: Vec<Entry>,
+    pub referrers: Vec<Entry>,
+    /// Requests rejected by `--deny-cidr` and `--allow-cidr`, by the rule rejecting them.
+    pub denied: Vec<Entry>,
+}
+
+pub struct UsageStats {
+    window: Duration,
+    buckets: Mutex<VecDeque<(Instant, Counts)>>,
+}
+
+/// The most frequent of `counts`, the most frequent first.
+pub fn top(counts: HashMap<String, u64>) -> Vec<Entry> {
+    let mut entries: Vec<_> = counts.into_iter().map(|(key, count)| Entry { key, count }).collect();
+    entries.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.key.cmp(&b.key)));
+    entries.truncate(TOP);
+    entries
+}
+
+/// The format of a response, taken from its content type.
+fn format_of(content_type: &str) -> Option<&'static str> {
+    match content_type.split(';').next()?.trim() {
+        "image/svg+xml" => Some("svg"),
+        "image/png" => Some("png"),
+        "application/json" => Some("json"),
+        _ => None,
+    }
+}
+
+impl UsageStats {
+    pub fn new(window: Duration) -> Self {
+        UsageStats { window, buckets: Mutex::new(VecDeque::new()) }
+    }
+
+    /// Updates the counts of the current bucket, dropping those out of the window.
+    fn count(&self, update: impl FnOnce(&mut Counts)) {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        while buckets.front().is_some_and(|(start, _)| now.duration_since(*start) >= self.window) {
+            buckets.pop_front();
+        }
+        if buckets.back().is_none_or(|(start, _)| now.duration_since(*start) >= BUCKET) {
+            buckets.push_back((now, Counts::default()));
+        }
+        let (_, counts) = buckets.back_mut().unwrap();
+        update(counts);
+    }
+
+    /// Counts a request rejected by `rule`.
+    pub fn record_denied(&self, rule: String) {
+        self.count(|counts| *counts.denied.entry(rule).or_default() += 1);
+    }
+
+    fn record(&self, parameters: String, format: Option<&str>, referrer: Option<String>) {
+        self.count(|counts| {
+            counts.requests += 1;
+            *counts.parameters.entry(parameters).or_default() += 1;
+            if let Some(format) = format {
+                *counts.formats.entry(format.to_string()).or_default() += 1;
+            }
+            if let Some(referrer) = referrer {
+                *counts.referrers.entry(referrer).or_default() += 1;
+            }
+        });
+    }
+
+    pub fn summary(&self) -> Summary {
+        let buckets = self.buckets.lock().unwrap();
+        let mut total = Counts::default();
+        for (_, counts) in buckets.iter().filter(|(start, _)| start.elapsed() < self.window) {
+            total.requests += counts.requests;
+            for (into, from) in [
+                (&mut total.parameters, &counts.parameters),
+                (&mut total.formats, &counts.formats),
+                (&mut total.referrers, &counts.referrers),
+                (&mut total.denied, &counts.denied),
+            ] {
+                for (key, count) in from {
+                    *into.entry(key.clone()).or_default() += count;
+                }
+            }
+        }
+        Summary {
+            window_secs: self.window.as_secs(),
+            requests: total.requests,
+            parameters: top(total.parameters),
+            formats: top(total.formats),
+            referrers: top(total.referrers),
+            denied: top(total.denied),
+        }
+    }
+}
+
+/// Middleware counting the requests of the named routes in the [`UsageStats`] found in the
+/// app data, except for `/stats` itself.
+pub async fn record(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let stats = req.app_data::<web::Data<UsageStats>>().cloned();
+    let route = req.match_name().filter(|name| *name != "stats").map(str::to_string);
+    let (Some(stats), Some(route)) = (stats, route) else {
+        return next.call(req).await;
+    };
+
+    let mut names: Vec<_> = req.query_string().split('&')
+        .filter_map(|pair| pair.split('=').next())
+        .filter(|name| !name.is_empty() && *name != "blackhole")
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    let parameters = format!("{route}?{}", names.join("&"));
+    let referrer = req.headers().get(header::REFERER)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| reqwest::Url::parse(x).ok())
+        .and_then(|x| x.host_str().map(str::to_string));
+
+    let response = next.call(req).await?;
+    let format = response.headers().get(header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .and_then(format_of);
+    stats.record(parameters, format, referrer);
+    Ok(response)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_roll_out_of_the_window() {
+        let stats = UsageStats::new(Duration::from_millis(50));
+        stats.record("render?progress".to_string(), Some("svg"), Some("example.com".to_string()));
+        stats.record("render?progress".to_string(), Some("png"), None);
+        stats.record_denied("10.0.0.0/8".to_string());
+        let summary = stats.summary();
+        assert_eq!((summary.requests, summary.parameters[0].count), (2, 2));
+        assert_eq!(summary.formats.iter().map(|x| x.key.as_str()).collect::<Vec<_>>(), ["png", "svg"]);
+        assert_eq!(summary.referrers[0].key, "example.com");
+        assert_eq!(summary.denied[0].key, "10.0.0.0/8");
+
+        std::thread::sleep(Duration::from_millis(60));
+        let summary = stats.summary();
+        assert_eq!(summary.requests, 0);
+        assert!(summary.parameters.is_empty() && summary.denied.is_empty());
+        stats.record("bar?".to_string(), None, None);
+        assert_eq!(stats.buckets.lock().unwrap().len(), 1, "the expired bucket is evicted");
+        let summary = stats.summary();
+        assert_eq!((summary.requests, summary.parameters[0].key.as_str()), (1, "bar?"));
+        assert!(summary.formats.is_empty());
+    }
+
+    #[test]
+    fn top_lists_the_most_frequent_first() {
+        let counts = (0..TOP as u64 + 5).map(|i| (format!("key{i:02}"), i / 2)).collect();
+        let entries = top(counts);
+        assert_eq!(entries.len(), TOP);
+        let keys: Vec<_> = entries.iter().take(4).map(|x| (x.key.as_str(), x.count)).collect();
+        assert_eq!(keys, [("key24", 12), ("key22", 11), ("key23", 11), ("key20", 10)]);
+        assert!(entries.windows(2).all(|x| x[0].count >= x[1].count));
+        assert!(top(HashMap::new()).is_empty());
+    }
+}
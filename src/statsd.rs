@@ -0,0 +1,133 @@
+//! A StatsD listener setting the values of stored bars from gauges, so existing metric
+//! emitters can drive bars without any HTTP integration, e.g.
+//!
+//! ```text
+//! pbar.release.progress:72|g
+//! pbar.release.progress:+3|g
+//! ```
+//!
+//! set the bar `release` to 72 and then to 75. Other metric types are ignored, and so are
+//! gauges of bars which neither exist nor are listed in `create_bars`.
+use std::fmt;
+use actix_web::{rt, web};
+use log::{debug, info, warn};
+use tokio::net::UdpSocket;
+use crate::bars::BarStore;
+use crate::config::StatsdConfig;
+
+
+/// Largest datagram read, as sent by StatsD clients on most networks.
+const MAX_DATAGRAM: usize = 8192;
+
+#[derive(Debug, PartialEq)]
+enum Gauge {
+    Set(f32),
+    /// Values with a sign change the current value, as in StatsD.
+    Add(f32),
+}
+
+#[derive(Debug, PartialEq)]
+enum LineError {
+    Malformed,
+    NotAGauge,
+    UnknownMetric,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineError::Malformed => write!(f, "malformed metric"),
+            LineError::NotAGauge => write!(f, "not a gauge"),
+            LineError::UnknownMetric => write!(f, "not a `<prefix><id>.progress` gauge"),
+        }
+    }
+}
+
+/// The bar and gauge of a line like `pbar.<id>.progress:<value>|g`, possibly followed by a
+/// sample rate or tags.
+fn parse_line<'a>(line: &'a str, prefix: &str) -> Result<(&'a str, Gauge), LineError> {
+    let (name, rest) = line.split_once(':').ok_or(LineError::Malformed)?;
+    let mut fields = rest.split('|');
+    let value = fields.next().ok_or(LineError::Malformed)?.trim();
+    if fields.next().map(str::trim) != Some("g") {
+        return Err(LineError::NotAGauge);
+    }
+    let id = name.trim()
+        .strip_prefix(prefix)
+        .and_then(|x| x.strip_suffix(".progress"))
+        .filter(|x| !x.is_empty() && x.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-'))
+        .ok_or(LineError::UnknownMetric)?;
+    let number: f32 = value.parse().ok().filter(|x: &f32| x.is_finite()).ok_or(LineError::Malformed)?;
+    let gauge = if value.starts_with(['+', '-']) { Gauge::Add(number) } else { Gauge::Set(number) };
+    Ok((id, gauge))
+}
+
+fn apply(store: &BarStore, config: &StatsdConfig, datagram: &str) {
+    for line in datagram.lines().filter(|x| !x.trim().is_empty()) {
+        let (id, gauge) = match parse_line(line, &config.prefix) {
+            Ok(x) => x,
+            Err(e) => {
+                debug!("Ignoring StatsD line `{}`: {}", line, e);
+                continue;
+            },
+        };
+        let create = config.create_bars.iter().any(|x| x == id);
+        let result = match gauge {
+            Gauge::Set(value) => store.update_value(id, create, |_| value),
+            Gauge::Add(delta) => store.update_value(id, create, |x| x.unwrap_or(0.0) + delta),
+        };
+        match result {
+            Ok(true) => {},
+            Ok(false) => debug!("Ignoring StatsD line `{}`: bar {} does not exist", line, id),
+            Err(e) => warn!("Failed to apply StatsD line `{}`: {:#}", line, e),
+        }
+    }
+}
+
+/// Listens for gauges if `config.bind` is given.
+pub async fn spawn(config: &StatsdConfig, store: web::Data<BarStore>) -> anyhow::Result<()> {
+    let Some(bind) = &config.bind else { return Ok(()) };
+    let socket = UdpSocket::bind(bind).await
+        .map_err(|e| anyhow::anyhow!("failed to bind the StatsD listener to {bind}: {e}"))?;
+    info!("StatsD listener bound to {}.", bind);
+    let config = config.clone();
+    rt::spawn(async move {
+        let mut buf = vec![0; MAX_DATAGRAM];
+        loop {
+            match socket.recv(&mut buf).await {
+                Ok(len) => apply(&store, &config, &String::from_utf8_lossy(&buf[..len])),
+                Err(e) => warn!("Failed to receive StatsD metrics: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use progress_bar::BarSpec;
+
+    #[test]
+    fn gauges_name_the_bar() {
+        assert_eq!(parse_line("pbar.release.progress:72|g", "pbar."), Ok(("release", Gauge::Set(72.0))));
+        assert_eq!(parse_line("pbar.a-b.progress:-2.5|g|#env:ci", "pbar."), Ok(("a-b", Gauge::Add(-2.5))));
+        assert_eq!(parse_line("pbar.release.progress:1|c", "pbar."), Err(LineError::NotAGauge));
+        assert_eq!(parse_line("other.release.progress:1|g", "pbar."), Err(LineError::UnknownMetric));
+        assert_eq!(parse_line("pbar.x.y.progress:1|g", "pbar."), Err(LineError::UnknownMetric));
+        assert_eq!(parse_line("pbar.release.progress:nan|g", "pbar."), Err(LineError::Malformed));
+    }
+
+    #[test]
+    fn gauges_create_listed_bars_only() {
+        let store = BarStore::open(None, 0).unwrap();
+        let config = StatsdConfig { create_bars: vec!["release".into()], ..Default::default() };
+        apply(&store, &config, "pbar.release.progress:72|g\npbar.other.progress:10|g");
+        assert_eq!(store.get("release").unwrap().spec.value, Some(72.0));
+        assert!(store.get("other").is_none());
+        store.put("other", BarSpec { value: Some(1.0), ..Default::default() }).unwrap();
+        apply(&store, &config, "pbar.other.progress:+2|g");
+        assert_eq!(store.get("other").unwrap().spec.value, Some(3.0));
+    }
+}
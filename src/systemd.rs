@@ -0,0 +1,47 @@
+//! Readiness and watchdog notifications for the systemd service manager.
+//!
+//! All functions are no-ops when the process is not started by systemd.
+use std::time::Duration;
+use actix_web::rt;
+use log::{error, info, warn};
+use sd_notify::NotifyState;
+
+
+fn notify(state: NotifyState) {
+    if let Err(e) = sd_notify::notify(&[state]) {
+        warn!("Failed to notify systemd: {}", e);
+    }
+}
+
+/// Tells systemd that startup is finished and requests can be served.
+pub fn notify_ready() {
+    notify(NotifyState::Ready);
+}
+
+/// Tells systemd that the service is shutting down.
+pub fn notify_stopping() {
+    notify(NotifyState::Stopping);
+}
+
+/// Pings the systemd watchdog every half of `WATCHDOG_USEC` as long as `probe` succeeds.
+///
+/// A failing probe withholds the ping, so systemd restarts the service once the
+/// watchdog timeout elapses.
+pub fn spawn_watchdog<F>(probe: F)
+    where F: Fn() -> anyhow::Result<()> + 'static
+{
+    let Some(timeout) = sd_notify::watchdog_enabled() else { return };
+    let period = (timeout / 2).max(Duration::from_millis(1));
+    info!("systemd watchdog enabled, pinging every {:?}.", period);
+
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(period);
+        loop {
+            interval.tick().await;
+            match probe() {
+                Ok(()) => notify(NotifyState::Watchdog),
+                Err(e) => error!("Watchdog self-test failed, withholding the ping: {}", e),
+            }
+        }
+    });
+}
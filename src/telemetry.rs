@@ -0,0 +1,206 @@
+//! Spans of the requests exported to an OpenTelemetry collector with OTLP/HTTP in its JSON
+//! encoding when `--otlp-endpoint` is given. Every request has a span, with children around
+//! fetching sources, building the context and rendering the template. A W3C `traceparent`
+//! header continues the trace of the caller.
+use std::time::{Duration, SystemTime};
+use actix_web::{body::MessageBody, dev::{ServiceRequest, ServiceResponse}, rt, web, HttpMessage, HttpRequest};
+use actix_web::middleware::Next;
+use anyhow::Context;
+use log::warn;
+use reqwest::Url;
+use serde_json::json;
+use tokio::sync::mpsc;
+use crate::request_id::{random_u128, RequestId};
+
+
+/// Finished spans are sent to the collector this often.
+const EXPORT_PERIOD: Duration = Duration::from_secs(5);
+/// Spans waiting for the export, further spans being dropped.
+const QUEUE: usize = 4096;
+
+struct SpanData {
+    trace_id: u128,
+    span_id: u64,
+    parent_id: Option<u64>,
+    /// Whether this is the span of a request rather than one of its steps.
+    server: bool,
+    name: String,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+    error: bool,
+}
+
+/// The span of a request, the parent of the spans of its steps.
+#[derive(Clone, Copy)]
+struct TraceContext {
+    trace_id: u128,
+    span_id: u64,
+}
+
+/// Collects the spans, doing nothing unless created with [`Tracer::export_to`].
+#[derive(Default)]
+pub struct Tracer {
+    sender: Option<mpsc::Sender<SpanData>>,
+}
+
+fn span_id() -> u64 {
+    (random_u128() as u64).max(1)
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos().to_string()
+}
+
+fn to_otlp(spans: &[SpanData]) -> serde_json::Value {
+    let spans: Vec<_> = spans.iter().map(|x| json!({
+        "traceId": format!("{:032x}", x.trace_id),
+        "spanId": format!("{:016x}", x.span_id),
+        "parentSpanId": x.parent_id.map_or(String::new(), |id| format!("{id:016x}")),
+        "name": x.name,
+        "kind": if x.server { 2 } else { 1 },
+        "startTimeUnixNano": unix_nanos(x.start),
+        "endTimeUnixNano": unix_nanos(x.end),
+        "attributes": x.attributes.iter()
+            .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+            .collect::<Vec<_>>(),
+        "status": { "code": if x.error { 2 } else { 0 } },
+    })).collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{ "key": "service.name", "value": { "stringValue": env!("CARGO_PKG_NAME") } }],
+            },
+            "scopeSpans": [{ "scope": { "name": env!("CARGO_PKG_NAME") }, "spans": spans }],
+        }],
+    })
+}
+
+impl Tracer {
+    /// Exports the spans to the OTLP/HTTP collector at `endpoint`, e.g. `http://localhost:4318`.
+    pub fn export_to(endpoint: &str) -> anyhow::Result<Self> {
+        let url = Url::parse(endpoint)
+            .and_then(|x| x.join("v1/traces"))
+            .with_context(|| format!("invalid OTLP endpoint `{endpoint}`"))?;
+        let client = reqwest::Client::builder().timeout(EXPORT_PERIOD).build()?;
+        let (sender, mut receiver) = mpsc::channel(QUEUE);
+        rt::spawn(async move {
+            let mut interval = rt::time::interval(EXPORT_PERIOD);
+            loop {
+                interval.tick().await;
+                let mut spans = Vec::new();
+                while let Ok(span) = receiver.try_recv() {
+                    spans.push(span);
+                }
+                if spans.is_empty() {
+                    continue;
+                }
+                let result = client.post(url.clone()).json(&to_otlp(&spans)).send().await
+                    .and_then(|x| x.error_for_status());
+                if let Err(e) = result {
+                    warn!("Failed to export {} span(s) to {}: {}", spans.len(), url, e);
+                }
+            }
+        });
+        Ok(Tracer { sender: Some(sender) })
+    }
+
+    fn record(&self, span: SpanData) {
+        if let Some(sender) = &self.sender {
+            // dropping spans is better than slowing down the requests.
+            let _ = sender.try_send(span);
+        }
+    }
+}
+
+/// The span of a step of a request, ending when dropped.
+pub struct Span {
+    tracer: web::Data<Tracer>,
+    data: Option<SpanData>,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        if let Some(mut data) = self.data.take() {
+            data.end = SystemTime::now();
+            self.tracer.record(data);
+        }
+    }
+}
+
+/// Starts the span `name` within the span of `req`, `None` if tracing is disabled.
+pub fn span(req: &HttpRequest, name: &str) -> Option<Span> {
+    let tracer = req.app_data::<web::Data<Tracer>>()?.clone();
+    let parent = *req.extensions().get::<TraceContext>()?;
+    Some(Span {
+        tracer,
+        data: Some(SpanData {
+            trace_id: parent.trace_id,
+            span_id: span_id(),
+            parent_id: Some(parent.span_id),
+            server: false,
+            name: name.to_string(),
+            start: SystemTime::now(),
+            end: SystemTime::now(),
+            attributes: Vec::new(),
+            error: false,
+        }),
+    })
+}
+
+/// The trace ID and parent span ID of a `traceparent` header, `00-<trace>-<span>-<flags>`.
+fn parse_traceparent(value: &str) -> Option<(u128, u64)> {
+    let mut parts = value.split('-');
+    let (version, trace, span) = (parts.next()?, parts.next()?, parts.next()?);
+    if version != "00" || trace.len() != 32 || span.len() != 16 {
+        return None;
+    }
+    let trace = u128::from_str_radix(trace, 16).ok().filter(|x| *x != 0)?;
+    let span = u64::from_str_radix(span, 16).ok().filter(|x| *x != 0)?;
+    Some((trace, span))
+}
+
+/// Middleware recording the span of every request if the [`Tracer`] is enabled.
+pub async fn trace(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(tracer) = req.app_data::<web::Data<Tracer>>().filter(|x| x.sender.is_some()).cloned() else {
+        return next.call(req).await;
+    };
+    let caller = req.headers().get("traceparent")
+        .and_then(|x| x.to_str().ok())
+        .and_then(parse_traceparent);
+    let context = TraceContext {
+        trace_id: caller.map_or_else(random_u128, |(trace, _)| trace),
+        span_id: span_id(),
+    };
+    req.extensions_mut().insert(context);
+    let start = SystemTime::now();
+    let name = format!("{} {}", req.method(), req.match_pattern().unwrap_or_else(|| req.path().to_string()));
+    let mut attributes = vec![
+        ("http.request.method", req.method().to_string()),
+        ("url.path", req.path().to_string()),
+    ];
+    if let Some(route) = req.match_name() {
+        attributes.push(("http.route.name", route.to_string()));
+    }
+    if let Some(RequestId(id)) = req.extensions().get::<RequestId>() {
+        attributes.push(("http.request.header.x-request-id", id.clone()));
+    }
+
+    let response = next.call(req).await?;
+    attributes.push(("http.response.status_code", response.status().as_u16().to_string()));
+    tracer.record(SpanData {
+        trace_id: context.trace_id,
+        span_id: context.span_id,
+        parent_id: caller.map(|(_, span)| span),
+        server: true,
+        name,
+        start,
+        end: SystemTime::now(),
+        attributes,
+        error: response.status().is_server_error(),
+    });
+    Ok(response)
+}
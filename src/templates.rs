@@ -0,0 +1,67 @@
+//! The templates read from disk: the default one given with `-f` and the named ones in the
+//! `templates` directory, one `<name>.svg` file each, next to the partials they extend, include
+//! or import, the `*.j2` files. They are re-read by `POST /admin/reload` and extended by
+//! `POST /admin/templates/{name}`, which writes to the directory as well.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use anyhow::Context;
+use log::warn;
+use progress_bar::{is_partial_name, is_template_name};
+
+
+#[derive(Debug, Clone, Default)]
+pub struct TemplateSources {
+    /// The template replacing the default one, as given with `-f`.
+    pub file: Option<PathBuf>,
+    /// The directory of the named templates.
+    pub dir: Option<PathBuf>,
+}
+
+/// The templates read from the [`TemplateSources`].
+#[derive(Debug, Default)]
+pub struct TemplateFiles {
+    /// The template replacing the default one, if any.
+    pub default: Option<String>,
+    pub named: BTreeMap<String, String>,
+    /// The `*.j2` files keyed by their file names, e.g. `label.svg.j2`.
+    pub partials: BTreeMap<String, String>,
+}
+
+impl TemplateSources {
+    /// The default template, if replaced, the named templates and the partials.
+    pub fn read(&self) -> anyhow::Result<TemplateFiles> {
+        let default = self.file.as_ref()
+            .map(|path| fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display())))
+            .transpose()?;
+        let mut files = TemplateFiles { default, ..Default::default() };
+        let Some(dir) = &self.dir else { return Ok(files) };
+        let entries = fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let (templates, name) = match path.extension().and_then(|x| x.to_str()) {
+                Some("svg") => (&mut files.named, path.file_stem().and_then(|x| x.to_str()).filter(|x| is_template_name(x))),
+                Some("j2") => (&mut files.partials, path.file_name().and_then(|x| x.to_str()).filter(|x| is_partial_name(x))),
+                _ => continue,
+            };
+            let Some(name) = name else {
+                warn!("Ignoring the template {}, whose name is not valid.", path.display());
+                continue;
+            };
+            let template = fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
+            templates.insert(name.to_string(), template);
+        }
+        Ok(files)
+    }
+
+    /// Writes the template `name` to the directory, returning whether there is one.
+    pub fn write(&self, name: &str, template: &str) -> anyhow::Result<bool> {
+        let Some(dir) = &self.dir else { return Ok(false) };
+        let path = dir.join(format!("{name}.svg"));
+        // the template is replaced at once, so a reload never reads half of it.
+        let partial = dir.join(format!(".{name}.svg.tmp"));
+        fs::write(&partial, template).with_context(|| format!("failed to write {}", partial.display()))?;
+        fs::rename(&partial, &path).with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,162 @@
+//! Progress computed from the time elapsed between two instants.
+use std::fmt;
+use chrono::{DateTime, LocalResult, NaiveDate, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use chrono_tz::Tz;
+
+
+#[derive(Debug)]
+pub enum TimeError {
+    InvalidInstant(String),
+    UnknownTimezone(String),
+    AmbiguousLocalTime(String),
+    /// A local time skipped by a daylight saving transition.
+    NonexistentLocalTime(String),
+    EmptySpan,
+}
+
+impl fmt::Display for TimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeError::InvalidInstant(s) => write!(f, "`{s}` is neither an ISO date/time nor a unix timestamp"),
+            TimeError::UnknownTimezone(s) => write!(f, "unknown time zone `{s}`"),
+            TimeError::AmbiguousLocalTime(s) => write!(f, "`{s}` is ambiguous in the given time zone"),
+            TimeError::NonexistentLocalTime(s) => write!(f, "`{s}` does not exist in the given time zone"),
+            TimeError::EmptySpan => write!(f, "`end` must be after `start`"),
+        }
+    }
+}
+
+impl std::error::Error for TimeError {}
+
+pub fn parse_timezone(tz: Option<&str>) -> Result<Tz, TimeError> {
+    match tz {
+        Some(name) => name.parse().map_err(|_| TimeError::UnknownTimezone(name.to_string())),
+        None => Ok(Tz::UTC),
+    }
+}
+
+/// Parses a unix timestamp, an RFC 3339 date time, or a date / date time without offset.
+///
+/// Dates and date times without an offset are interpreted in `tz`, a bare date meaning
+/// the start of that day.
+pub fn parse_instant(s: &str, tz: Tz) -> Result<DateTime<Utc>, TimeError> {
+    let invalid = || TimeError::InvalidInstant(s.to_string());
+    if let Ok(secs) = s.parse::<f64>() {
+        if !secs.is_finite() {
+            return Err(invalid());
+        }
+        // the fraction is counted forward from the whole second before, also for negative ones.
+        let whole = secs.floor();
+        let nanos = (((secs - whole) * 1e9) as u32).min(999_999_999);
+        return DateTime::from_timestamp(whole as i64, nanos).ok_or_else(invalid);
+    }
+    if let Ok(x) = DateTime::parse_from_rfc3339(s) {
+        return Ok(x.with_timezone(&Utc));
+    }
+    let naive = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M"))
+        .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(|d| d.and_time(Default::default())))
+        .map_err(|_| invalid())?;
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(x) => Ok(x.with_timezone(&Utc)),
+        LocalResult::Ambiguous(..) => Err(TimeError::AmbiguousLocalTime(s.to_string())),
+        LocalResult::None => Err(TimeError::NonexistentLocalTime(s.to_string())),
+    }
+}
+
+/// Percentage of the span from `start` to `end` elapsed at `now`, not limited to `[0, 100]`.
+pub fn elapsed_percent(start: DateTime<Utc>, end: DateTime<Utc>, now: DateTime<Utc>) -> Result<f32, TimeError> {
+    if end <= start {
+        return Err(TimeError::EmptySpan);
+    }
+    let total = (end - start).num_milliseconds() as f64;
+    let elapsed = (now - start).num_milliseconds() as f64;
+    Ok((100.0 * elapsed / total) as f32)
+}
+
+/// Formats a positive duration in its largest whole unit, e.g. `42 days` or `3 hours`.
+pub fn humanize(duration: TimeDelta) -> String {
+    let plural = |n: i64, unit: &str| format!("{n} {unit}{}", if n == 1 { "" } else { "s" });
+    if duration.num_days() > 0 {
+        plural(duration.num_days(), "day")
+    } else if duration.num_hours() > 0 {
+        plural(duration.num_hours(), "hour")
+    } else if duration.num_minutes() > 0 {
+        plural(duration.num_minutes(), "minute")
+    } else {
+        "less than a minute".to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn instants_are_parsed_in_every_format() {
+        let berlin = parse_timezone(Some("Europe/Berlin")).unwrap();
+        for (text, tz, instant) in [
+            ("2024-03-01T12:00:00Z", Tz::UTC, "2024-03-01T12:00:00Z"),
+            ("2024-03-01T12:00:00+02:00", berlin, "2024-03-01T10:00:00Z"),
+            ("2024-03-01T12:00:00", berlin, "2024-03-01T11:00:00Z"),
+            ("2024-03-01T12:00", Tz::UTC, "2024-03-01T12:00:00Z"),
+            ("2024-03-01", Tz::UTC, "2024-03-01T00:00:00Z"),
+            ("2024-03-01", berlin, "2024-02-29T23:00:00Z"),
+            ("1700000000", berlin, "2023-11-14T22:13:20Z"),
+            ("1700000000.25", Tz::UTC, "2023-11-14T22:13:20.25Z"),
+            ("-1.5", Tz::UTC, "1969-12-31T23:59:58.5Z"),
+        ] {
+            assert_eq!(parse_instant(text, tz).unwrap(), utc(instant), "{text}");
+        }
+        for text in ["nan", "inf", "-infinity", "1e300", "tomorrow", "2024-13-01", ""] {
+            assert!(matches!(parse_instant(text, Tz::UTC), Err(TimeError::InvalidInstant(_))), "{text}");
+        }
+        assert!(matches!(parse_timezone(Some("Mars/Olympus")), Err(TimeError::UnknownTimezone(_))));
+        assert_eq!(parse_timezone(None).unwrap(), Tz::UTC);
+    }
+
+    #[test]
+    fn daylight_saving_transitions_are_reported() {
+        let berlin = parse_timezone(Some("Europe/Berlin")).unwrap();
+        // the clocks jump from 2:00 to 3:00, and back from 3:00 to 2:00 in October.
+        assert!(matches!(parse_instant("2024-03-31T02:30", berlin), Err(TimeError::NonexistentLocalTime(_))));
+        assert!(matches!(parse_instant("2024-10-27T02:30", berlin), Err(TimeError::AmbiguousLocalTime(_))));
+        assert_eq!(parse_instant("2024-03-31T03:30", berlin).unwrap(), utc("2024-03-31T01:30:00Z"));
+        // offsets leave no doubt.
+        assert_eq!(parse_instant("2024-10-27T02:30:00+01:00", berlin).unwrap(), utc("2024-10-27T01:30:00Z"));
+    }
+
+    #[test]
+    fn elapsed_percent_extends_beyond_the_span() {
+        let (start, end) = (utc("2024-01-01T00:00:00Z"), utc("2024-01-05T00:00:00Z"));
+        for (now, percent) in [
+            ("2023-12-30T00:00:00Z", -50.0),
+            ("2024-01-01T00:00:00Z", 0.0),
+            ("2024-01-02T00:00:00Z", 25.0),
+            ("2024-01-05T00:00:00Z", 100.0),
+            ("2024-01-07T00:00:00Z", 150.0),
+        ] {
+            assert_eq!(elapsed_percent(start, end, utc(now)).unwrap(), percent, "{now}");
+        }
+        assert!(matches!(elapsed_percent(end, start, start), Err(TimeError::EmptySpan)));
+        assert!(matches!(elapsed_percent(start, start, start), Err(TimeError::EmptySpan)));
+    }
+
+    #[test]
+    fn durations_are_humanized_in_their_largest_unit() {
+        for (duration, text) in [
+            (TimeDelta::days(42) + TimeDelta::hours(23), "42 days"),
+            (TimeDelta::days(1), "1 day"),
+            (TimeDelta::hours(3) + TimeDelta::minutes(59), "3 hours"),
+            (TimeDelta::minutes(1), "1 minute"),
+            (TimeDelta::seconds(59), "less than a minute"),
+        ] {
+            assert_eq!(humanize(duration), text);
+        }
+    }
+}
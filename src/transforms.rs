@@ -0,0 +1,183 @@
+//! Named pipelines from the `[transforms]` config section, applied to a value before it is
+//! placed within the range of the bar, e.g.
+//!
+//! ```toml
+//! [transforms]
+//! coverage = [{ scale = 100 }, { clamp = [0, 100] }, { expr = "value | round" }]
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use anyhow::bail;
+use minijinja::{context, Environment, Expression};
+use serde::Deserialize;
+
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransformStep {
+    /// Multiplies the value.
+    Scale(f32),
+    /// Adds to the value.
+    Offset(f32),
+    /// Limits the value to `[min, max]`.
+    Clamp([f32; 2]),
+    /// Interpolates the value in a table of `[from, to]` points.
+    Map(Vec<[f32; 2]>),
+    /// A minijinja expression of `value`, e.g. `(value * 100) | round`.
+    Expr(String),
+}
+
+#[derive(Debug)]
+pub enum TransformError {
+    Unknown(String),
+    Expression { expr: String, error: String },
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransformError::Unknown(name) => write!(f, "unknown transform `{name}`"),
+            TransformError::Expression { expr, error } =>
+                write!(f, "failed to evaluate `{expr}`: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+pub struct Transforms {
+    pipelines: HashMap<String, Vec<TransformStep>>,
+    /// The `expr` steps by their source, compiled once with the value bound to `value`. Their
+    /// environment and sources are leaked, as the renderers live as long as the server.
+    expressions: HashMap<String, Expression<'static, 'static>>,
+}
+
+/// Linearly interpolates `value` in a table of `[from, to]` points sorted by `from`,
+/// holding the first and last `to` outside of the table.
+fn interpolate(table: &[[f32; 2]], value: f32) -> f32 {
+    let [first, .., last] = table else {
+        return table.first().map_or(value, |[_, to]| *to);
+    };
+    if value <= first[0] {
+        return first[1];
+    }
+    if value >= last[0] {
+        return last[1];
+    }
+    let i = table.partition_point(|[from, _]| *from <= value);
+    let ([x0, y0], [x1, y1]) = (table[i - 1], table[i]);
+    y0 + (y1 - y0) * (value - x0) / (x1 - x0)
+}
+
+impl Transforms {
+    pub fn new(mut pipelines: HashMap<String, Vec<TransformStep>>) -> anyhow::Result<Self> {
+        let env: &'static Environment<'static> = Box::leak(Box::new(Environment::new()));
+        let mut expressions = HashMap::new();
+        for (name, steps) in pipelines.iter_mut() {
+            for step in steps {
+                match step {
+                    &mut TransformStep::Clamp([min, max]) if min > max =>
+                        bail!("transform `{name}`: clamp minimum {min} exceeds the maximum {max}"),
+                    TransformStep::Map(table) => {
+                        if table.is_empty() {
+                            bail!("transform `{name}`: map table is empty");
+                        }
+                        table.sort_by(|a, b| a[0].total_cmp(&b[0]));
+                    },
+                    TransformStep::Expr(expr) if !expressions.contains_key(expr) => {
+                        let source: &'static str = Box::leak(expr.clone().into_boxed_str());
+                        match env.compile_expression(source) {
+                            Ok(compiled) => { expressions.insert(expr.clone(), compiled); },
+                            Err(e) => bail!("transform `{name}`: invalid expression `{expr}`: {e}"),
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+        Ok(Transforms { pipelines, expressions })
+    }
+
+    /// Runs `value` through the pipeline `name`.
+    pub fn apply(&self, name: &str, mut value: f32) -> Result<f32, TransformError> {
+        let steps = self.pipelines.get(name)
+            .ok_or_else(|| TransformError::Unknown(name.to_string()))?;
+        for step in steps {
+            value = match step {
+                TransformStep::Scale(factor) => value * factor,
+                TransformStep::Offset(offset) => value + offset,
+                TransformStep::Clamp([min, max]) => value.clamp(*min, *max),
+                TransformStep::Map(table) => interpolate(table, value),
+                TransformStep::Expr(expr) => {
+                    let error = |error: String| TransformError::Expression { expr: expr.clone(), error };
+                    let result = self.expressions[expr].eval(context!(value => value))
+                        .map_err(|e| error(e.to_string()))?;
+                    f64::try_from(result.clone())
+                        .map_err(|_| error(format!("`{result}` is not a number")))? as f32
+                },
+            };
+        }
+        Ok(value)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transforms(config: &str) -> anyhow::Result<Transforms> {
+        Transforms::new(toml::from_str(config)?)
+    }
+
+    #[test]
+    fn maps_interpolate_and_hold_their_ends() {
+        let t = transforms("m = [{ map = [[10, 100], [0, 0], [20, 120]] }]").unwrap();
+        for (value, expected) in [(-5.0, 0.0), (0.0, 0.0), (5.0, 50.0), (10.0, 100.0), (15.0, 110.0), (20.0, 120.0), (99.0, 120.0)] {
+            assert_eq!(t.apply("m", value).unwrap(), expected, "{value}");
+        }
+        let single = transforms("m = [{ map = [[3, 7]] }]").unwrap();
+        assert_eq!(single.apply("m", -1.0).unwrap(), 7.0);
+        assert!(transforms("m = [{ map = [] }]").is_err());
+    }
+
+    #[test]
+    fn clamps_limit_the_value() {
+        let t = transforms("c = [{ clamp = [0, 100] }]").unwrap();
+        assert_eq!(t.apply("c", -3.0).unwrap(), 0.0);
+        assert_eq!(t.apply("c", 42.0).unwrap(), 42.0);
+        assert_eq!(t.apply("c", 250.0).unwrap(), 100.0);
+        assert!(transforms("c = [{ clamp = [100, 0] }]").is_err());
+    }
+
+    #[test]
+    fn expressions_are_evaluated_with_the_value() {
+        let t = transforms(r#"
+            round = [{ expr = "(value * 100) | round" }]
+            text = [{ expr = "'high' if value > 1 else 'low'" }]
+        "#).unwrap();
+        assert_eq!(t.apply("round", 0.426).unwrap(), 43.0);
+        assert_eq!(t.apply("round", 0.426).unwrap(), 43.0, "the compiled expression is reused");
+        let e = t.apply("text", 2.0).unwrap_err();
+        assert!(matches!(&e, TransformError::Expression { expr, .. } if expr.starts_with("'high'")), "{e}");
+        assert!(e.to_string().contains("`high` is not a number"), "{e}");
+        assert!(transforms(r#"bad = [{ expr = "value *" }]"#).is_err());
+    }
+
+    #[test]
+    fn steps_are_chained_in_order() {
+        let t = transforms(r#"
+            coverage = [{ scale = 100 }, { offset = 5 }, { clamp = [0, 100] }, { expr = "value | round" }]
+        "#).unwrap();
+        assert_eq!(t.apply("coverage", 0.421).unwrap(), 47.0);
+        assert_eq!(t.apply("coverage", 0.99).unwrap(), 100.0);
+    }
+
+    #[test]
+    fn unknown_pipelines_are_errors() {
+        let t = transforms("known = [{ scale = 2 }]").unwrap();
+        let e = t.apply("unknown", 1.0).unwrap_err();
+        assert!(matches!(&e, TransformError::Unknown(name) if name == "unknown"));
+        assert_eq!(e.to_string(), "unknown transform `unknown`");
+    }
+}
@@ -0,0 +1,68 @@
+//! Small line charts of the recent values of a bar, drawn between its title and its current
+//! value, e.g. the coverage of the last 30 builds.
+use minijinja::value::Value;
+
+
+pub(crate) const TREND_NAME: &str = "pbar_trend";
+/// The template drawing the charts of [`ProgressBarRenderer::render_trend`](crate::ProgressBarRenderer::render_trend).
+pub const TREND_TEMPLATE: &str = include_str!("../resources/trend.svg");
+/// The width of the chart between the title and the value.
+pub const CHART_WIDTH: i32 = 60;
+
+/// Extends the context `ctx` of a bar with the chart of `values`, oldest first: the
+/// `points` of the line and the `last_x` and `last_y` of its end, the `chart_width`, the
+/// `title_x` and the total `trend_width`. The values span the height of the chart, so
+/// small changes stay visible.
+pub(crate) fn trend_context(ctx: &Value, values: &[f32]) -> Value {
+    let mut args = serde_json::to_value(ctx).unwrap_or_default();
+    let number = |key: &str| args[key].as_i64().unwrap_or_default() as i32;
+    let (title_width, text_width) = (number("title_width"), number("text_width"));
+    let low = values.iter().copied().fold(f32::INFINITY, f32::min);
+    let high = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    // 4px are kept free around the line, which is flat in the middle without changes.
+    let step = (CHART_WIDTH - 8) as f32 / values.len().saturating_sub(1).max(1) as f32;
+    let points: Vec<(f32, f32)> = values.iter().enumerate()
+        .map(|(i, value)| {
+            let y = if high - low > f32::EPSILON { 16.0 - 12.0 * (value - low) / (high - low) } else { 10.0 };
+            (title_width as f32 + 4.0 + step * i as f32, y)
+        })
+        .collect();
+    let (last_x, last_y) = points.last().copied().unwrap_or((title_width as f32 + 4.0, 10.0));
+    args["points"] = points.iter()
+        .map(|(x, y)| format!("{x:.1},{y:.1}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .into();
+    args["last_x"] = format!("{last_x:.1}").into();
+    args["last_y"] = format!("{last_y:.1}").into();
+    args["chart_width"] = CHART_WIDTH.into();
+    args["title_x"] = if args.get("logo").is_some() { 21 } else { 4 }.into();
+    args["trend_width"] = (title_width + CHART_WIDTH + text_width).into();
+    args["trend_description"] = match values {
+        [] => "no values yet".to_string(),
+        [value] => format!("1 value of {value}"),
+        _ => format!("{} values from {low} to {high}", values.len()),
+    }.into();
+    Value::from_serializable(&args)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BarSpec, ProgressBarRenderer};
+
+    #[test]
+    fn trend_spans_the_height_of_the_chart() {
+        let renderer = ProgressBarRenderer::new(Default::default()).unwrap();
+        let spec = BarSpec { title: Some("cov".into()), progress: Some(82.0), ..Default::default() };
+        let ctx = trend_context(&renderer.context(&spec).unwrap(), &[80.0, 81.0, 82.0]);
+        assert_eq!(ctx.get_attr("points").unwrap().to_string(), "32.0,16.0 58.0,10.0 84.0,4.0");
+        assert_eq!(ctx.get_attr("trend_width").unwrap().to_string(), "128");
+
+        let flat = trend_context(&renderer.context(&spec).unwrap(), &[82.0]);
+        assert_eq!(flat.get_attr("points").unwrap().to_string(), "32.0,10.0");
+        let svg = renderer.render_trend(&spec, &[80.0, 81.0, 82.0]).unwrap();
+        assert!(svg.contains("<polyline") && svg.contains("3 values from 80 to 82"));
+    }
+}
@@ -0,0 +1,58 @@
+//! HTTP clients for fetching data from upstream services.
+use std::time::Duration;
+use anyhow::Context;
+use reqwest::{Client, NoProxy, Proxy};
+use crate::config::ProxyConfig;
+use crate::secrets::resolve_secret;
+use crate::sources;
+
+
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Builds identically configured clients.
+///
+/// `reqwest` clients should not be shared between runtimes, so every worker builds its own.
+#[derive(Clone)]
+pub struct ClientFactory {
+    proxy: Option<Proxy>,
+    /// The hosts of the sources, whose redirects are restricted by [`sources::redirect_policy`].
+    source_hosts: Vec<String>,
+}
+
+impl ClientFactory {
+    pub fn new(config: &ProxyConfig, source_hosts: &[String]) -> anyhow::Result<Self> {
+        let proxy = match &config.url {
+            Some(url) => {
+                let mut proxy = Proxy::all(url).with_context(|| format!("invalid proxy url `{url}`"))?;
+                if let Some(username) = &config.username {
+                    let password = match &config.password {
+                        Some(source) => resolve_secret("proxy password", source)?,
+                        None => String::new(),
+                    };
+                    proxy = proxy.basic_auth(username, &password);
+                }
+                Some(proxy.no_proxy(config.no_proxy.as_deref().and_then(NoProxy::from_string)))
+            },
+            None => None,
+        };
+        let factory = ClientFactory { proxy, source_hosts: source_hosts.to_vec() };
+        factory.try_build().context("failed to set up the HTTP client")?;
+        Ok(factory)
+    }
+
+    fn try_build(&self) -> reqwest::Result<Client> {
+        let mut builder = Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(TIMEOUT)
+            .redirect(sources::redirect_policy(self.source_hosts.clone()));
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        builder.build()
+    }
+
+    pub fn build(&self) -> Client {
+        self.try_build().expect("the client configuration is checked by ClientFactory::new")
+    }
+}
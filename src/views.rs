@@ -0,0 +1,85 @@
+//! View counts of badges and stored bars, an opt-in with `[views] enabled = true`, served
+//! at `/b/{name}/stats` and `/bars/{id}/stats` to the holders of the stats or bars token, so
+//! maintainers learn whether anyone looks at their badges. Only the hosts of the referring pages are kept, never addresses, and the
+//! counts start over when the server restarts.
+use std::collections::HashMap;
+use std::sync::Mutex;
+use actix_web::HttpRequest;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use crate::referrers;
+use crate::stats::{self, Entry};
+
+
+/// Distinct referring hosts kept per bar, further hosts being counted as `other_referrers`.
+const MAX_REFERRERS: usize = 100;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Badge,
+    Bar,
+}
+
+#[derive(Default)]
+struct Counts {
+    views: u64,
+    referrers: HashMap<String, u64>,
+    other_referrers: u64,
+}
+
+#[derive(Serialize)]
+pub struct Views {
+    pub since: DateTime<Utc>,
+    pub views: u64,
+    pub unique_referrers: usize,
+    pub referrers: Vec<Entry>,
+    pub other_referrers: u64,
+}
+
+pub struct ViewCounter {
+    enabled: bool,
+    since: DateTime<Utc>,
+    counts: Mutex<HashMap<(Kind, String), Counts>>,
+}
+
+impl ViewCounter {
+    pub fn new(enabled: bool) -> Self {
+        ViewCounter { enabled, since: Utc::now(), counts: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Counts a view of the bar `id` requested with `req`, if enabled.
+    pub fn record(&self, kind: Kind, id: &str, req: &HttpRequest) {
+        if !self.enabled {
+            return;
+        }
+        let referrer = referrers::host(req);
+        let mut counts = self.counts.lock().unwrap();
+        let counts = counts.entry((kind, id.to_string())).or_default();
+        counts.views += 1;
+        if let Some(host) = referrer {
+            let known = counts.referrers.len();
+            match counts.referrers.get_mut(&host) {
+                Some(count) => *count += 1,
+                None if known < MAX_REFERRERS => { counts.referrers.insert(host, 1); },
+                None => counts.other_referrers += 1,
+            }
+        }
+    }
+
+    /// The views of the bar `id` since the start of the server.
+    pub fn get(&self, kind: Kind, id: &str) -> Views {
+        let counts = self.counts.lock().unwrap();
+        let counts = counts.get(&(kind, id.to_string()));
+        Views {
+            since: self.since,
+            views: counts.map_or(0, |x| x.views),
+            unique_referrers: counts.map_or(0, |x| x.referrers.len()),
+            referrers: counts.map_or_else(Vec::new, |x| stats::top(x.referrers.clone())),
+            other_referrers: counts.map_or(0, |x| x.other_referrers),
+        }
+    }
+}